@@ -0,0 +1,242 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Client side helpers for handling responses from key servers.
+//!
+//! A key server returns the requested user secret keys ElGamal encrypted under an ephemeral public
+//! key chosen by the client. The functions in this module decrypt these keys, verify them against
+//! the public key of the key server and assemble them into [IBEUserSecretKeys] which can be used
+//! with [crate::seal_decrypt].
+
+use crate::elgamal;
+use crate::ibe::{verify_user_secret_key, PublicKey, UserSecretKey};
+use crate::{IBEUserSecretKeys, ObjectID};
+use fastcrypto::error::FastCryptoError::InvalidInput;
+use fastcrypto::error::FastCryptoResult;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The full id of a key, e.g., [package_id][inner_id].
+pub type KeyId = Vec<u8>;
+
+/// The ElGamal secret key used by a client to decrypt the keys returned from a key server.
+pub type ElGamalSecretKey = elgamal::SecretKey<UserSecretKey>;
+
+/// An encrypted user secret key for a single id.
+/// Must be aligned with the response format of the key server.
+#[derive(Serialize, Deserialize)]
+pub struct DecryptionKey {
+    pub id: KeyId,
+    pub encrypted_key: elgamal::Encryption<UserSecretKey>,
+}
+
+/// The response of a key server to a fetch key request.
+/// Must be aligned with the response format of the key server.
+#[derive(Serialize, Deserialize)]
+pub struct FetchKeyResponse {
+    pub decryption_keys: Vec<DecryptionKey>,
+}
+
+/// Decrypt all keys in a response from a single key server and verify them against the public key
+/// of that key server. Returns the keys indexed by their full id.
+///
+/// Returns an [InvalidInput] error if any of the keys is not valid for the given public key.
+pub fn decrypt_and_verify_response(
+    response: &FetchKeyResponse,
+    enc_secret_key: &ElGamalSecretKey,
+    public_key: &PublicKey,
+) -> FastCryptoResult<HashMap<KeyId, UserSecretKey>> {
+    response
+        .decryption_keys
+        .iter()
+        .map(|key| {
+            let user_secret_key = elgamal::decrypt(enc_secret_key, &key.encrypted_key);
+            verify_user_secret_key(&user_secret_key, &key.id, public_key)?;
+            Ok((key.id.clone(), user_secret_key))
+        })
+        .collect()
+}
+
+/// Given verified keys from a set of key servers, pick the user secret keys for the given full id.
+/// Key servers that did not return a key for this id are skipped.
+pub fn user_secret_keys_for_id(
+    keys: &HashMap<ObjectID, HashMap<KeyId, UserSecretKey>>,
+    full_id: &[u8],
+) -> IBEUserSecretKeys {
+    IBEUserSecretKeys::BonehFranklinBLS12381(
+        keys.iter()
+            .filter_map(|(object_id, server_keys)| {
+                server_keys.get(full_id).map(|key| (*object_id, *key))
+            })
+            .collect(),
+    )
+}
+
+/// Decrypt and verify the responses from a set of key servers and assemble the user secret keys for
+/// the given full id.
+///
+/// @param responses Tuples of the object id of a key server, its public key and its response.
+/// @param enc_secret_key The ElGamal secret key matching the public key used in the requests.
+/// @param full_id The full id for which the user secret keys should be returned.
+/// @return The user secret keys for the given id. Returns an [InvalidInput] error if any response
+/// contains an invalid key or does not contain a key for the given id.
+pub fn decrypt_user_secret_keys<'a>(
+    responses: impl IntoIterator<Item = (ObjectID, &'a PublicKey, &'a FetchKeyResponse)>,
+    enc_secret_key: &ElGamalSecretKey,
+    full_id: &[u8],
+) -> FastCryptoResult<IBEUserSecretKeys> {
+    let user_secret_keys: HashMap<_, _> = responses
+        .into_iter()
+        .map(|(object_id, public_key, response)| {
+            decrypt_and_verify_response(response, enc_secret_key, public_key)?
+                .remove(full_id)
+                .map(|key| (object_id, key))
+                .ok_or(InvalidInput)
+        })
+        .collect::<FastCryptoResult<_>>()?;
+    Ok(IBEUserSecretKeys::BonehFranklinBLS12381(user_secret_keys))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ibe::{extract, generate_key_pair, MasterKey};
+    use crate::{create_full_id, seal_decrypt, seal_encrypt, EncryptionInput, IBEPublicKeys};
+    use fastcrypto::groups::bls12381::G2Element;
+    use itertools::Itertools;
+    use rand::thread_rng;
+
+    fn create_response(
+        master_key: &MasterKey,
+        ids: &[KeyId],
+        enc_key: &elgamal::PublicKey<UserSecretKey>,
+    ) -> FetchKeyResponse {
+        FetchKeyResponse {
+            decryption_keys: ids
+                .iter()
+                .map(|id| DecryptionKey {
+                    id: id.clone(),
+                    encrypted_key: elgamal::encrypt(
+                        &mut thread_rng(),
+                        &extract(master_key, id),
+                        enc_key,
+                    ),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_decrypt_and_verify_response() {
+        let mut rng = thread_rng();
+        let (master_key, public_key) = generate_key_pair(&mut rng);
+        let (enc_secret_key, enc_key, _) = elgamal::genkey::<_, G2Element, _>(&mut rng);
+
+        let ids = vec![vec![1, 2, 3], vec![4, 5, 6]];
+        let response = create_response(&master_key, &ids, &enc_key);
+        let keys = decrypt_and_verify_response(&response, &enc_secret_key, &public_key).unwrap();
+        assert_eq!(keys.len(), 2);
+        for id in &ids {
+            assert_eq!(keys[id], extract(&master_key, id));
+        }
+
+        // Verification fails against another key server's public key
+        let (_, other_public_key) = generate_key_pair(&mut rng);
+        assert_eq!(
+            decrypt_and_verify_response(&response, &enc_secret_key, &other_public_key).err(),
+            Some(InvalidInput)
+        );
+
+        // Verification fails if decrypted with another ElGamal key
+        let (other_enc_secret_key, _, _) = elgamal::genkey::<_, G2Element, _>(&mut rng);
+        assert!(
+            decrypt_and_verify_response(&response, &other_enc_secret_key, &public_key).is_err()
+        );
+    }
+
+    #[test]
+    fn test_decrypt_user_secret_keys_round_trip() {
+        let data = b"Hello, World!";
+        let package_id = ObjectID::random();
+        let id = vec![1, 2, 3, 4];
+        let full_id = create_full_id(&package_id, &id);
+
+        let mut rng = thread_rng();
+        let keypairs = (0..3).map(|_| generate_key_pair(&mut rng)).collect_vec();
+        let services = keypairs.iter().map(|_| ObjectID::random()).collect_vec();
+        let public_keys = keypairs.iter().map(|(_, pk)| *pk).collect_vec();
+
+        let encrypted = seal_encrypt(
+            package_id,
+            id,
+            services.clone(),
+            &IBEPublicKeys::BonehFranklinBLS12381(public_keys.clone()),
+            2,
+            EncryptionInput::Aes256Gcm {
+                data: data.to_vec(),
+                aad: None,
+            },
+        )
+        .unwrap()
+        .0;
+
+        let (enc_secret_key, enc_key, _) = elgamal::genkey::<_, G2Element, _>(&mut rng);
+        let responses = keypairs
+            .iter()
+            .map(|(master_key, _)| create_response(master_key, &[full_id.clone()], &enc_key))
+            .collect_vec();
+
+        // Use only the responses from the first two key servers
+        let user_secret_keys = decrypt_user_secret_keys(
+            services
+                .iter()
+                .zip(&public_keys)
+                .zip(&responses)
+                .take(2)
+                .map(|((s, pk), r)| (*s, pk, r)),
+            &enc_secret_key,
+            &full_id,
+        )
+        .unwrap();
+        assert_eq!(
+            seal_decrypt(&encrypted, &user_secret_keys, None).unwrap(),
+            data
+        );
+
+        // Fails if a response does not contain the requested id
+        let wrong_id = create_full_id(&package_id, &[5, 6, 7]);
+        assert!(decrypt_user_secret_keys(
+            services
+                .iter()
+                .zip(&public_keys)
+                .zip(&responses)
+                .map(|((s, pk), r)| (*s, pk, r)),
+            &enc_secret_key,
+            &wrong_id,
+        )
+        .is_err());
+
+        // The keys can also be assembled from the individually verified responses
+        let keys = services
+            .iter()
+            .zip(&public_keys)
+            .zip(&responses)
+            .map(|((s, pk), r)| {
+                (
+                    *s,
+                    decrypt_and_verify_response(r, &enc_secret_key, pk).unwrap(),
+                )
+            })
+            .collect();
+        let user_secret_keys = user_secret_keys_for_id(&keys, &full_id);
+        assert_eq!(
+            seal_decrypt(
+                &encrypted,
+                &user_secret_keys,
+                Some(&IBEPublicKeys::BonehFranklinBLS12381(public_keys))
+            )
+            .unwrap(),
+            data
+        );
+    }
+}
@@ -8,8 +8,8 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize)]
 pub struct SecretKey<G: GroupElement>(G::ScalarType);
 
-#[derive(Serialize, Deserialize)]
-pub struct PublicKey<G: GroupElement>(G);
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PublicKey<G: GroupElement>(pub(crate) G);
 
 #[derive(Serialize, Deserialize)]
 pub struct VerificationKey<G: GroupElement>(G);
@@ -197,10 +197,30 @@ pub type ProofOfPossession = G1Element;
 /// It is created as a BLS signature over the public key and the message.
 pub fn create_proof_of_possession(master_key: &MasterKey, message: &[u8]) -> ProofOfPossession {
     let public_key = public_key_from_master_key(master_key);
+    hash_to_pop_element(&public_key, message) * master_key
+}
+
+/// Verify a proof-of-possession created with [create_proof_of_possession] for the master key
+/// corresponding to the given public key and the given message.
+pub fn verify_proof_of_possession(
+    pop: &ProofOfPossession,
+    public_key: &PublicKey,
+    message: &[u8],
+) -> FastCryptoResult<()> {
+    if pop.pairing(&G2Element::generator())
+        == hash_to_pop_element(public_key, message).pairing(public_key)
+    {
+        Ok(())
+    } else {
+        Err(InvalidInput)
+    }
+}
+
+pub(crate) fn hash_to_pop_element(public_key: &PublicKey, message: &[u8]) -> G1Element {
     let mut full_msg = DST_POP.to_vec();
-    full_msg.extend(bcs::to_bytes(&public_key).expect("valid pk"));
+    full_msg.extend(bcs::to_bytes(public_key).expect("valid pk"));
     full_msg.extend(message);
-    G1Element::hash_to_group_element(&full_msg) * master_key
+    G1Element::hash_to_group_element(&full_msg)
 }
 
 #[cfg(test)]
@@ -240,4 +260,43 @@ mod tests {
             derive_master_key(&seed, derivation_index)
         );
     }
+
+    #[test]
+    fn test_proof_of_possession() {
+        let mut rng = rand::thread_rng();
+        let (master_key, public_key) = generate_key_pair(&mut rng);
+        let message = ObjectID::random().into_bytes();
+
+        let pop = create_proof_of_possession(&master_key, &message);
+        assert!(verify_proof_of_possession(&pop, &public_key, &message).is_ok());
+
+        // Fails for another message
+        let other_message = ObjectID::random().into_bytes();
+        assert!(verify_proof_of_possession(&pop, &public_key, &other_message).is_err());
+
+        // Fails for another public key
+        let (_, other_public_key) = generate_key_pair(&mut rng);
+        assert!(verify_proof_of_possession(&pop, &other_public_key, &message).is_err());
+    }
+
+    #[test]
+    fn test_proof_of_possession_derived_key() {
+        let master_key = derive_master_key(&[1u8; SEED_LENGTH], 42);
+        let public_key = public_key_from_master_key(&master_key);
+        let message = ObjectID::random().into_bytes();
+
+        let pop = create_proof_of_possession(&master_key, &message);
+        assert!(verify_proof_of_possession(&pop, &public_key, &message).is_ok());
+
+        // A proof-of-possession by another key of the same seed is rejected
+        let other_pop =
+            create_proof_of_possession(&derive_master_key(&[1u8; SEED_LENGTH], 43), &message);
+        assert!(verify_proof_of_possession(&other_pop, &public_key, &message).is_err());
+
+        // Trivial proofs are rejected
+        assert!(verify_proof_of_possession(&G1Element::zero(), &public_key, &message).is_err());
+        assert!(
+            verify_proof_of_possession(&G1Element::generator(), &public_key, &message).is_err()
+        );
+    }
 }
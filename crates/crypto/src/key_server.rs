@@ -0,0 +1,116 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The on-chain representation of key server objects, see `key_server.move`.
+
+use crate::ibe;
+use crate::ObjectID;
+use fastcrypto::error::FastCryptoError::InvalidInput;
+use fastcrypto::error::FastCryptoResult;
+use fastcrypto::groups::bls12381::G2_ELEMENT_BYTE_LENGTH;
+use fastcrypto::serde_helpers::ToFromByteArray;
+use serde::Deserialize;
+
+/// The key type of Boneh-Franklin over BLS12-381, see `key_server.move`.
+pub const KEY_TYPE_BONEH_FRANKLIN_BLS12381: u8 = 0;
+
+/// The version of the key server object stored as a dynamic field, see `key_server.move`.
+pub const KEY_SERVER_V1: u64 = 1;
+
+/// A dynamic field, see `sui::dynamic_field::Field`.
+#[derive(Deserialize)]
+struct Field<N, V> {
+    #[allow(dead_code)]
+    id: ObjectID,
+    #[allow(dead_code)]
+    name: N,
+    value: V,
+}
+
+/// See `KeyServerV1` in `key_server.move`.
+#[derive(Deserialize)]
+struct KeyServerV1Object {
+    name: String,
+    url: String,
+    key_type: u8,
+    pk: Vec<u8>,
+}
+
+/// A key server as registered on-chain in the `KeyServerV1` dynamic field of its object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyServerV1 {
+    pub name: String,
+    pub url: String,
+    pub public_key: ibe::PublicKey,
+}
+
+/// Parse the BCS bytes of the `KeyServerV1` dynamic field of a key server object. Only
+/// Boneh-Franklin over BLS12-381 keys are supported.
+pub fn parse_key_server_v1(bytes: &[u8]) -> FastCryptoResult<KeyServerV1> {
+    let field: Field<u64, KeyServerV1Object> = bcs::from_bytes(bytes).map_err(|_| InvalidInput)?;
+    if field.value.key_type != KEY_TYPE_BONEH_FRANKLIN_BLS12381 {
+        return Err(InvalidInput);
+    }
+    let pk: [u8; G2_ELEMENT_BYTE_LENGTH] = field.value.pk.try_into().map_err(|_| InvalidInput)?;
+    Ok(KeyServerV1 {
+        name: field.value.name,
+        url: field.value.url,
+        public_key: ibe::PublicKey::from_byte_array(&pk)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct KeyServerField {
+        id: ObjectID,
+        name: u64,
+        value: (String, String, u8, Vec<u8>),
+    }
+
+    #[test]
+    fn test_parse_key_server_v1() {
+        let (_, public_key) = ibe::generate_key_pair(&mut thread_rng());
+        let field = KeyServerField {
+            id: ObjectID::random(),
+            name: KEY_SERVER_V1,
+            value: (
+                "Key server".to_string(),
+                "https://seal.example.com".to_string(),
+                KEY_TYPE_BONEH_FRANKLIN_BLS12381,
+                public_key.to_byte_array().to_vec(),
+            ),
+        };
+        assert_eq!(
+            parse_key_server_v1(&bcs::to_bytes(&field).unwrap()),
+            Ok(KeyServerV1 {
+                name: "Key server".to_string(),
+                url: "https://seal.example.com".to_string(),
+                public_key,
+            })
+        );
+
+        // Unknown key type
+        let mut field = field;
+        field.value.2 = 1;
+        assert_eq!(
+            parse_key_server_v1(&bcs::to_bytes(&field).unwrap()),
+            Err(InvalidInput)
+        );
+
+        // Invalid public key
+        field.value.2 = KEY_TYPE_BONEH_FRANKLIN_BLS12381;
+        field.value.3 = vec![1, 2, 3];
+        assert_eq!(
+            parse_key_server_v1(&bcs::to_bytes(&field).unwrap()),
+            Err(InvalidInput)
+        );
+
+        // Invalid BCS
+        assert_eq!(parse_key_server_v1(&[1, 2, 3]), Err(InvalidInput));
+    }
+}
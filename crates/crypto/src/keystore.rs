@@ -0,0 +1,108 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Passphrase encrypted keystore files holding a single secret, e.g., the master key of a key server.
+//!
+//! The encryption key is derived from the passphrase and a random salt using Argon2id with the
+//! default parameters, and the secret is encrypted using [Aes256Gcm]. Since the salt is fresh for
+//! each keystore, the encryption key is never reused.
+
+use crate::dem::Aes256Gcm;
+use crate::prefixed_hex::PrefixedHex;
+use crate::utils::generate_random_bytes;
+use crate::KEY_SIZE;
+use argon2::Argon2;
+use fastcrypto::encoding::Encoding;
+use fastcrypto::error::FastCryptoError::{GeneralError, InvalidInput};
+use fastcrypto::error::FastCryptoResult;
+use fastcrypto::traits::AllowedRng;
+use serde::{Deserialize, Serialize};
+
+/// The current version of the keystore format.
+pub const KEYSTORE_VERSION: u8 = 1;
+
+const SALT_LENGTH: usize = 16;
+
+/// Domain separation for the encrypted secret.
+const KEYSTORE_AAD: &[u8] = b"SUI-SEAL-KEYSTORE-00";
+
+/// A keystore as stored in a file. Binary fields are hex encoded.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Keystore {
+    pub version: u8,
+    pub salt: String,
+    pub ciphertext: String,
+}
+
+impl Keystore {
+    /// Encrypt a secret under a passphrase.
+    pub fn encrypt<R: AllowedRng>(
+        secret: &[u8],
+        passphrase: &str,
+        rng: &mut R,
+    ) -> FastCryptoResult<Self> {
+        let salt: [u8; SALT_LENGTH] = generate_random_bytes(rng);
+        let key = derive_key(passphrase, &salt)?;
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            salt: PrefixedHex::encode(salt),
+            ciphertext: PrefixedHex::encode(Aes256Gcm::encrypt(secret, KEYSTORE_AAD, &key)),
+        })
+    }
+
+    /// Decrypt the secret. Fails if the passphrase is wrong or the keystore has been modified.
+    pub fn decrypt(&self, passphrase: &str) -> FastCryptoResult<Vec<u8>> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(GeneralError(format!(
+                "Unsupported keystore version {}",
+                self.version
+            )));
+        }
+        let salt = PrefixedHex::decode(&self.salt)?;
+        let ciphertext = PrefixedHex::decode(&self.ciphertext)?;
+        let key = derive_key(passphrase, &salt)?;
+        Aes256Gcm::decrypt(&ciphertext, KEYSTORE_AAD, &key)
+            .map_err(|_| GeneralError("Invalid passphrase or corrupted keystore".to_string()))
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> FastCryptoResult<[u8; KEY_SIZE]> {
+    if passphrase.is_empty() {
+        return Err(InvalidInput);
+    }
+    let mut key = [0u8; KEY_SIZE];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| GeneralError(format!("Key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn test_keystore() {
+        let secret = [7u8; 32];
+        let keystore = Keystore::encrypt(&secret, "passphrase", &mut thread_rng()).unwrap();
+        assert_eq!(keystore.decrypt("passphrase").unwrap(), secret);
+        assert!(keystore.decrypt("Passphrase").is_err());
+        assert!(Keystore::encrypt(&secret, "", &mut thread_rng()).is_err());
+
+        // Salts are fresh
+        let other = Keystore::encrypt(&secret, "passphrase", &mut thread_rng()).unwrap();
+        assert_ne!(keystore.salt, other.salt);
+        assert_ne!(keystore.ciphertext, other.ciphertext);
+
+        let mut ciphertext = PrefixedHex::decode(&keystore.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let mut modified = keystore.clone();
+        modified.ciphertext = PrefixedHex::encode(ciphertext);
+        assert!(modified.decrypt("passphrase").is_err());
+
+        let mut modified = keystore;
+        modified.version = 2;
+        assert!(modified.decrypt("passphrase").is_err());
+    }
+}
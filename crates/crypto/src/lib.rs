@@ -16,12 +16,16 @@ pub use sui_types::base_types::ObjectID;
 use tss::split;
 use utils::generate_random_bytes;
 
+pub mod client;
 pub mod dem;
 pub mod elgamal;
 pub mod gf256;
 pub mod ibe;
+pub mod key_server;
+pub mod keystore;
 mod polynomial;
 pub mod prefixed_hex;
+pub mod threshold;
 pub mod tss;
 mod utils;
 
@@ -0,0 +1,374 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Threshold variant of the [ibe] master key, such that a key server can be run by a committee in
+//! which no member holds the full master key.
+//!
+//! The master key is split into shares using Shamir's secret sharing over the scalar field of
+//! BLS12-381. Since [ibe::extract] and [ibe::create_proof_of_possession] are linear in the master
+//! key, members compute partial results using their shares, and any `threshold` partial results
+//! can be combined into the full result by Lagrange interpolation in the exponent.
+//!
+//! Partial user secret keys can also be computed encrypted under the ElGamal key of a user, see
+//! [partial_extract_encrypted]. Since ElGamal encryption is additively homomorphic, the encrypted
+//! partial keys can be verified and combined into an encryption of the user secret key without
+//! learning it.
+
+use crate::elgamal::Encryption;
+use crate::ibe::{MasterKey, ProofOfPossession, PublicKey, UserSecretKey};
+use crate::{elgamal, ibe};
+use fastcrypto::error::FastCryptoError::InvalidInput;
+use fastcrypto::error::FastCryptoResult;
+use fastcrypto::groups::bls12381::{G1Element, G2Element, Scalar};
+use fastcrypto::groups::{GroupElement, Pairing, Scalar as GenericScalar};
+use fastcrypto::traits::AllowedRng;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::iter::repeat_with;
+
+/// The index of a share. Indices are non-zero since the master key is the evaluation at zero.
+pub type ShareIndex = u16;
+
+/// A share of a master key held by a committee member.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MasterKeyShare {
+    pub index: ShareIndex,
+    pub share: MasterKey,
+}
+
+/// A partial result computed by a committee member using its share.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Partial<G> {
+    pub index: ShareIndex,
+    pub value: G,
+}
+
+/// A partial user secret key encrypted under the ElGamal key of a user, see
+/// [partial_extract_encrypted].
+#[derive(Serialize, Deserialize)]
+pub struct EncryptedPartialUserSecretKey {
+    pub index: ShareIndex,
+    pub encryption: Encryption<UserSecretKey>,
+    /// The randomness of the encryption times the generator of G2, used to verify the encryption.
+    pub randomness: G2Element,
+}
+
+/// Split a master key into `number_of_shares` shares such that at least `threshold` shares are
+/// needed to compute user secret keys. Returns the shares with indices `1..=number_of_shares`.
+pub fn split<R: AllowedRng>(
+    rng: &mut R,
+    master_key: &MasterKey,
+    threshold: u16,
+    number_of_shares: u16,
+) -> FastCryptoResult<Vec<MasterKeyShare>> {
+    if threshold > number_of_shares || threshold == 0 {
+        return Err(InvalidInput);
+    }
+
+    // Random polynomial of degree threshold - 1 with the master key as constant term.
+    let coefficients = std::iter::once(*master_key)
+        .chain(repeat_with(|| Scalar::rand(rng)).take(threshold as usize - 1))
+        .collect_vec();
+
+    Ok((1..=number_of_shares)
+        .map(|index| {
+            // Horner's method to evaluate the polynomial at the index
+            let x = Scalar::from(index as u128);
+            let share = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::zero(), |sum, coefficient| sum * x + *coefficient);
+            MasterKeyShare { index, share }
+        })
+        .collect())
+}
+
+/// The public key of a share, used to verify the partial results of a member.
+pub fn public_key_share(share: &MasterKeyShare) -> Partial<PublicKey> {
+    Partial {
+        index: share.index,
+        value: ibe::public_key_from_master_key(&share.share),
+    }
+}
+
+/// Compute a partial user secret key for an id, see [ibe::extract].
+pub fn partial_extract(share: &MasterKeyShare, id: &[u8]) -> Partial<UserSecretKey> {
+    Partial {
+        index: share.index,
+        value: ibe::extract(&share.share, id),
+    }
+}
+
+/// Verify a partial user secret key computed with [partial_extract] using the public key share of
+/// the member.
+pub fn verify_partial_user_secret_key(
+    partial: &Partial<UserSecretKey>,
+    id: &[u8],
+    public_key_share: &Partial<PublicKey>,
+) -> FastCryptoResult<()> {
+    if partial.index != public_key_share.index {
+        return Err(InvalidInput);
+    }
+    ibe::verify_user_secret_key(&partial.value, id, &public_key_share.value)
+}
+
+/// Compute a partial user secret key for an id and encrypt it under the given ElGamal key, such
+/// that only the owner of the ElGamal secret key learns it.
+pub fn partial_extract_encrypted<R: AllowedRng>(
+    rng: &mut R,
+    share: &MasterKeyShare,
+    id: &[u8],
+    enc_key: &elgamal::PublicKey<UserSecretKey>,
+) -> EncryptedPartialUserSecretKey {
+    let r = Scalar::rand(rng);
+    let partial = partial_extract(share, id);
+    EncryptedPartialUserSecretKey {
+        index: share.index,
+        encryption: Encryption(G1Element::generator() * r, enc_key.0 * r + partial.value),
+        randomness: G2Element::generator() * r,
+    }
+}
+
+/// Verify an encrypted partial user secret key computed with [partial_extract_encrypted] using the
+/// public key share of the member, without decrypting it.
+pub fn verify_encrypted_partial_user_secret_key(
+    partial: &EncryptedPartialUserSecretKey,
+    id: &[u8],
+    enc_key: &elgamal::PublicKey<UserSecretKey>,
+    public_key_share: &Partial<PublicKey>,
+) -> FastCryptoResult<()> {
+    let Encryption(c1, c2) = &partial.encryption;
+    // c1 and randomness have the same discrete log r, and c2 - r * enc_key is the partial key
+    if partial.index == public_key_share.index
+        && c1.pairing(&G2Element::generator())
+            == G1Element::generator().pairing(&partial.randomness)
+        && c2.pairing(&G2Element::generator())
+            == ibe::hash_to_g1(id).pairing(&public_key_share.value)
+                + enc_key.0.pairing(&partial.randomness)
+    {
+        Ok(())
+    } else {
+        Err(InvalidInput)
+    }
+}
+
+/// Combine `threshold` encrypted partial user secret keys into an encryption of the user secret
+/// key under the same ElGamal key. Like [combine], only the first `threshold` are used.
+pub fn combine_encrypted(
+    partials: &[&EncryptedPartialUserSecretKey],
+    threshold: u16,
+) -> FastCryptoResult<Encryption<UserSecretKey>> {
+    let (c1, c2): (Vec<_>, Vec<_>) = partials
+        .iter()
+        .map(|partial| {
+            (
+                Partial {
+                    index: partial.index,
+                    value: partial.encryption.0,
+                },
+                Partial {
+                    index: partial.index,
+                    value: partial.encryption.1,
+                },
+            )
+        })
+        .unzip();
+    Ok(Encryption(
+        combine(&c1, threshold)?,
+        combine(&c2, threshold)?,
+    ))
+}
+
+/// Compute a partial proof-of-possession of the master key with the given public key, see
+/// [ibe::create_proof_of_possession].
+pub fn partial_proof_of_possession(
+    share: &MasterKeyShare,
+    public_key: &PublicKey,
+    message: &[u8],
+) -> Partial<ProofOfPossession> {
+    Partial {
+        index: share.index,
+        value: ibe::hash_to_pop_element(public_key, message) * share.share,
+    }
+}
+
+/// Verify a partial proof-of-possession computed with [partial_proof_of_possession] using the
+/// public key share of the member.
+pub fn verify_partial_proof_of_possession(
+    partial: &Partial<ProofOfPossession>,
+    public_key: &PublicKey,
+    message: &[u8],
+    public_key_share: &Partial<PublicKey>,
+) -> FastCryptoResult<()> {
+    if partial.index == public_key_share.index
+        && partial.value.pairing(&G2Element::generator())
+            == ibe::hash_to_pop_element(public_key, message).pairing(&public_key_share.value)
+    {
+        Ok(())
+    } else {
+        Err(InvalidInput)
+    }
+}
+
+/// Combine `threshold` partial results into the full result, e.g., user secret keys from
+/// [partial_extract] or public keys from [public_key_share]. Only the first `threshold` partial
+/// results are used, so they should be verified first.
+///
+/// If the indices are not unique or non-zero, or there are fewer than `threshold` partial results,
+/// an [InvalidInput] is returned.
+pub fn combine<G: GroupElement<ScalarType = Scalar>>(
+    partials: &[Partial<G>],
+    threshold: u16,
+) -> FastCryptoResult<G> {
+    if threshold == 0 || partials.len() < threshold as usize {
+        return Err(InvalidInput);
+    }
+    interpolate(&partials[..threshold as usize], 0)
+}
+
+/// Evaluate the polynomial in the exponent given by the partial results at `x`. Can be used to check
+/// that more than `threshold` public key shares are consistent.
+pub fn interpolate<G: GroupElement<ScalarType = Scalar>>(
+    partials: &[Partial<G>],
+    x: ShareIndex,
+) -> FastCryptoResult<G> {
+    if partials.is_empty()
+        || partials.iter().any(|p| p.index == 0)
+        || !partials.iter().map(|p| p.index).all_unique()
+    {
+        return Err(InvalidInput);
+    }
+    let x = Scalar::from(x as u128);
+    partials.iter().try_fold(G::zero(), |sum, partial_i| {
+        // Lagrange coefficient of partial_i evaluated at x
+        let x_i = Scalar::from(partial_i.index as u128);
+        let (numerator, denominator) = partials
+            .iter()
+            .filter(|partial_j| partial_j.index != partial_i.index)
+            .map(|partial_j| Scalar::from(partial_j.index as u128))
+            .fold(
+                (Scalar::generator(), Scalar::generator()),
+                |(numerator, denominator), x_j| (numerator * (x - x_j), denominator * (x_i - x_j)),
+            );
+        Ok(sum + partial_i.value * (numerator * denominator.inverse()?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn test_threshold_extract() {
+        let mut rng = thread_rng();
+        let (master_key, public_key) = ibe::generate_key_pair(&mut rng);
+        let shares = split(&mut rng, &master_key, 3, 5).unwrap();
+        let public_key_shares = shares.iter().map(public_key_share).collect_vec();
+        let id = b"id";
+
+        let partials = shares
+            .iter()
+            .map(|share| partial_extract(share, id))
+            .collect_vec();
+        for (partial, pk_share) in partials.iter().zip(&public_key_shares) {
+            assert!(verify_partial_user_secret_key(partial, id, pk_share).is_ok());
+        }
+        assert!(verify_partial_user_secret_key(&partials[0], id, &public_key_shares[1]).is_err());
+
+        // Any threshold partial keys give the user secret key
+        let expected = ibe::extract(&master_key, id);
+        for subset in partials.iter().copied().combinations(3) {
+            assert_eq!(combine(&subset, 3).unwrap(), expected);
+        }
+        assert!(ibe::verify_user_secret_key(&expected, id, &public_key).is_ok());
+
+        // But fewer do not
+        assert!(combine(&partials[..2], 3).is_err());
+        assert_ne!(combine(&partials[..2], 2).unwrap(), expected);
+
+        // Duplicate indices are rejected
+        assert!(combine(&[partials[0], partials[0], partials[1]], 3).is_err());
+
+        // The public key shares give the public key and are consistent
+        assert_eq!(combine(&public_key_shares, 3).unwrap(), public_key);
+        assert_eq!(
+            interpolate(&public_key_shares[..3], 5).unwrap(),
+            public_key_shares[4].value
+        );
+
+        assert!(split(&mut rng, &master_key, 0, 5).is_err());
+        assert!(split(&mut rng, &master_key, 6, 5).is_err());
+    }
+
+    #[test]
+    fn test_threshold_proof_of_possession() {
+        let mut rng = thread_rng();
+        let (master_key, public_key) = ibe::generate_key_pair(&mut rng);
+        let shares = split(&mut rng, &master_key, 2, 3).unwrap();
+        let message = b"key server object id";
+
+        let partials = shares
+            .iter()
+            .map(|share| partial_proof_of_possession(share, &public_key, message))
+            .collect_vec();
+        for (partial, share) in partials.iter().zip(&shares) {
+            assert!(verify_partial_proof_of_possession(
+                partial,
+                &public_key,
+                message,
+                &public_key_share(share)
+            )
+            .is_ok());
+        }
+        let pop = combine(&partials[1..], 2).unwrap();
+        assert_eq!(pop, ibe::create_proof_of_possession(&master_key, message));
+        assert!(ibe::verify_proof_of_possession(&pop, &public_key, message).is_ok());
+    }
+
+    #[test]
+    fn test_threshold_extract_encrypted() {
+        let mut rng = thread_rng();
+        let (master_key, _) = ibe::generate_key_pair(&mut rng);
+        let shares = split(&mut rng, &master_key, 2, 3).unwrap();
+        let (sk, enc_key, _) = elgamal::genkey::<_, G2Element, _>(&mut rng);
+        let id = b"id";
+
+        let partials = shares
+            .iter()
+            .map(|share| partial_extract_encrypted(&mut rng, share, id, &enc_key))
+            .collect_vec();
+        for (partial, share) in partials.iter().zip(&shares) {
+            assert!(verify_encrypted_partial_user_secret_key(
+                partial,
+                id,
+                &enc_key,
+                &public_key_share(share)
+            )
+            .is_ok());
+        }
+        assert!(verify_encrypted_partial_user_secret_key(
+            &partials[0],
+            b"other id",
+            &enc_key,
+            &public_key_share(&shares[0])
+        )
+        .is_err());
+
+        // A partial key encrypted under another key is rejected
+        let (_, other_enc_key, _) = elgamal::genkey::<_, G2Element, _>(&mut rng);
+        assert!(verify_encrypted_partial_user_secret_key(
+            &partials[0],
+            id,
+            &other_enc_key,
+            &public_key_share(&shares[0])
+        )
+        .is_err());
+
+        let encrypted = combine_encrypted(&[&partials[2], &partials[0]], 2).unwrap();
+        assert_eq!(
+            elgamal::decrypt(&sk, &encrypted),
+            ibe::extract(&master_key, id)
+        );
+    }
+}
@@ -0,0 +1,319 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Authenticated admin API for operators, served on a separate port, see [AdminConfig].
+//!
+//! All requests must present the token from [AdminConfig::token_env_var] as a bearer token.
+//! Clients enabled or disabled through the admin API keep their state across configuration
+//! reloads, but not across restarts.
+
+use crate::externals::{cache_stats, flush_caches, CacheStats};
+use crate::key_server_options::{AdminConfig, ClientKeyType, KeyServerOptions, ServerMode};
+use crate::master_keys::MasterKeys;
+use crate::time::saturating_duration_since;
+use crate::utils::constant_time_eq;
+use crate::{DefaultEncoding, MyState, Timestamp};
+use anyhow::{anyhow, Context};
+use axum::extract::{Path, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::StatusCode;
+use axum::middleware::{from_fn_with_state, Next};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use crypto::ibe::public_key_from_master_key;
+use fastcrypto::encoding::Encoding;
+use fastcrypto::serde_helpers::ToFromByteArray;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use sui_types::base_types::ObjectID;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+#[derive(Clone)]
+struct AdminState {
+    app_state: MyState,
+    token: Arc<str>,
+    config_path: Option<PathBuf>,
+}
+
+/// A client served by the key server. In open mode, there is a single client without a name that
+/// serves all packages.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ClientInfo {
+    pub name: Option<String>,
+    pub key_server_object_id: ObjectID,
+    pub package_ids: Vec<ObjectID>,
+    pub client_master_key: Option<ClientKeyType>,
+    /// Not set if the key was exported.
+    pub public_key: Option<String>,
+    pub enabled: bool,
+}
+
+/// The master keys of a key server object, see [crate::key_rotation].
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct KeyServerKeys {
+    pub key_server_object_id: ObjectID,
+    pub current_version: u32,
+    /// The version whose public key is registered on-chain. Only fetched while keys are rotated.
+    pub onchain_version: Option<u32>,
+    pub keys: Vec<KeyVersionInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct KeyVersionInfo {
+    pub version: u32,
+    pub public_key: String,
+    pub deprecated: bool,
+    /// The number of requests served with this key since the server started, if it is deprecated.
+    pub deprecated_requests: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct CheckpointFreshness {
+    pub latest_checkpoint_timestamp: Timestamp,
+    pub staleness_ms: u64,
+    pub allowed_staleness_ms: u64,
+    pub is_fresh: bool,
+}
+
+/// Start serving the admin API. Fails if the token is not set or the port is not available.
+pub(crate) async fn start_admin_server(
+    config: &AdminConfig,
+    app_state: MyState,
+    config_path: Option<PathBuf>,
+) -> anyhow::Result<JoinHandle<anyhow::Result<()>>> {
+    let token = std::env::var(&config.token_env_var)
+        .with_context(|| format!("Admin token {} must be set", config.token_env_var))?;
+    if token.is_empty() {
+        return Err(anyhow!(
+            "Admin token {} must not be empty",
+            config.token_env_var
+        ));
+    }
+    let addr = SocketAddr::new(config.host, config.host_port);
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Cannot bind admin API to {}", addr))?;
+
+    info!("Serving admin API on {}", addr);
+    let router = admin_router(app_state, token, config_path);
+    Ok(tokio::spawn(async move {
+        axum::serve(listener, router)
+            .await
+            .context("Admin API stopped")
+    }))
+}
+
+pub(crate) fn admin_router(
+    app_state: MyState,
+    token: String,
+    config_path: Option<PathBuf>,
+) -> Router {
+    let state = AdminState {
+        app_state,
+        token: token.into(),
+        config_path,
+    };
+    Router::new()
+        .route("/v1/config", get(handle_get_config))
+        .route("/v1/config/reload", post(handle_reload_config))
+        .route("/v1/clients", get(handle_get_clients))
+        .route("/v1/clients/:name/enable", post(handle_enable_client))
+        .route("/v1/clients/:name/disable", post(handle_disable_client))
+        .route("/v1/keys", get(handle_get_keys))
+        .route("/v1/caches", get(handle_get_caches))
+        .route("/v1/caches/flush", post(handle_flush_caches))
+        .route("/v1/checkpoint", get(handle_get_checkpoint))
+        .layer(from_fn_with_state(state.clone(), handle_admin_auth))
+        .with_state(state)
+}
+
+/// Middleware to check the bearer token of admin requests.
+async fn handle_admin_auth(
+    State(state): State<AdminState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token, &state.token));
+    if !authorized {
+        warn!("Unauthorized admin request to {}", request.uri().path());
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(request).await)
+}
+
+/// The configuration with secrets redacted. The server mode is the one of the last reload.
+async fn handle_get_config(State(state): State<AdminState>) -> Json<KeyServerOptions> {
+    let server = &state.app_state.server;
+    let mut options = server.options.redacted();
+    options.server_mode = server.key_state.load().server_mode.clone();
+    Json(options)
+}
+
+async fn handle_reload_config(
+    State(state): State<AdminState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let Some(path) = &state.config_path else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The configuration is not read from a file".to_string(),
+        ));
+    };
+    let result = state.app_state.server.reload_config(path).await;
+    state
+        .app_state
+        .metrics
+        .observe_config_reload(result.is_ok());
+    result.map(|_| StatusCode::OK).map_err(|e| {
+        warn!("Rejected new configuration: {:?}", e);
+        (StatusCode::BAD_REQUEST, format!("{:#}", e))
+    })
+}
+
+async fn handle_get_clients(State(state): State<AdminState>) -> Json<Vec<ClientInfo>> {
+    let key_state = state.app_state.server.key_state.load();
+    let public_key = |key_server_object_id: &ObjectID| {
+        let public_key = match &key_state.master_keys {
+            MasterKeys::Committee { committee } => Some(committee.public_key),
+            master_keys => master_keys
+                .get_key_for_key_server(key_server_object_id)
+                .ok()
+                .map(|keys| public_key_from_master_key(keys.current())),
+        };
+        public_key.map(|pk| DefaultEncoding::encode(pk.to_byte_array()))
+    };
+    let clients = match &key_state.server_mode {
+        ServerMode::Open {
+            key_server_object_id,
+            ..
+        }
+        | ServerMode::Committee {
+            key_server_object_id,
+            ..
+        } => vec![ClientInfo {
+            name: None,
+            key_server_object_id: *key_server_object_id,
+            package_ids: vec![],
+            client_master_key: None,
+            public_key: public_key(key_server_object_id),
+            enabled: true,
+        }],
+        ServerMode::Permissioned { client_configs } => client_configs
+            .iter()
+            .map(|config| ClientInfo {
+                name: Some(config.name.clone()),
+                key_server_object_id: config.key_server_object_id,
+                package_ids: config.package_ids.clone(),
+                client_master_key: Some(config.client_master_key.clone()),
+                public_key: public_key(&config.key_server_object_id),
+                enabled: key_state.client_access.is_enabled(&config.name),
+            })
+            .collect(),
+        // Members serve no clients
+        ServerMode::CommitteeMember { .. } => vec![],
+    };
+    Json(clients)
+}
+
+async fn handle_enable_client(
+    State(state): State<AdminState>,
+    Path(name): Path<String>,
+) -> StatusCode {
+    set_client_enabled(&state, &name, true)
+}
+
+async fn handle_disable_client(
+    State(state): State<AdminState>,
+    Path(name): Path<String>,
+) -> StatusCode {
+    set_client_enabled(&state, &name, false)
+}
+
+fn set_client_enabled(state: &AdminState, name: &str, enabled: bool) -> StatusCode {
+    let key_state = state.app_state.server.key_state.load();
+    if !key_state.client_access.set_enabled(name, enabled) {
+        return StatusCode::NOT_FOUND;
+    }
+    info!(
+        "Client {} {} through the admin API",
+        name,
+        if enabled { "enabled" } else { "disabled" }
+    );
+    StatusCode::OK
+}
+
+async fn handle_get_keys(State(state): State<AdminState>) -> Json<Vec<KeyServerKeys>> {
+    let server = &state.app_state.server;
+    let metrics = &state.app_state.metrics;
+    let key_state = server.key_state.load();
+    let keys = key_state
+        .master_keys
+        .all()
+        .into_iter()
+        .map(|keys| {
+            let ks_oid = keys.key_server_object_id;
+            KeyServerKeys {
+                key_server_object_id: ks_oid,
+                current_version: keys.current_version,
+                onchain_version: server
+                    .onchain_public_keys
+                    .get(&ks_oid)
+                    .and_then(|pk| keys.version_of(&pk)),
+                keys: keys
+                    .keys
+                    .iter()
+                    .map(|(version, key)| {
+                        let deprecated = *version != keys.current_version;
+                        KeyVersionInfo {
+                            version: *version,
+                            public_key: DefaultEncoding::encode(
+                                public_key_from_master_key(key).to_byte_array(),
+                            ),
+                            deprecated,
+                            deprecated_requests: if deprecated {
+                                metrics.deprecated_key_requests(&ks_oid, *version)
+                            } else {
+                                0
+                            },
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    Json(keys)
+}
+
+async fn handle_get_caches() -> Json<CacheStats> {
+    Json(cache_stats())
+}
+
+async fn handle_flush_caches() -> StatusCode {
+    flush_caches();
+    info!("Caches flushed through the admin API");
+    StatusCode::OK
+}
+
+async fn handle_get_checkpoint(State(state): State<AdminState>) -> Json<CheckpointFreshness> {
+    let latest_checkpoint_timestamp = *state
+        .app_state
+        .latest_checkpoint_timestamp_receiver
+        .borrow();
+    let staleness = saturating_duration_since(latest_checkpoint_timestamp);
+    let allowed_staleness = state.app_state.server.options.allowed_staleness;
+    Json(CheckpointFreshness {
+        latest_checkpoint_timestamp,
+        staleness_ms: staleness.as_millis() as u64,
+        allowed_staleness_ms: allowed_staleness.as_millis() as u64,
+        is_fresh: staleness <= allowed_staleness,
+    })
+}
@@ -0,0 +1,514 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! An append-only audit log of the decisions on fetch key requests, written as JSON lines.
+//!
+//! If the hash chain is enabled, each record contains the hash of the previous record as
+//! `prev_hash` and ends with its own hash as `hash`, which is the hex encoded SHA3-256 hash of the
+//! line without the `hash` field. Removing or modifying a record breaks the chain. When writing to a
+//! file, the chain is continued from the last record in the file after a restart; for the other
+//! sinks it starts from zero.
+//!
+//! Records are written by a dedicated thread, so that request handlers never block on the sink.
+
+use crate::errors::InternalError;
+use crate::key_server_options::{AuditLogConfig, AuditLogSink};
+use crate::metrics::Metrics;
+use crate::valid_ptb::ValidPtb;
+use crate::{DefaultEncoding, KeyId, KeyIdError};
+use chrono::{SecondsFormat, Utc};
+use fastcrypto::encoding::Encoding;
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use sui_types::base_types::{ObjectID, SuiAddress};
+use tracing::warn;
+
+/// The syslog priority of audit records: facility authpriv (10) and severity info (6).
+const SYSLOG_PRIORITY: u8 = 10 * 8 + 6;
+
+const SYSLOG_TAG: &str = "seal-key-server";
+
+/// The maximum number of records waiting to be written. If the sink does not keep up, further
+/// records are dropped and counted as failures rather than slowing down requests.
+const MAX_PENDING_RECORDS: usize = 10_000;
+
+type Hash = [u8; 32];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Decision {
+    Granted,
+    /// Only some ids were granted in a partial success request.
+    PartiallyGranted,
+    Denied,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DeniedKeyId {
+    key_id: String,
+    reason: &'static str,
+}
+
+/// The decision on a fetch key request, or on a bundle of a batch request.
+#[derive(Debug, Serialize)]
+pub(crate) struct AuditRecord {
+    pub user: SuiAddress,
+    /// The first version of the package, or the package of the PTB if it could not be resolved.
+    /// Not set for batch requests that were denied as a whole.
+    pub package_id: Option<ObjectID>,
+    /// The requested ids without the package id prefix.
+    pub key_ids: Vec<String>,
+    pub req_id: Option<String>,
+    pub sdk_version: String,
+    pub batch: bool,
+    pub decision: Decision,
+    /// Why the request was denied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+    /// The ids denied in a partial success request.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub denied_key_ids: Vec<DeniedKeyId>,
+}
+
+impl AuditRecord {
+    /// Create a record from the result of checking a request with the given PTB.
+    pub fn new(
+        user: SuiAddress,
+        ptb: &str,
+        result: Result<&(ObjectID, Vec<KeyId>, Vec<KeyIdError>), &InternalError>,
+        req_id: Option<&str>,
+        sdk_version: &str,
+        batch: bool,
+    ) -> Self {
+        let valid_ptb = ValidPtb::try_from_base64(ptb).ok();
+        let key_ids = key_ids(valid_ptb.iter());
+        let (package_id, decision, reason, denied_key_ids) = match result {
+            Ok((first_pkg_id, ids, errors)) => {
+                let decision = match (ids.is_empty(), errors.is_empty()) {
+                    (_, true) => Decision::Granted,
+                    (true, false) => Decision::Denied,
+                    (false, false) => Decision::PartiallyGranted,
+                };
+                let denied_key_ids = errors
+                    .iter()
+                    .map(|e| DeniedKeyId {
+                        key_id: DefaultEncoding::encode(
+                            e.id.strip_prefix(first_pkg_id.as_ref())
+                                .unwrap_or(&e.id[..]),
+                        ),
+                        reason: e.error.as_str(),
+                    })
+                    .collect();
+                (Some(*first_pkg_id), decision, None, denied_key_ids)
+            }
+            Err(e) => (
+                valid_ptb.as_ref().map(ValidPtb::pkg_id),
+                Decision::Denied,
+                Some(e.as_str()),
+                vec![],
+            ),
+        };
+        Self {
+            user,
+            package_id,
+            key_ids,
+            req_id: req_id.map(str::to_string),
+            sdk_version: sdk_version.to_string(),
+            batch,
+            decision,
+            reason,
+            denied_key_ids,
+        }
+    }
+
+    /// Create a single record for a batch request that was denied as a whole, e.g., because of an
+    /// invalid session signature, with the requested ids of all bundles.
+    pub fn new_for_denied_batch<'a>(
+        user: SuiAddress,
+        ptbs: impl IntoIterator<Item = &'a str>,
+        error: &InternalError,
+        req_id: Option<&str>,
+        sdk_version: &str,
+    ) -> Self {
+        let valid_ptbs = ptbs
+            .into_iter()
+            .filter_map(|ptb| ValidPtb::try_from_base64(ptb).ok())
+            .collect::<Vec<_>>();
+        Self {
+            user,
+            package_id: None,
+            key_ids: key_ids(valid_ptbs.iter()),
+            req_id: req_id.map(str::to_string),
+            sdk_version: sdk_version.to_string(),
+            batch: true,
+            decision: Decision::Denied,
+            reason: Some(error.as_str()),
+            denied_key_ids: vec![],
+        }
+    }
+}
+
+/// The hex encoded ids requested by the given PTBs, without the package id prefix.
+fn key_ids<'a>(valid_ptbs: impl Iterator<Item = &'a ValidPtb>) -> Vec<String> {
+    valid_ptbs
+        .flat_map(|ptb| ptb.inner_ids())
+        .map(DefaultEncoding::encode)
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    record: &'a AuditRecord,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prev_hash: Option<String>,
+}
+
+enum Sink {
+    File {
+        file: File,
+        path: PathBuf,
+        size: u64,
+        max_size_bytes: u64,
+        max_files: usize,
+    },
+    Stdout,
+    Syslog(UnixDatagram),
+}
+
+impl Sink {
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        match self {
+            Sink::File {
+                file,
+                path,
+                size,
+                max_size_bytes,
+                max_files,
+            } => {
+                let len = line.len() as u64 + 1;
+                if *size > 0 && *size + len > *max_size_bytes {
+                    rotate(path, *max_files)?;
+                    *file = open_log_file(path)?;
+                    *size = 0;
+                }
+                file.write_all(format!("{line}\n").as_bytes())?;
+                *size += len;
+                Ok(())
+            }
+            Sink::Stdout => writeln!(std::io::stdout().lock(), "{line}"),
+            Sink::Syslog(socket) => socket
+                .send(format!("<{SYSLOG_PRIORITY}>{SYSLOG_TAG}: {line}").as_bytes())
+                .map(|_| ()),
+        }
+    }
+}
+
+/// A record and the time at which it was made.
+struct Entry {
+    timestamp: String,
+    record: AuditRecord,
+}
+
+/// Writes the records to the sink, running on the writer thread.
+struct Writer {
+    sink: Sink,
+    hash_chain: bool,
+    last_hash: Hash,
+}
+
+impl Writer {
+    fn write(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(&Line {
+            timestamp: entry.timestamp.clone(),
+            record: &entry.record,
+            prev_hash: self.hash_chain.then(|| hex::encode(self.last_hash)),
+        })?;
+        let hash = self.hash_chain.then(|| {
+            let hash = Sha3_256::digest(line.as_bytes()).digest;
+            // Replace the closing brace
+            line.pop();
+            line.push_str(&format!(",\"hash\":\"{}\"}}", hex::encode(hash)));
+            hash
+        });
+        self.sink.write(&line)?;
+        if let Some(hash) = hash {
+            self.last_hash = hash;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct AuditLog {
+    // Only None while dropping
+    sender: Option<SyncSender<Entry>>,
+    writer: Option<JoinHandle<()>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl AuditLog {
+    pub fn new(config: &AuditLogConfig, metrics: Option<Arc<Metrics>>) -> anyhow::Result<Self> {
+        let mut last_hash = Hash::default();
+        let sink = match &config.sink {
+            AuditLogSink::File {
+                path,
+                max_size_bytes,
+                max_files,
+            } => {
+                if config.hash_chain {
+                    // The current file may be empty if the server stopped right after a rotation
+                    let hash = match last_hash_in(path)? {
+                        Some(hash) => Some(hash),
+                        None => last_hash_in(&rotated(path, 1))?,
+                    };
+                    last_hash = hash.unwrap_or_default();
+                }
+                let file = open_log_file(path)?;
+                Sink::File {
+                    size: file.metadata()?.len(),
+                    file,
+                    path: path.clone(),
+                    max_size_bytes: *max_size_bytes,
+                    max_files: *max_files,
+                }
+            }
+            AuditLogSink::Stdout => Sink::Stdout,
+            AuditLogSink::Syslog { socket_path } => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(socket_path)?;
+                Sink::Syslog(socket)
+            }
+        };
+
+        let mut writer = Writer {
+            sink,
+            hash_chain: config.hash_chain,
+            last_hash,
+        };
+        let (sender, receiver) = sync_channel::<Entry>(MAX_PENDING_RECORDS);
+        let writer_metrics = metrics.clone();
+        let writer = std::thread::Builder::new()
+            .name("audit-log".to_string())
+            .spawn(move || {
+                for entry in receiver {
+                    if let Err(e) = writer.write(&entry) {
+                        warn!("Failed to write audit record {:?}: {:?}", entry.record, e);
+                        if let Some(m) = &writer_metrics {
+                            m.audit_log_failures.inc();
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            sender: Some(sender),
+            writer: Some(writer),
+            metrics,
+        })
+    }
+
+    /// Queue a record to be appended to the log. Does not block.
+    pub fn record(&self, record: AuditRecord) {
+        let entry = Entry {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            record,
+        };
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        if let Err(TrySendError::Full(entry) | TrySendError::Disconnected(entry)) =
+            sender.try_send(entry)
+        {
+            warn!("Dropped audit record {:?}", entry.record);
+            if let Some(m) = &self.metrics {
+                m.audit_log_failures.inc();
+            }
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    /// Wait for the queued records to be written.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Audit logs may contain user addresses, so they are only accessible by the owner.
+fn open_log_file(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{index}"));
+    rotated.into()
+}
+
+/// Rotate `path` to `path.1`, `path.1` to `path.2` and so on, dropping `path.<max_files>`.
+fn rotate(path: &Path, max_files: usize) -> std::io::Result<()> {
+    for index in (1..max_files).rev() {
+        let from = rotated(path, index);
+        if from.exists() {
+            std::fs::rename(from, rotated(path, index + 1))?;
+        }
+    }
+    std::fs::rename(path, rotated(path, 1))
+}
+
+/// The hash of the last record in a log file, if any.
+fn last_hash_in(path: &Path) -> anyhow::Result<Option<Hash>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let Some(line) = BufReader::new(File::open(path)?)
+        .lines()
+        .last()
+        .transpose()?
+    else {
+        return Ok(None);
+    };
+    let record: serde_json::Value = serde_json::from_str(&line)?;
+    let hash = record["hash"]
+        .as_str()
+        .and_then(|hash| hex::decode(hash).ok())
+        .and_then(|hash| hash.try_into().ok())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "The last record in {} has no hash, cannot continue the hash chain",
+                path.display()
+            )
+        })?;
+    Ok(Some(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that the lines form a hash chain starting from zero.
+    fn verify_hash_chain(lines: &[String]) -> bool {
+        let mut prev_hash = hex::encode(Hash::default());
+        for line in lines {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            let Some(hash) = record["hash"].as_str() else {
+                return false;
+            };
+            let Some(content) = line.strip_suffix(&format!(",\"hash\":\"{hash}\"}}")) else {
+                return false;
+            };
+            if record["prev_hash"] != prev_hash.as_str()
+                || hex::encode(Sha3_256::digest(format!("{content}}}").as_bytes()).digest) != hash
+            {
+                return false;
+            }
+            prev_hash = hash.to_string();
+        }
+        true
+    }
+
+    fn read_lines(path: &Path) -> Vec<String> {
+        BufReader::new(File::open(path).unwrap())
+            .lines()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn record(decision: Decision) -> AuditRecord {
+        AuditRecord {
+            user: SuiAddress::random_for_testing_only(),
+            package_id: Some(ObjectID::random()),
+            key_ids: vec!["0x0102".to_string()],
+            req_id: Some("req".to_string()),
+            sdk_version: "0.4.5".to_string(),
+            batch: false,
+            decision,
+            reason: (decision == Decision::Denied).then_some("NoAccess"),
+            denied_key_ids: vec![],
+        }
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let dir = std::env::temp_dir().join(format!("audit-{}", ObjectID::random()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+        let config = AuditLogConfig {
+            sink: AuditLogSink::File {
+                path: path.clone(),
+                max_size_bytes: 1024,
+                max_files: 2,
+            },
+            hash_chain: true,
+        };
+
+        // Dropping the log waits for the queued records to be written
+        let log = AuditLog::new(&config, None).unwrap();
+        log.record(record(Decision::Granted));
+        log.record(record(Decision::Denied));
+        drop(log);
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 2);
+        assert!(verify_hash_chain(&lines));
+        let first: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["decision"], "granted");
+        assert_eq!(first["key_ids"][0], "0x0102");
+        assert!(first.get("reason").is_none());
+        let second: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(second["decision"], "denied");
+        assert_eq!(second["reason"], "NoAccess");
+
+        // The chain continues after a restart
+        let log = AuditLog::new(&config, None).unwrap();
+        log.record(record(Decision::PartiallyGranted));
+        drop(log);
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 3);
+        assert!(verify_hash_chain(&lines));
+
+        // Modified and removed records are detected
+        let mut modified = lines.clone();
+        modified[1] = modified[1].replace("denied", "granted");
+        assert!(!verify_hash_chain(&modified));
+        assert!(!verify_hash_chain(&[lines[0].clone(), lines[2].clone()]));
+
+        // Files are rotated once they exceed the maximum size, keeping at most max_files
+        let log = AuditLog::new(&config, None).unwrap();
+        for _ in 0..20 {
+            log.record(record(Decision::Granted));
+        }
+        drop(log);
+        assert!(std::fs::metadata(&path).unwrap().len() <= 1024);
+        assert!(rotated(&path, 1).exists());
+        assert!(rotated(&path, 2).exists());
+        assert!(!rotated(&path, 3).exists());
+        let mut lines = read_lines(&rotated(&path, 1));
+        lines.extend(read_lines(&path));
+        assert!(lines.len() > 1);
+        // The chain continues across files
+        let first: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        for (previous, line) in lines.iter().zip(&lines[1..]) {
+            let previous: serde_json::Value = serde_json::from_str(previous).unwrap();
+            let line: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(line["prev_hash"], previous["hash"]);
+        }
+        assert_ne!(first["prev_hash"], hex::encode(Hash::default()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,399 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! API keys and request quotas of permissioned clients, see [ClientAccess].
+
+use crate::errors::InternalError;
+use crate::key_server_options::{KeyServerOptions, Quota, ServerMode};
+use crate::metrics::Metrics;
+use crate::utils::constant_time_eq;
+use anyhow::Context;
+use chrono::{DateTime, Datelike};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use sui_types::base_types::ObjectID;
+use tracing::{debug, info};
+
+/// The header in which clients send their API key.
+pub(crate) const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// The requests of a client in the current day and month.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Usage {
+    /// Days since the common era, see [Datelike::num_days_from_ce].
+    pub day: i32,
+    pub daily_requests: u64,
+    /// Months since the common era.
+    pub month: i32,
+    pub monthly_requests: u64,
+}
+
+impl Usage {
+    /// Move the counters to the period of the given time, resetting them if a period has passed.
+    fn roll_over(&mut self, now_ms: u64) {
+        let now = DateTime::from_timestamp_millis(now_ms as i64).unwrap_or_default();
+        let day = now.num_days_from_ce();
+        let month = now.year() * 12 + now.month0() as i32;
+        if self.day != day {
+            self.day = day;
+            self.daily_requests = 0;
+        }
+        if self.month != month {
+            self.month = month;
+            self.monthly_requests = 0;
+        }
+    }
+}
+
+/// The access configuration of a single client.
+struct ClientEntry {
+    name: String,
+    api_key: Option<String>,
+    quota: Option<Quota>,
+}
+
+/// Enforces the API keys and quotas of the clients of a permissioned server, and rejects requests
+/// of clients disabled through the admin API. In open mode, all requests are allowed.
+///
+/// Request counters are kept in memory and written to the usage store by [ClientAccess::persist],
+/// which is called periodically. Requests since the last write are lost if the server stops.
+pub(crate) struct ClientAccess {
+    clients_by_package: HashMap<ObjectID, usize>,
+    clients: Vec<ClientEntry>,
+    /// Shared with the instances created by [ClientAccess::reload].
+    usage: Arc<Mutex<HashMap<String, Usage>>>,
+    store_path: Option<PathBuf>,
+    dirty: Arc<AtomicBool>,
+    /// Names of the disabled clients. Shared with the instances created by [ClientAccess::reload].
+    disabled: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ClientAccess {
+    /// Load the API keys from the environment and the counters from the usage store.
+    pub fn load(options: &KeyServerOptions) -> anyhow::Result<Self> {
+        let usage = match &options.usage_store_path {
+            Some(path) if path.exists() => {
+                info!("Loading client usage from {}", path.display());
+                serde_json::from_slice(&std::fs::read(path)?)
+                    .with_context(|| format!("Cannot parse usage store {}", path.display()))?
+            }
+            _ => HashMap::new(),
+        };
+        Self::new(
+            options,
+            Arc::new(Mutex::new(usage)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(Mutex::new(HashSet::new())),
+        )
+    }
+
+    /// Load the API keys of a new configuration while sharing the counters and disabled clients
+    /// with this instance, such that requests counted by either are not lost.
+    pub fn reload(&self, options: &KeyServerOptions) -> anyhow::Result<Self> {
+        Self::new(
+            options,
+            self.usage.clone(),
+            self.dirty.clone(),
+            self.disabled.clone(),
+        )
+    }
+
+    fn new(
+        options: &KeyServerOptions,
+        usage: Arc<Mutex<HashMap<String, Usage>>>,
+        dirty: Arc<AtomicBool>,
+        disabled: Arc<Mutex<HashSet<String>>>,
+    ) -> anyhow::Result<Self> {
+        let mut clients_by_package = HashMap::new();
+        let mut clients = vec![];
+        if let ServerMode::Permissioned { client_configs } = &options.server_mode {
+            for config in client_configs {
+                let api_key = config
+                    .api_key_env_var
+                    .as_ref()
+                    .map(|env_var| {
+                        std::env::var(env_var)
+                            .with_context(|| format!("API key {} must be set", env_var))
+                    })
+                    .transpose()?;
+                for pkg_id in &config.package_ids {
+                    clients_by_package.insert(*pkg_id, clients.len());
+                }
+                clients.push(ClientEntry {
+                    name: config.name.clone(),
+                    api_key,
+                    quota: config.quota.clone(),
+                });
+            }
+        }
+
+        Ok(Self {
+            clients_by_package,
+            clients,
+            usage,
+            store_path: options.usage_store_path.clone(),
+            dirty,
+            disabled,
+        })
+    }
+
+    /// Enable or disable a client. Returns false if there is no client with the given name.
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> bool {
+        if !self.clients.iter().any(|client| client.name == name) {
+            return false;
+        }
+        let mut disabled = self.disabled.lock().unwrap();
+        if enabled {
+            disabled.remove(name);
+        } else {
+            disabled.insert(name.to_string());
+        }
+        true
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.lock().unwrap().contains(name)
+    }
+
+    /// Check the API key of a request for the given package and that its client has quota left.
+    /// The request is only counted towards the quota by [ClientAccess::charge] once access is
+    /// granted, so concurrent requests may exceed the quota slightly.
+    pub fn check(
+        &self,
+        first_pkg_id: &ObjectID,
+        api_key: Option<&str>,
+        now_ms: u64,
+        metrics: Option<&Metrics>,
+        req_id: Option<&str>,
+    ) -> Result<(), InternalError> {
+        let Some(client) = self.client(first_pkg_id) else {
+            return Ok(());
+        };
+
+        if let Some(expected) = &client.api_key {
+            if !api_key.is_some_and(|api_key| constant_time_eq(api_key, expected)) {
+                debug!(
+                    "Invalid API key for client {} (req_id: {:?})",
+                    client.name, req_id
+                );
+                return Err(InternalError::InvalidApiKey);
+            }
+        }
+
+        if !self.is_enabled(&client.name) {
+            debug!("Client {} is disabled (req_id: {:?})", client.name, req_id);
+            return Err(InternalError::ClientDisabled);
+        }
+
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(client.name.clone()).or_default();
+        usage.roll_over(now_ms);
+        if let Some(quota) = &client.quota {
+            let exceeded = if quota.daily.is_some_and(|max| usage.daily_requests >= max) {
+                Some("daily")
+            } else if quota
+                .monthly
+                .is_some_and(|max| usage.monthly_requests >= max)
+            {
+                Some("monthly")
+            } else {
+                None
+            };
+            if let Some(period) = exceeded {
+                debug!(
+                    "Client {} exceeded its {} quota (req_id: {:?})",
+                    client.name, period, req_id
+                );
+                if let Some(m) = metrics {
+                    m.observe_quota_exceeded(&client.name, period);
+                }
+                return Err(InternalError::QuotaExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a request for the given package that was granted access towards the quota of its
+    /// client.
+    pub fn charge(&self, first_pkg_id: &ObjectID, now_ms: u64, metrics: Option<&Metrics>) {
+        let Some(client) = self.client(first_pkg_id) else {
+            return;
+        };
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(client.name.clone()).or_default();
+        usage.roll_over(now_ms);
+        usage.daily_requests += 1;
+        usage.monthly_requests += 1;
+        self.dirty.store(true, Ordering::Relaxed);
+        if let Some(m) = metrics {
+            m.observe_client_request(&client.name);
+        }
+    }
+
+    fn client(&self, first_pkg_id: &ObjectID) -> Option<&ClientEntry> {
+        self.clients_by_package
+            .get(first_pkg_id)
+            .map(|i| &self.clients[*i])
+    }
+
+    /// Write the counters to the usage store if they changed since the last write.
+    pub fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.store_path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let usage = serde_json::to_vec(&*self.usage.lock().unwrap())?;
+        write_atomically(path, &usage).inspect_err(|_| {
+            // Try again next time
+            self.dirty.store(true, Ordering::Relaxed);
+        })
+    }
+}
+
+/// Write to a temporary file first, such that the store is not corrupted by a crash.
+fn write_atomically(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, contents)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_server_options::{ClientConfig, ClientKeyType};
+    use crate::types::Network;
+
+    const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+    // 2025-01-30
+    const NOW_MS: u64 = 1_738_195_200_000;
+
+    fn options(store_path: Option<PathBuf>) -> (KeyServerOptions, ObjectID) {
+        let package_id = ObjectID::random();
+        let mut options =
+            KeyServerOptions::new_open_server_with_default_values(Network::Testnet, package_id);
+        options.server_mode = ServerMode::Permissioned {
+            client_configs: vec![ClientConfig {
+                name: "alice".to_string(),
+                client_master_key: ClientKeyType::Derived {
+                    derivation_index: 0,
+                },
+                key_server_object_id: ObjectID::random(),
+                package_ids: vec![package_id],
+                api_key_env_var: Some("ALICE_API_KEY".to_string()),
+                quota: Some(Quota {
+                    daily: Some(2),
+                    monthly: Some(3),
+                }),
+                key_version: 0,
+                deprecated_keys: vec![],
+            }],
+        };
+        options.usage_store_path = store_path;
+        (options, package_id)
+    }
+
+    #[test]
+    fn test_api_key() {
+        let (options, package_id) = options(None);
+        assert!(ClientAccess::load(&options).is_err());
+
+        let access = temp_env::with_var("ALICE_API_KEY", Some("secret"), || {
+            ClientAccess::load(&options).unwrap()
+        });
+        let check = |api_key| access.check(&package_id, api_key, NOW_MS, None, None);
+        assert_eq!(check(None), Err(InternalError::InvalidApiKey));
+        assert_eq!(check(Some("secreT")), Err(InternalError::InvalidApiKey));
+        assert_eq!(check(Some("secret")), Ok(()));
+
+        // Packages of other clients are not affected
+        assert_eq!(
+            access.check(&ObjectID::random(), None, NOW_MS, None, None),
+            Ok(())
+        );
+
+        // Disabled clients are rejected until enabled again
+        assert!(access.set_enabled("alice", false));
+        assert!(!access.set_enabled("bob", false));
+        assert_eq!(check(Some("secret")), Err(InternalError::ClientDisabled));
+        assert!(access.set_enabled("alice", true));
+        assert_eq!(check(Some("secret")), Ok(()));
+    }
+
+    #[test]
+    fn test_quota() {
+        let dir = std::env::temp_dir().join(format!("usage-{}", ObjectID::random()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (options, package_id) = options(Some(dir.join("usage.json")));
+
+        let access = temp_env::with_var("ALICE_API_KEY", Some("secret"), || {
+            ClientAccess::load(&options).unwrap()
+        });
+        let check = |access: &ClientAccess, now| {
+            access
+                .check(&package_id, Some("secret"), now, None, None)
+                .map(|()| access.charge(&package_id, now, None))
+        };
+
+        // Requests are only counted once they are charged
+        for _ in 0..3 {
+            assert_eq!(
+                access.check(&package_id, Some("secret"), NOW_MS, None, None),
+                Ok(())
+            );
+        }
+
+        // The daily quota resets the next day
+        assert_eq!(check(&access, NOW_MS), Ok(()));
+        assert_eq!(check(&access, NOW_MS), Ok(()));
+        assert_eq!(check(&access, NOW_MS), Err(InternalError::QuotaExceeded));
+        assert_eq!(check(&access, NOW_MS + DAY_MS), Ok(()));
+
+        // The counters survive a restart
+        access.persist().unwrap();
+        let access = temp_env::with_var("ALICE_API_KEY", Some("secret"), || {
+            ClientAccess::load(&options).unwrap()
+        });
+        assert_eq!(
+            check(&access, NOW_MS + DAY_MS),
+            Err(InternalError::QuotaExceeded)
+        );
+
+        // The monthly quota resets the next month
+        assert_eq!(check(&access, NOW_MS + 2 * DAY_MS), Ok(()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_denied_requests_are_not_charged() {
+        let (options, package_id) = options(None);
+        let access = temp_env::with_var("ALICE_API_KEY", Some("secret"), || {
+            ClientAccess::load(&options).unwrap()
+        });
+
+        // Mirrors the server, which only charges a request once its policy check passed
+        let request = |policy_granted: bool| {
+            access.check(&package_id, Some("secret"), NOW_MS, None, None)?;
+            if !policy_granted {
+                return Err(InternalError::NoAccess);
+            }
+            access.charge(&package_id, NOW_MS, None);
+            Ok(())
+        };
+
+        // Denied requests do not use up the daily quota of two requests
+        for _ in 0..5 {
+            assert_eq!(request(false), Err(InternalError::NoAccess));
+        }
+        assert_eq!(request(true), Ok(()));
+        assert_eq!(request(false), Err(InternalError::NoAccess));
+        assert_eq!(request(true), Ok(()));
+        assert_eq!(request(false), Err(InternalError::QuotaExceeded));
+        assert_eq!(request(true), Err(InternalError::QuotaExceeded));
+    }
+}
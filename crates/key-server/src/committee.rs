@@ -0,0 +1,407 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A key server object backed by a committee of key servers, see [crypto::threshold].
+//!
+//! Each member holds a share of the master key and runs in [ServerMode::CommitteeMember] mode, in
+//! which it serves partial keys on a separate port to requests authenticated with the committee
+//! token. Members serve partial keys for any id without checking any policy, so anyone holding the
+//! committee token can get partial keys from all members and thus effectively holds the master key.
+//! The coordinator runs in [ServerMode::Committee] mode: it checks requests as in Open mode,
+//! requests partial keys from the members, verifies them using the public key shares of the members
+//! and combines `threshold` of them. The partial keys are encrypted under the ElGamal key of the
+//! user, so no process holds the full master key and the coordinator does not learn the user
+//! secret keys it returns to users.
+//!
+//! [ServerMode::CommitteeMember]: crate::key_server_options::ServerMode::CommitteeMember
+//! [ServerMode::Committee]: crate::key_server_options::ServerMode::Committee
+
+use crate::config_reload::KeyState;
+use crate::errors::InternalError;
+use crate::key_server_options::CommitteeMemberConfig;
+use crate::master_keys::MasterKeys;
+use crate::types::{ElGamalPublicKey, ElgamalEncryption, IbePublicKey, MasterKeyPOP};
+use crate::utils::constant_time_eq;
+use crate::{DefaultEncoding, KeyId, MAX_BATCH_SIZE};
+use anyhow::{anyhow, Context};
+use arc_swap::ArcSwap;
+use axum::extract::State;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use crypto::threshold::{
+    combine, combine_encrypted, interpolate, partial_extract_encrypted,
+    partial_proof_of_possession, verify_encrypted_partial_user_secret_key,
+    verify_partial_proof_of_possession, EncryptedPartialUserSecretKey, MasterKeyShare, Partial,
+};
+use fastcrypto::encoding::Encoding;
+use fastcrypto::groups::bls12381::G2_ELEMENT_BYTE_LENGTH;
+use fastcrypto::serde_helpers::ToFromByteArray;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use rand::thread_rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use sui_types::base_types::ObjectID;
+use tokio::net::TcpListener;
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+const PARTIAL_KEYS_PATH: &str = "/v1/partial_keys";
+const PARTIAL_POP_PATH: &str = "/v1/partial_pop";
+
+/// The timeout of requests to committee members.
+const MEMBER_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The minimum length in bytes of the committee token.
+const MIN_TOKEN_LENGTH: usize = 32;
+
+/// Read the committee token from the given environment variable. Anyone holding the token can get
+/// partial keys for any id from the members without any policy check, so it must be long enough
+/// not to be guessed.
+pub(crate) fn load_token(token_env_var: &str) -> anyhow::Result<String> {
+    let token = std::env::var(token_env_var)
+        .map_err(|_| anyhow!("Committee token {} must be set", token_env_var))?;
+    if token.len() < MIN_TOKEN_LENGTH {
+        return Err(anyhow!(
+            "Committee token {} must have at least {} bytes",
+            token_env_var,
+            MIN_TOKEN_LENGTH
+        ));
+    }
+    Ok(token)
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartialKeysRequest {
+    /// At most [MAX_BATCH_SIZE] ids.
+    ids: Vec<KeyId>,
+    /// The ElGamal key of the user the partial keys are encrypted under.
+    enc_key: ElGamalPublicKey,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartialKeysResponse {
+    partial_keys: Vec<EncryptedPartialUserSecretKey>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartialPopRequest {
+    // The public key of the committee
+    public_key: IbePublicKey,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartialPopResponse {
+    partial_pop: Partial<MasterKeyPOP>,
+}
+
+struct Member {
+    url: String,
+    public_key_share: Partial<IbePublicKey>,
+}
+
+/// The committee of a key server object as seen by the coordinator.
+pub(crate) struct Committee {
+    pub key_server_object_id: ObjectID,
+    pub threshold: u16,
+    /// The public key of the committee, derived from the public key shares of the members.
+    pub public_key: IbePublicKey,
+    members: Vec<Member>,
+    token: String,
+    client: reqwest::Client,
+    /// The proof-of-possession is computed by the members on the first request.
+    pop: OnceCell<MasterKeyPOP>,
+}
+
+impl Committee {
+    /// Create a committee from its configuration. Fails if the public key shares of the members are
+    /// inconsistent.
+    pub fn new(
+        key_server_object_id: ObjectID,
+        threshold: u16,
+        members: &[CommitteeMemberConfig],
+        token_env_var: &str,
+    ) -> anyhow::Result<Self> {
+        let token = load_token(token_env_var)?;
+        let members = members
+            .iter()
+            .map(|config| {
+                let bytes: [u8; G2_ELEMENT_BYTE_LENGTH] =
+                    DefaultEncoding::decode(&config.public_key_share)
+                        .ok()
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .ok_or_else(|| {
+                            anyhow!("Invalid public key share of member {}", config.index)
+                        })?;
+                Ok(Member {
+                    url: config.url.trim_end_matches('/').to_string(),
+                    public_key_share: Partial {
+                        index: config.index,
+                        value: IbePublicKey::from_byte_array(&bytes).map_err(|_| {
+                            anyhow!("Invalid public key share of member {}", config.index)
+                        })?,
+                    },
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if threshold == 0 || threshold as usize > members.len() {
+            return Err(anyhow!(
+                "The threshold of a committee must be between 1 and the number of members"
+            ));
+        }
+
+        // All public key shares must be evaluations of the same polynomial.
+        let shares = members
+            .iter()
+            .map(|m| m.public_key_share)
+            .collect::<Vec<_>>();
+        let (first, others) = shares.split_at(threshold as usize);
+        let public_key =
+            combine(first, threshold).map_err(|_| anyhow!("Invalid committee configuration"))?;
+        for share in others {
+            if interpolate(first, share.index).ok() != Some(share.value) {
+                return Err(anyhow!(
+                    "The public key share of member {} does not match the other members",
+                    share.index
+                ));
+            }
+        }
+
+        Ok(Self {
+            key_server_object_id,
+            threshold,
+            public_key,
+            members,
+            token,
+            client: reqwest::Client::builder()
+                .timeout(MEMBER_REQUEST_TIMEOUT)
+                .build()
+                .context("Cannot create HTTP client")?,
+            pop: OnceCell::new(),
+        })
+    }
+
+    /// Compute the user secret keys for the given ids using the partial keys of the members,
+    /// encrypted under the given ElGamal key.
+    pub async fn extract(
+        &self,
+        ids: &[KeyId],
+        enc_key: &ElGamalPublicKey,
+    ) -> Result<Vec<ElgamalEncryption>, InternalError> {
+        let mut keys = Vec::with_capacity(ids.len());
+        // Members reject requests with more than MAX_BATCH_SIZE ids
+        for ids in ids.chunks(MAX_BATCH_SIZE) {
+            let request = PartialKeysRequest {
+                ids: ids.to_vec(),
+                enc_key: enc_key.clone(),
+            };
+            let responses = self
+                .request_members(
+                    PARTIAL_KEYS_PATH,
+                    &request,
+                    |member, response: PartialKeysResponse| {
+                        (response.partial_keys.len() == ids.len()
+                            && response.partial_keys.iter().zip(ids).all(|(partial, id)| {
+                                verify_encrypted_partial_user_secret_key(
+                                    partial,
+                                    id,
+                                    enc_key,
+                                    &member.public_key_share,
+                                )
+                                .is_ok()
+                            }))
+                        .then_some(response.partial_keys)
+                    },
+                )
+                .await?;
+            for i in 0..ids.len() {
+                keys.push(
+                    combine_encrypted(
+                        &responses
+                            .iter()
+                            .map(|partials| &partials[i])
+                            .collect::<Vec<_>>(),
+                        self.threshold,
+                    )
+                    .map_err(|_| InternalError::Failure)?,
+                );
+            }
+        }
+        Ok(keys)
+    }
+
+    /// The proof-of-possession of the master key of the committee.
+    pub async fn proof_of_possession(&self) -> Result<MasterKeyPOP, InternalError> {
+        self.pop
+            .get_or_try_init(|| async {
+                let message = self.key_server_object_id.into_bytes();
+                let request = PartialPopRequest {
+                    public_key: self.public_key,
+                };
+                let partials = self
+                    .request_members(
+                        PARTIAL_POP_PATH,
+                        &request,
+                        |member, response: PartialPopResponse| {
+                            verify_partial_proof_of_possession(
+                                &response.partial_pop,
+                                &self.public_key,
+                                &message,
+                                &member.public_key_share,
+                            )
+                            .is_ok()
+                            .then_some(response.partial_pop)
+                        },
+                    )
+                    .await?;
+                combine(&partials, self.threshold).map_err(|_| InternalError::Failure)
+            })
+            .await
+            .copied()
+    }
+
+    /// Send a request to all members concurrently and return the first `threshold` responses that
+    /// are accepted by `validate`. Fails if fewer members respond with valid responses.
+    async fn request_members<Req: Serialize, Res: DeserializeOwned, T>(
+        &self,
+        path: &str,
+        request: &Req,
+        validate: impl Fn(&Member, Res) -> Option<T>,
+    ) -> Result<Vec<T>, InternalError> {
+        let mut responses = self
+            .members
+            .iter()
+            .map(|member| async move {
+                let response = self
+                    .client
+                    .post(format!("{}{}", member.url, path))
+                    .bearer_auth(&self.token)
+                    .json(request)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                let response = match response {
+                    Ok(response) => response.json::<Res>().await,
+                    Err(e) => Err(e),
+                };
+                (member, response)
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut valid = Vec::with_capacity(self.threshold as usize);
+        while let Some((member, response)) = responses.next().await {
+            match response.map(|response| validate(member, response)) {
+                Ok(Some(result)) => valid.push(result),
+                Ok(None) => warn!("Invalid response from committee member {}", member.url),
+                Err(e) => warn!("Request to committee member {} failed: {}", member.url, e),
+            }
+            if valid.len() == self.threshold as usize {
+                return Ok(valid);
+            }
+        }
+        warn!(
+            "Only {} of the {} required committee members responded",
+            valid.len(),
+            self.threshold
+        );
+        Err(InternalError::Failure)
+    }
+}
+
+/// Start serving the routes of a committee member on the given port, separately from the public
+/// API. Fails if the port is not available.
+pub(crate) async fn start_committee_member_server(
+    host_port: u16,
+    key_state: Arc<ArcSwap<KeyState>>,
+) -> anyhow::Result<JoinHandle<anyhow::Result<()>>> {
+    let listener = TcpListener::bind(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        host_port,
+    ))
+    .await
+    .with_context(|| format!("Cannot bind committee member API to port {}", host_port))?;
+
+    info!("Serving committee member API on port {}", host_port);
+    let router = committee_member_router(key_state);
+    Ok(tokio::spawn(async move {
+        axum::serve(listener, router)
+            .await
+            .context("Committee member API stopped")
+    }))
+}
+
+/// The routes served by committee members. Requests are rejected unless the server is a member.
+pub(crate) fn committee_member_router(key_state: Arc<ArcSwap<KeyState>>) -> Router {
+    Router::new()
+        .route(PARTIAL_KEYS_PATH, post(handle_partial_keys))
+        .route(PARTIAL_POP_PATH, post(handle_partial_pop))
+        .with_state(key_state)
+}
+
+/// The share of this member and its key server object if the request is authenticated.
+fn authorized_share(
+    key_state: &KeyState,
+    headers: &HeaderMap,
+) -> Result<(ObjectID, MasterKeyShare), StatusCode> {
+    let MasterKeys::CommitteeMember {
+        key_server_object_id,
+        share,
+        token,
+    } = &key_state.master_keys
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let authorized = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|t| constant_time_eq(t, token));
+    if !authorized {
+        warn!("Unauthorized request for partial keys");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok((*key_server_object_id, share.clone()))
+}
+
+async fn handle_partial_keys(
+    State(key_state): State<Arc<ArcSwap<KeyState>>>,
+    headers: HeaderMap,
+    Json(request): Json<PartialKeysRequest>,
+) -> Result<Json<PartialKeysResponse>, StatusCode> {
+    let (_, share) = authorized_share(&key_state.load(), &headers)?;
+    if request.ids.len() > MAX_BATCH_SIZE {
+        debug!("Too many ids requested: {}", request.ids.len());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    debug!("Computing partial keys for ids: {:?}", request.ids);
+    let mut rng = thread_rng();
+    Ok(Json(PartialKeysResponse {
+        partial_keys: request
+            .ids
+            .iter()
+            .map(|id| partial_extract_encrypted(&mut rng, &share, id, &request.enc_key))
+            .collect(),
+    }))
+}
+
+async fn handle_partial_pop(
+    State(key_state): State<Arc<ArcSwap<KeyState>>>,
+    headers: HeaderMap,
+    Json(request): Json<PartialPopRequest>,
+) -> Result<Json<PartialPopResponse>, StatusCode> {
+    let (key_server_object_id, share) = authorized_share(&key_state.load(), &headers)?;
+    Ok(Json(PartialPopResponse {
+        partial_pop: partial_proof_of_possession(
+            &share,
+            &request.public_key,
+            &key_server_object_id.into_bytes(),
+        ),
+    }))
+}
@@ -0,0 +1,357 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hot reload of the client configurations without restarting the key server.
+//!
+//! The keys and clients that are derived from [KeyServerOptions::server_mode] are kept in a
+//! [KeyState] which is swapped atomically once a new configuration has been validated. Requests
+//! that are in flight keep using the state they started with. Invalid configurations are rejected
+//! and the current state is kept.
+
+use crate::client_access::ClientAccess;
+use crate::key_server_options::{KeyServerOptions, ServerMode};
+use crate::master_keys::{MasterKeys, VersionedKeys};
+use crate::metrics::Metrics;
+use crate::types::MasterKeyPOP;
+use crate::Server;
+use anyhow::{anyhow, Context};
+use crypto::ibe::create_proof_of_possession;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use sui_types::base_types::ObjectID;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// The interval at which the configuration file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The keys and clients of the key server that can be reloaded.
+pub(crate) struct KeyState {
+    /// The server mode these keys and clients were loaded from.
+    pub server_mode: ServerMode,
+    pub master_keys: MasterKeys,
+    /// The proofs-of-possession of the key server objects by key version.
+    pub key_server_oid_to_pop: HashMap<ObjectID, HashMap<u32, MasterKeyPOP>>,
+    pub client_access: Arc<ClientAccess>,
+}
+
+impl KeyState {
+    /// Load the keys and clients of a validated configuration. If `previous` is given, its usage
+    /// counters are kept.
+    pub fn load(options: &KeyServerOptions, previous: Option<&KeyState>) -> anyhow::Result<Self> {
+        let master_keys = MasterKeys::load(options)?;
+        // Committees compute their proof-of-possession on the first request, see [crate::committee].
+        let key_server_oid_to_pop = options
+            .get_supported_key_server_object_ids()
+            .into_iter()
+            .filter(|_| !matches!(master_keys, MasterKeys::Committee { .. }))
+            .map(|ks_oid| {
+                let pops = master_keys
+                    .get_key_for_key_server(&ks_oid)
+                    .expect("checked already")
+                    .keys
+                    .iter()
+                    .map(|(version, key)| {
+                        (
+                            *version,
+                            create_proof_of_possession(key, &ks_oid.into_bytes()),
+                        )
+                    })
+                    .collect();
+                (ks_oid, pops)
+            })
+            .collect();
+        let client_access = Arc::new(match previous {
+            Some(previous) => previous.client_access.reload(options)?,
+            None => ClientAccess::load(options)?,
+        });
+        Ok(Self {
+            server_mode: options.server_mode.clone(),
+            master_keys,
+            key_server_oid_to_pop,
+            client_access,
+        })
+    }
+}
+
+/// Read a configuration file.
+pub(crate) fn read_config(path: &Path) -> anyhow::Result<KeyServerOptions> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Cannot open configuration file {}", path.display()))?;
+    serde_yaml::from_reader(file)
+        .with_context(|| format!("Cannot parse configuration file {}", path.display()))
+}
+
+/// Validate a new configuration and load its keys and clients.
+///
+/// Key server objects and packages must keep their master keys, so a configuration that changes a
+/// key version of one that is served by the current state is rejected. Keys can be rotated by adding
+/// a new version. Likewise, committees must keep their public key and members their share. Only
+/// the server mode is reloaded; changes to other options are ignored until the server is restarted.
+fn load_new_key_state(
+    current_options: &KeyServerOptions,
+    current: &KeyState,
+    new_options: &KeyServerOptions,
+) -> anyhow::Result<KeyState> {
+    new_options.validate()?;
+    let new = KeyState::load(new_options, Some(current))?;
+
+    for ks_oid in current.key_server_oid_to_pop.keys() {
+        let (Ok(current_keys), Ok(new_keys)) = (
+            current.master_keys.get_key_for_key_server(ks_oid),
+            new.master_keys.get_key_for_key_server(ks_oid),
+        ) else {
+            continue;
+        };
+        if let Some(version) = changed_version(current_keys, new_keys) {
+            return Err(anyhow!(
+                "The master key of key server object {} with version {} cannot be changed",
+                ks_oid,
+                version
+            ));
+        }
+    }
+    if let ServerMode::Permissioned { client_configs } = &current_options.server_mode {
+        for pkg_id in client_configs.iter().flat_map(|c| &c.package_ids) {
+            let (Ok(current_key), Ok(new_key)) = (
+                current.master_keys.get_key_for_package(pkg_id),
+                new.master_keys.get_key_for_package(pkg_id),
+            ) else {
+                continue;
+            };
+            if let Some(version) = changed_version(current_key, new_key) {
+                return Err(anyhow!(
+                    "The master key of package {} with version {} cannot be changed",
+                    pkg_id,
+                    version
+                ));
+            }
+        }
+    }
+
+    match (&current.master_keys, &new.master_keys) {
+        (
+            MasterKeys::Committee { committee },
+            MasterKeys::Committee {
+                committee: new_committee,
+            },
+        ) if committee.key_server_object_id == new_committee.key_server_object_id
+            && committee.public_key != new_committee.public_key =>
+        {
+            return Err(anyhow!(
+                "The public key of the committee of key server object {} cannot be changed",
+                committee.key_server_object_id
+            ));
+        }
+        (
+            MasterKeys::CommitteeMember {
+                key_server_object_id,
+                share,
+                ..
+            },
+            MasterKeys::CommitteeMember {
+                key_server_object_id: new_key_server_object_id,
+                share: new_share,
+                ..
+            },
+        ) if key_server_object_id == new_key_server_object_id && share != new_share => {
+            return Err(anyhow!(
+                "The share of the master key of key server object {} cannot be changed",
+                key_server_object_id
+            ));
+        }
+        _ => {}
+    }
+
+    let mut expected_options = new_options.clone();
+    expected_options.server_mode = current_options.server_mode.clone();
+    if serde_json::to_value(&expected_options)? != serde_json::to_value(current_options)? {
+        warn!("Only the server mode is reloaded, other changes require a restart");
+    }
+    Ok(new)
+}
+
+/// Returns a version that has different keys in `current` and `new`, if any. Versions that are only
+/// in one of them are ignored.
+fn changed_version(current: &VersionedKeys, new: &VersionedKeys) -> Option<u32> {
+    current
+        .keys
+        .iter()
+        .find(|(version, key)| new.get(**version).is_some_and(|new_key| new_key != *key))
+        .map(|(version, _)| *version)
+}
+
+impl Server {
+    /// Reload the configuration at `path` and swap the keys and clients of the server if it is
+    /// valid. Reading the file and loading the keys is blocking, so it runs on a blocking thread.
+    pub(crate) async fn reload_config(self: &Arc<Self>, path: &Path) -> anyhow::Result<()> {
+        let server = self.clone();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || server.reload_config_blocking(&path)).await?
+    }
+
+    fn reload_config_blocking(&self, path: &Path) -> anyhow::Result<()> {
+        let new_options = read_config(path)?;
+        let new = load_new_key_state(&self.options, &self.key_state.load(), &new_options)?;
+        self.key_state.store(Arc::new(new));
+        info!("Reloaded configuration from {}", path.display());
+        Ok(())
+    }
+}
+
+/// Spawn a task that reloads the configuration when the file at `path` changes or the process
+/// receives SIGHUP.
+pub(crate) fn spawn_config_reloader(
+    server: Arc<Server>,
+    path: PathBuf,
+    metrics: Arc<Metrics>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut sighup = signal(SignalKind::hangup()).expect("SIGHUP handler can be installed");
+        let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut last_modified = modified(&path);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let modified = modified(&path);
+                    if modified == last_modified {
+                        continue;
+                    }
+                    last_modified = modified;
+                    info!("Configuration file {} changed", path.display());
+                }
+                _ = sighup.recv() => {
+                    info!("Received SIGHUP");
+                }
+            }
+            let result = server.reload_config(&path).await;
+            metrics.observe_config_reload(result.is_ok());
+            if let Err(error) = result {
+                error!(
+                    ?error,
+                    "Rejected new configuration, keeping the current one"
+                );
+            }
+        }
+    })
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::InternalError;
+    use crate::key_server_options::{ClientConfig, ClientKeyType, DeprecatedClientKey, Quota};
+    use crate::types::Network;
+    use crate::DefaultEncoding;
+    use fastcrypto::encoding::Encoding;
+
+    fn client(name: &str, derivation_index: u64) -> ClientConfig {
+        ClientConfig {
+            name: name.to_string(),
+            client_master_key: ClientKeyType::Derived { derivation_index },
+            key_server_object_id: ObjectID::random(),
+            package_ids: vec![ObjectID::random()],
+            api_key_env_var: None,
+            quota: Some(Quota {
+                daily: Some(1),
+                monthly: None,
+            }),
+            key_version: 0,
+            deprecated_keys: vec![],
+        }
+    }
+
+    fn options(client_configs: Vec<ClientConfig>) -> KeyServerOptions {
+        let mut options =
+            KeyServerOptions::new_open_server_with_default_values(Network::Testnet, ObjectID::ZERO);
+        options.server_mode = ServerMode::Permissioned { client_configs };
+        options
+    }
+
+    #[test]
+    fn test_reload_key_state() {
+        let seed = DefaultEncoding::encode([1u8; 32]);
+        temp_env::with_var("MASTER_SEED", Some(seed), || {
+            let alice = client("alice", 0);
+            let alice_pkg = alice.package_ids[0];
+            let current_options = options(vec![alice.clone()]);
+            let current = KeyState::load(&current_options, None).unwrap();
+            assert_eq!(
+                current.client_access.check(&alice_pkg, None, 0, None, None),
+                Ok(())
+            );
+            current.client_access.charge(&alice_pkg, 0, None);
+
+            // Adding a client keeps the existing keys and usage counters
+            let bob = client("bob", 1);
+            let bob_pkg = bob.package_ids[0];
+            let new_options = options(vec![alice.clone(), bob.clone()]);
+            let new = load_new_key_state(&current_options, &current, &new_options).unwrap();
+            assert!(new.master_keys.has_key_for_package(&bob_pkg).is_ok());
+            assert_eq!(
+                new.master_keys.get_key_for_package(&alice_pkg).unwrap(),
+                current.master_keys.get_key_for_package(&alice_pkg).unwrap()
+            );
+            assert_eq!(new.key_server_oid_to_pop.len(), 2);
+            assert_eq!(
+                new.client_access.check(&alice_pkg, None, 0, None, None),
+                Err(InternalError::QuotaExceeded)
+            );
+
+            // Invalid configurations are rejected
+            let duplicate_options = options(vec![alice.clone(), alice.clone()]);
+            assert!(load_new_key_state(&current_options, &current, &duplicate_options).is_err());
+
+            // The key of an existing key server object cannot change
+            let mut changed_alice = alice.clone();
+            changed_alice.client_master_key = ClientKeyType::Derived {
+                derivation_index: 1,
+            };
+            let changed_options = options(vec![changed_alice, client("bob", 0)]);
+            assert!(load_new_key_state(&current_options, &current, &changed_options).is_err());
+
+            // Neither can the key of an existing package
+            let mut moved_bob = bob.clone();
+            moved_bob.package_ids.push(alice_pkg);
+            let moved_options = options(vec![client("alice", 0), moved_bob]);
+            assert!(load_new_key_state(&current_options, &current, &moved_options).is_err());
+
+            // But keys can be rotated by adding a version
+            let mut rotated_alice = alice.clone();
+            rotated_alice.client_master_key = ClientKeyType::Derived {
+                derivation_index: 2,
+            };
+            rotated_alice.key_version = 1;
+            rotated_alice.deprecated_keys = vec![DeprecatedClientKey {
+                version: 0,
+                client_master_key: alice.client_master_key.clone(),
+            }];
+            let rotated_options = options(vec![rotated_alice, bob.clone()]);
+            let rotated = load_new_key_state(&current_options, &current, &rotated_options).unwrap();
+            let rotated_keys = rotated.master_keys.get_key_for_package(&alice_pkg).unwrap();
+            assert_eq!(rotated_keys.current_version, 1);
+            assert_eq!(
+                rotated_keys.get(0),
+                Some(
+                    current
+                        .master_keys
+                        .get_key_for_package(&alice_pkg)
+                        .unwrap()
+                        .current()
+                )
+            );
+            assert_eq!(
+                rotated.key_server_oid_to_pop[&alice.key_server_object_id].len(),
+                2
+            );
+        });
+    }
+}
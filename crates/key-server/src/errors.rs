@@ -1,16 +1,19 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::policy::MoveAbort;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub enum InternalError {
     InvalidPTB(String),
     InvalidPackage,
     NoAccess,
+    // Like NoAccess but with details about the abort, only used if enabled in the config
+    NoAccessWithAbort(MoveAbort),
     InvalidSignature,
     InvalidSessionSignature,
     InvalidCertificate,
@@ -21,25 +24,64 @@ pub enum InternalError {
     InvalidMVRName,
     InvalidServiceId,
     UnsupportedPackageId,
+    InvalidBatch(String),
+    InsufficientGas,
+    PolicyExecutionError(String),
+    RateLimited,
+    InvalidApiKey,
+    QuotaExceeded,
+    ClientDisabled,
+    UnknownKeyVersion,
     Failure, // Internal error, try again later
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     error: InternalError,
     message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    abort: Option<MoveAbort>,
+}
+
+impl From<InternalError> for ErrorResponse {
+    fn from(error: InternalError) -> Self {
+        let message = error.status_and_message().1;
+        match error {
+            // Reported as NoAccess such that clients do not need to handle another error type
+            InternalError::NoAccessWithAbort(abort) => ErrorResponse {
+                error: InternalError::NoAccess,
+                message,
+                abort: Some(abort),
+            },
+            error => ErrorResponse {
+                error,
+                message,
+                abort: None,
+            },
+        }
+    }
 }
 
 impl IntoResponse for InternalError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            InternalError::InvalidPTB(ref inner) => {
+        let status = self.status_and_message().0;
+        (status, Json(ErrorResponse::from(self))).into_response()
+    }
+}
+
+impl InternalError {
+    fn status_and_message(&self) -> (StatusCode, String) {
+        match self {
+            InternalError::InvalidPTB(inner) => {
                 (StatusCode::FORBIDDEN, format!("Invalid PTB: {}", inner))
             }
             InternalError::InvalidPackage => {
                 (StatusCode::FORBIDDEN, "Invalid package ID".to_string())
             }
             InternalError::NoAccess => (StatusCode::FORBIDDEN, "Access denied".to_string()),
+            InternalError::NoAccessWithAbort(abort) => {
+                (StatusCode::FORBIDDEN, format!("Access denied: {}", abort))
+            }
             InternalError::InvalidCertificate => (
                 StatusCode::FORBIDDEN,
                 "Invalid certificate time or ttl".to_string(),
@@ -54,7 +96,7 @@ impl IntoResponse for InternalError {
                 StatusCode::UPGRADE_REQUIRED,
                 "Deprecated SDK version".to_string(),
             ),
-            InternalError::MissingRequiredHeader(ref inner) => (
+            InternalError::MissingRequiredHeader(inner) => (
                 StatusCode::BAD_REQUEST,
                 format!("Missing required header: {}", inner).to_string(),
             ),
@@ -76,27 +118,49 @@ impl IntoResponse for InternalError {
                 StatusCode::BAD_REQUEST,
                 "Unsupported package ID".to_string(),
             ),
+            InternalError::InvalidBatch(inner) => {
+                (StatusCode::BAD_REQUEST, format!("Invalid batch: {}", inner))
+            }
+            InternalError::InsufficientGas => (
+                StatusCode::FORBIDDEN,
+                "Insufficient gas for evaluating the policy".to_string(),
+            ),
+            InternalError::PolicyExecutionError(inner) => (
+                StatusCode::FORBIDDEN,
+                format!("Policy execution failed: {}", inner),
+            ),
+            InternalError::RateLimited => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many requests, try again later".to_string(),
+            ),
+            InternalError::InvalidApiKey => (
+                StatusCode::UNAUTHORIZED,
+                "Missing or invalid API key".to_string(),
+            ),
+            InternalError::QuotaExceeded => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Request quota exceeded".to_string(),
+            ),
+            InternalError::ClientDisabled => (
+                StatusCode::FORBIDDEN,
+                "The client of this package is disabled".to_string(),
+            ),
+            InternalError::UnknownKeyVersion => (
+                StatusCode::BAD_REQUEST,
+                "The key server has no master key with the requested version".to_string(),
+            ),
             InternalError::Failure => (
                 StatusCode::SERVICE_UNAVAILABLE,
                 "Internal server error, please try again later".to_string(),
             ),
-        };
-
-        let error_response = ErrorResponse {
-            error: self,
-            message,
-        };
-
-        (status, Json(error_response)).into_response()
+        }
     }
-}
 
-impl InternalError {
     pub fn as_str(&self) -> &'static str {
         match self {
             InternalError::InvalidPTB(_) => "InvalidPTB",
             InternalError::InvalidPackage => "InvalidPackage",
-            InternalError::NoAccess => "NoAccess",
+            InternalError::NoAccess | InternalError::NoAccessWithAbort(_) => "NoAccess",
             InternalError::InvalidCertificate => "InvalidCertificate",
             InternalError::InvalidSignature => "InvalidSignature",
             InternalError::InvalidSessionSignature => "InvalidSessionSignature",
@@ -107,6 +171,14 @@ impl InternalError {
             InternalError::InvalidMVRName => "InvalidMVRName",
             InternalError::InvalidServiceId => "InvalidServiceId",
             InternalError::UnsupportedPackageId => "UnsupportedPackageId",
+            InternalError::InvalidBatch(_) => "InvalidBatch",
+            InternalError::InsufficientGas => "InsufficientGas",
+            InternalError::PolicyExecutionError(_) => "PolicyExecutionError",
+            InternalError::RateLimited => "RateLimited",
+            InternalError::InvalidApiKey => "InvalidApiKey",
+            InternalError::QuotaExceeded => "QuotaExceeded",
+            InternalError::ClientDisabled => "ClientDisabled",
+            InternalError::UnknownKeyVersion => "UnknownKeyVersion",
             InternalError::Failure => "Failure",
         }
     }
@@ -8,6 +8,7 @@ use crate::sui_rpc_client::SuiRpcClient;
 use crate::{mvr_forward_resolution, Timestamp};
 use moka::sync::Cache;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use sui_sdk::error::SuiRpcResult;
 use sui_sdk::rpc_types::{CheckpointId, SuiData, SuiObjectDataOptions};
 use sui_types::base_types::ObjectID;
@@ -100,10 +101,32 @@ pub(crate) fn get_mvr_cache(mvr_name: &str) -> Option<ObjectID> {
     MVR_CACHE.get(&mvr_name.to_string())
 }
 
-/// Returns the timestamp for the latest checkpoint.
-pub(crate) async fn get_latest_checkpoint_timestamp(
+/// The number of entries in the caches of first package IDs and MVR names.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct CacheStats {
+    pub packages: u64,
+    pub mvr_names: u64,
+}
+
+pub(crate) fn cache_stats() -> CacheStats {
+    CACHE.run_pending_tasks();
+    MVR_CACHE.run_pending_tasks();
+    CacheStats {
+        packages: CACHE.entry_count(),
+        mvr_names: MVR_CACHE.entry_count(),
+    }
+}
+
+/// Remove all entries from the caches of first package IDs and MVR names.
+pub(crate) fn flush_caches() {
+    CACHE.invalidate_all();
+    MVR_CACHE.invalidate_all();
+}
+
+/// Returns the sequence number and timestamp of the latest checkpoint.
+pub(crate) async fn get_latest_checkpoint(
     sui_rpc_client: SuiRpcClient,
-) -> SuiRpcResult<Timestamp> {
+) -> SuiRpcResult<(u64, Timestamp)> {
     let latest_checkpoint_sequence_number = sui_rpc_client
         .get_latest_checkpoint_sequence_number()
         .await?;
@@ -112,7 +135,7 @@ pub(crate) async fn get_latest_checkpoint_timestamp(
             latest_checkpoint_sequence_number,
         ))
         .await?;
-    Ok(checkpoint.timestamp_ms)
+    Ok((latest_checkpoint_sequence_number, checkpoint.timestamp_ms))
 }
 
 pub(crate) async fn get_reference_gas_price(sui_rpc_client: SuiRpcClient) -> SuiRpcResult<u64> {
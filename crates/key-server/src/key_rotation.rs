@@ -0,0 +1,212 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rotation of master keys, see [VersionedKeys].
+//!
+//! While a key is rotated, a key server object has a current and one or more deprecated master
+//! keys. Ciphertexts do not record the key they were encrypted under, so clients may request a
+//! version. Otherwise, the key matching the public key registered on-chain is used, since that is
+//! the key clients encrypt under. The public keys on-chain are fetched periodically for key server
+//! objects with more than one key. Until the public key on-chain is known, or if it matches none of
+//! the keys, the oldest key is used.
+
+use crate::errors::InternalError;
+use crate::master_keys::VersionedKeys;
+use crate::metrics::Metrics;
+use crate::sui_rpc_client::SuiRpcClient;
+use crate::types::{IbeMasterKey, IbePublicKey};
+use crate::Server;
+use anyhow::anyhow;
+use crypto::key_server::{parse_key_server_v1, KEY_SERVER_V1};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use sui_sdk::rpc_types::SuiObjectDataOptions;
+use sui_types::base_types::ObjectID;
+use sui_types::dynamic_field::DynamicFieldName;
+use sui_types::TypeTag;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// The interval at which the public keys on-chain are fetched.
+const ONCHAIN_KEY_UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The public keys of the key server objects on-chain that have been fetched so far.
+#[derive(Clone, Default)]
+pub(crate) struct OnchainPublicKeys(Arc<RwLock<HashMap<ObjectID, IbePublicKey>>>);
+
+impl OnchainPublicKeys {
+    pub fn get(&self, key_server_object_id: &ObjectID) -> Option<IbePublicKey> {
+        self.0.read().unwrap().get(key_server_object_id).copied()
+    }
+
+    fn set(&self, key_server_object_id: ObjectID, public_key: IbePublicKey) {
+        self.0
+            .write()
+            .unwrap()
+            .insert(key_server_object_id, public_key);
+    }
+}
+
+impl VersionedKeys {
+    /// Select the key for a request. If no version is requested, the key matching the given public
+    /// key on-chain is used, or the oldest key if the public key on-chain is unknown.
+    pub(crate) fn select(
+        &self,
+        requested_version: Option<u32>,
+        onchain_public_key: Option<&IbePublicKey>,
+    ) -> Result<(u32, &IbeMasterKey), InternalError> {
+        let version = requested_version.unwrap_or_else(|| {
+            onchain_public_key
+                .and_then(|public_key| self.version_of(public_key))
+                .unwrap_or_else(|| self.oldest_version())
+        });
+        self.get(version)
+            .map(|key| (version, key))
+            .ok_or(InternalError::UnknownKeyVersion)
+    }
+}
+
+impl Server {
+    /// Select the key for a request for the given keys, and count requests using deprecated keys.
+    pub(crate) fn select_key<'a>(
+        &self,
+        keys: &'a VersionedKeys,
+        requested_version: Option<u32>,
+        metrics: Option<&Metrics>,
+    ) -> Result<(u32, &'a IbeMasterKey), InternalError> {
+        let onchain_public_key = self.onchain_public_keys.get(&keys.key_server_object_id);
+        let (version, key) = keys.select(requested_version, onchain_public_key.as_ref())?;
+        if version != keys.current_version {
+            debug!(
+                "Using deprecated key version {} of key server object {}",
+                version, keys.key_server_object_id
+            );
+            if let Some(m) = metrics {
+                m.observe_deprecated_key_request(&keys.key_server_object_id, version);
+            }
+        }
+        Ok((version, key))
+    }
+
+    /// Fetch the public keys on-chain of all key server objects that have more than one key.
+    pub(crate) async fn update_onchain_public_keys(&self, metrics: &Metrics) {
+        let key_state = self.key_state.load();
+        for keys in key_state.master_keys.all() {
+            if !keys.is_rotating() {
+                continue;
+            }
+            let ks_oid = keys.key_server_object_id;
+            match fetch_onchain_public_key(&self.sui_rpc_client, ks_oid).await {
+                Ok(public_key) => {
+                    let version = keys.version_of(&public_key);
+                    if self.onchain_public_keys.get(&ks_oid) != Some(public_key) {
+                        match version {
+                            Some(version) => info!(
+                                "Key server object {} has key version {} on-chain",
+                                ks_oid, version
+                            ),
+                            None => warn!(
+                                "The public key of key server object {} on-chain does not match any of its keys",
+                                ks_oid
+                            ),
+                        }
+                    }
+                    metrics.observe_onchain_key_version(&ks_oid, version);
+                    self.onchain_public_keys.set(ks_oid, public_key);
+                }
+                Err(e) => debug!(
+                    "Failed to fetch the public key of key server object {}: {:?}",
+                    ks_oid, e
+                ),
+            }
+        }
+    }
+}
+
+/// Spawn a task that periodically fetches the public keys on-chain of key server objects whose
+/// keys are being rotated.
+pub(crate) fn spawn_onchain_key_updater(
+    server: Arc<Server>,
+    metrics: Arc<Metrics>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ONCHAIN_KEY_UPDATE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            server.update_onchain_public_keys(&metrics).await;
+        }
+    })
+}
+
+/// Fetch the public key registered in a key server object.
+async fn fetch_onchain_public_key(
+    sui_rpc_client: &SuiRpcClient,
+    key_server_object_id: ObjectID,
+) -> anyhow::Result<IbePublicKey> {
+    let field_id = sui_rpc_client
+        .get_dynamic_field_object(
+            key_server_object_id,
+            DynamicFieldName {
+                type_: TypeTag::U64,
+                value: json!(KEY_SERVER_V1.to_string()),
+            },
+        )
+        .await?
+        .object_id()?;
+    let response = sui_rpc_client
+        .get_object_with_options(field_id, SuiObjectDataOptions::new().with_bcs())
+        .await?;
+    let bytes = response
+        .move_object_bcs()
+        .ok_or_else(|| anyhow!("Invalid key server object {}", key_server_object_id))?;
+    let key_server = parse_key_server_v1(bytes)
+        .map_err(|_| anyhow!("Invalid key server object {}", key_server_object_id))?;
+    Ok(key_server.public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::ibe;
+    use fastcrypto::groups::GroupElement;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_select_key() {
+        let old_key = IbeMasterKey::generator();
+        let new_key = old_key + old_key;
+        let keys = VersionedKeys {
+            key_server_object_id: ObjectID::random(),
+            current_version: 1,
+            keys: BTreeMap::from([(0, old_key), (1, new_key)]),
+        };
+
+        let old_pk = ibe::public_key_from_master_key(&old_key);
+        let new_pk = ibe::public_key_from_master_key(&new_key);
+
+        // Requested versions are served if known, regardless of the public key on-chain
+        assert_eq!(keys.select(Some(0), Some(&new_pk)), Ok((0, &old_key)));
+        assert_eq!(keys.select(Some(1), Some(&old_pk)), Ok((1, &new_key)));
+        assert_eq!(
+            keys.select(Some(2), None),
+            Err(InternalError::UnknownKeyVersion)
+        );
+
+        // Otherwise, the key registered on-chain is served
+        assert_eq!(keys.select(None, Some(&old_pk)), Ok((0, &old_key)));
+        assert_eq!(keys.select(None, Some(&new_pk)), Ok((1, &new_key)));
+
+        // Or the oldest key until the public key on-chain is known
+        let unknown_pk = ibe::public_key_from_master_key(&(new_key + old_key));
+        assert_eq!(keys.select(None, None), Ok((0, &old_key)));
+        assert_eq!(keys.select(None, Some(&unknown_pk)), Ok((0, &old_key)));
+        let keys = VersionedKeys {
+            keys: BTreeMap::from([(1, new_key)]),
+            ..keys
+        };
+        assert_eq!(keys.select(None, None), Ok((1, &new_key)));
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::committee::load_token;
 use crate::metrics_push::MetricsPushConfig;
 use crate::time::from_mins;
 use crate::types::Network;
@@ -8,6 +9,8 @@ use anyhow::{anyhow, Result};
 use duration_str::deserialize_duration;
 use semver::VersionReq;
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
 use std::time::Duration;
 use sui_types::base_types::ObjectID;
 use tracing::info;
@@ -19,7 +22,12 @@ pub enum ClientKeyType {
         derivation_index: u64, // Must be unique
     },
     Imported {
-        env_var: String, // Expected a BLS key
+        /// The environment variable holding the key. Shorthand for an `!Env` key source.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        env_var: Option<String>, // Expected a BLS key
+        /// Where to load the key from if it is not set in an environment variable.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key_source: Option<KeySource>,
     },
     Exported {
         // Indicates that the derived master key was exported and should not be used
@@ -27,6 +35,50 @@ pub enum ClientKeyType {
     },
 }
 
+/// Where a master key is loaded from. Keys are expected to be hex encoded BLS keys, except for
+/// PKCS#11 objects which hold the raw 32 bytes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum KeySource {
+    /// An environment variable.
+    Env { env_var: String },
+    /// A file that must not be accessible by group or others.
+    File { path: PathBuf },
+    /// A keystore created with `seal-cli create-keystore`, unlocked by the passphrase in the given
+    /// environment variable. The file must not be accessible by group or others.
+    Keystore {
+        path: PathBuf,
+        passphrase_env_var: String,
+    },
+    /// The value of a data or extractable secret key object on a PKCS#11 token, e.g., an HSM or
+    /// SoftHSM.
+    Pkcs11 {
+        /// The PKCS#11 library of the token, e.g., `/usr/lib/softhsm/libsofthsm2.so`.
+        module: PathBuf,
+        token_label: String,
+        /// The label of the object holding the key.
+        key_label: String,
+        /// The environment variable holding the user PIN of the token.
+        pin_env_var: String,
+    },
+}
+
+/// A master key replaced by a rotation. It is still served to clients that request its version, and
+/// to clients that do not request a version while the key server object on-chain has its public
+/// key, see [crate::key_rotation].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeprecatedMasterKey {
+    pub version: u32,
+    pub key_source: KeySource,
+}
+
+/// A client master key replaced by a rotation, see [DeprecatedMasterKey].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeprecatedClientKey {
+    pub version: u32,
+    /// Either a derived or an imported key.
+    pub client_master_key: ClientKeyType,
+}
+
 /// ClientConfig for a permissioned client.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ClientConfig {
@@ -34,6 +86,28 @@ pub struct ClientConfig {
     pub client_master_key: ClientKeyType,
     pub key_server_object_id: ObjectID, // Must be unique
     pub package_ids: Vec<ObjectID>,     // first versions only
+    /// If set, requests for the packages of this client must include the API key stored in this
+    /// environment variable in the API key header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env_var: Option<String>,
+    /// Optional limits on the number of requests of this client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<Quota>,
+    /// The version of the client master key. Must be changed when the key is rotated.
+    #[serde(default)]
+    pub key_version: u32,
+    /// Previous keys of this client that are still served during a rotation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deprecated_keys: Vec<DeprecatedClientKey>,
+}
+
+/// Request quotas of a permissioned client. Periods are calendar days and months in UTC.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Quota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -42,11 +116,173 @@ pub enum ServerMode {
         // Master key is expected to be a BLS key.
         /// The object ID of the key server object.
         key_server_object_id: ObjectID,
+        /// Where to load the master key from. Defaults to the environment variable `MASTER_KEY`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        master_key_source: Option<KeySource>,
+        /// The version of the master key. Must be changed when the key is rotated.
+        #[serde(default)]
+        master_key_version: u32,
+        /// Previous master keys that are still served during a rotation.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        deprecated_master_keys: Vec<DeprecatedMasterKey>,
     },
     Permissioned {
         // Master key is expected to by 32 byte HKDF seed
         client_configs: Vec<ClientConfig>,
     },
+    /// Like Open, but the master key is split between the members of a committee, see
+    /// [crate::committee]. This server checks requests and combines the partial keys of the members.
+    Committee {
+        /// The object ID of the key server object.
+        key_server_object_id: ObjectID,
+        /// The number of members needed to compute a key.
+        threshold: u16,
+        members: Vec<CommitteeMemberConfig>,
+        /// The environment variable holding the token used to authenticate to the members. Must
+        /// have at least 32 bytes, see [ServerMode::CommitteeMember].
+        token_env_var: String,
+    },
+    /// A member of a committee holding a share of the master key. It only serves partial keys to
+    /// the server coordinating the committee.
+    CommitteeMember {
+        /// The object ID of the key server object of the committee.
+        key_server_object_id: ObjectID,
+        /// The index of the share held by this member.
+        index: u16,
+        /// Where to load the share of the master key from.
+        share_source: KeySource,
+        /// The environment variable holding the token the coordinator must present. Must have at
+        /// least 32 bytes. Members serve partial keys for any id without checking any policy, so
+        /// anyone holding the token can get the partial keys of all members and thus effectively
+        /// holds the master key. It must be kept as secret as the shares themselves.
+        token_env_var: String,
+        /// The port on which partial keys are served to the coordinator, separately from the
+        /// public API.
+        #[serde(default = "default_committee_member_host_port")]
+        host_port: u16,
+    },
+}
+
+/// A member of a committee as seen by the coordinator, see [ServerMode::Committee].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommitteeMemberConfig {
+    /// The index of the share held by the member.
+    pub index: u16,
+    /// The URL of the committee member API of the member. Must use https.
+    pub url: String,
+    /// The hex encoded public key of the share, used to verify partial keys.
+    pub public_key_share: String,
+}
+
+/// How the `seal_approve*` functions of a request are evaluated.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum PolicyEvaluation {
+    /// Dry run the PTB on the full node as a transaction with a fixed gas budget and the reference
+    /// gas price.
+    #[default]
+    DryRun,
+    /// Evaluate the PTB on the full node using dev-inspect, which does not depend on gas.
+    DevInspect,
+    /// Execute the PTB in the key server against objects fetched from the full node. Only packages
+    /// and immutable objects are cached, so policies are evaluated against the latest version of
+    /// shared and owned objects.
+    Local,
+}
+
+/// Configuration of the cache of successful policy decisions.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PolicyCacheConfig {
+    /// How long a decision is cached.
+    #[serde(
+        default = "default_policy_cache_ttl",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub ttl: Duration,
+
+    /// The maximum number of cached decisions.
+    #[serde(default = "default_policy_cache_size")]
+    pub size: u64,
+}
+
+/// A token bucket rate limit.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RateLimit {
+    /// The rate at which requests are allowed on average.
+    pub requests_per_second: f64,
+
+    /// The maximum number of requests allowed at once.
+    pub burst: u32,
+}
+
+/// Rate limits of the fetch key requests. A limit that is not set is not enforced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RateLimitConfig {
+    /// Limit per user address of the certificate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_user: Option<RateLimit>,
+
+    /// Limit per source IP address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_ip: Option<RateLimit>,
+
+    /// Limit per first package ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_package: Option<RateLimit>,
+
+    /// The header holding the source IP address of a request, e.g. `X-Forwarded-For`. Only set this
+    /// if a trusted proxy in front of the key server appends the address of its peer to the
+    /// header, since the right-most address is used. If not set, the address of the peer of the
+    /// key server is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_header: Option<String>,
+}
+
+/// Configuration of the admin API.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AdminConfig {
+    /// The address on which the admin API is served. Only reachable locally by default.
+    #[serde(default = "default_admin_host")]
+    pub host: IpAddr,
+
+    /// The port on which the admin API is served.
+    #[serde(default = "default_admin_host_port")]
+    pub host_port: u16,
+
+    /// The environment variable holding the bearer token that admin requests must present.
+    pub token_env_var: String,
+}
+
+/// Configuration of the audit log of fetch key requests, see [crate::audit_log].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditLogConfig {
+    pub sink: AuditLogSink,
+
+    /// If set, each record contains the hash of the previous record, such that removed or modified
+    /// records can be detected. Only supported for the file sink, since the chain is continued from
+    /// the last record in the file after a restart.
+    #[serde(default)]
+    pub hash_chain: bool,
+}
+
+/// Where audit records are written to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AuditLogSink {
+    /// A file that is appended to. Once it exceeds `max_size_bytes`, it is rotated to `<path>.1`,
+    /// `<path>.1` to `<path>.2` and so on, keeping at most `max_files` rotated files.
+    File {
+        path: PathBuf,
+        #[serde(default = "default_audit_log_max_size_bytes")]
+        max_size_bytes: u64,
+        #[serde(default = "default_audit_log_max_files")]
+        max_files: usize,
+    },
+    /// The standard output of the key server.
+    Stdout,
+    /// A syslog-compatible Unix datagram socket, e.g., `/dev/log`.
+    Syslog {
+        #[serde(default = "default_syslog_socket_path")]
+        socket_path: PathBuf,
+    },
 }
 
 /// Configuration for the RPC client.
@@ -140,6 +376,37 @@ pub struct KeyServerOptions {
     #[serde(default)]
     pub rpc_config: RpcConfig,
 
+    /// How the policies of requests are evaluated.
+    #[serde(default)]
+    pub policy_evaluation: PolicyEvaluation,
+
+    /// If set, the abort code, module and function are included in the error response when a
+    /// policy denies access. Off by default since it may reveal details about the policy.
+    #[serde(default)]
+    pub expose_abort_details: bool,
+
+    /// If set, successful policy decisions are cached for a short time, such that retries of the
+    /// same request are not evaluated again. Leave unset for policies that depend on time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_cache: Option<PolicyCacheConfig>,
+
+    /// Rate limits of the fetch key requests. No limits are enforced by default.
+    #[serde(default)]
+    pub rate_limits: RateLimitConfig,
+
+    /// The file in which the request counters of the client quotas are persisted. If not set,
+    /// counters are reset when the server restarts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_store_path: Option<PathBuf>,
+
+    /// If set, an authenticated admin API is served on a separate port.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<AdminConfig>,
+
+    /// If set, the decisions on fetch key requests are written to an append-only audit log.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<AuditLogConfig>,
+
     /// Optional configuration for pushing metrics to an external endpoint (e.g., seal-proxy).
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub metrics_push_config: Option<MetricsPushConfig>,
@@ -155,6 +422,9 @@ impl KeyServerOptions {
             sdk_version_requirement: default_sdk_version_requirement(),
             server_mode: ServerMode::Open {
                 key_server_object_id,
+                master_key_source: None,
+                master_key_version: 0,
+                deprecated_master_keys: vec![],
             },
             metrics_host_port: default_metrics_host_port(),
             checkpoint_update_interval: default_checkpoint_update_interval(),
@@ -162,6 +432,13 @@ impl KeyServerOptions {
             allowed_staleness: default_allowed_staleness(),
             session_key_ttl_max: default_session_key_ttl_max(),
             rpc_config: RpcConfig::default(),
+            policy_evaluation: PolicyEvaluation::default(),
+            expose_abort_details: false,
+            policy_cache: None,
+            rate_limits: RateLimitConfig::default(),
+            usage_store_path: None,
+            admin: None,
+            audit_log: None,
             metrics_push_config: None,
         }
     }
@@ -173,6 +450,9 @@ impl KeyServerOptions {
             sdk_version_requirement: default_sdk_version_requirement(),
             server_mode: ServerMode::Open {
                 key_server_object_id: ObjectID::random(),
+                master_key_source: None,
+                master_key_version: 0,
+                deprecated_master_keys: vec![],
             },
             metrics_host_port: default_metrics_host_port(),
             checkpoint_update_interval: default_checkpoint_update_interval(),
@@ -180,6 +460,13 @@ impl KeyServerOptions {
             allowed_staleness: default_allowed_staleness(),
             session_key_ttl_max: default_session_key_ttl_max(),
             rpc_config: RpcConfig::default(),
+            policy_evaluation: PolicyEvaluation::default(),
+            expose_abort_details: false,
+            policy_cache: None,
+            rate_limits: RateLimitConfig::default(),
+            usage_store_path: None,
+            admin: None,
+            audit_log: None,
             metrics_push_config: None,
         }
     }
@@ -190,11 +477,17 @@ impl KeyServerOptions {
     /// 3. Each client name must be unique.
     /// 4. Each derived key or deprecated key (in case of export) must have a unique derivation index.
     /// 5. The set of derivation indices must be incremental starting from 0.
-    /// 6. The environment variable names for clients with imported keys must be unique.
+    /// 6. Imported keys must have either an environment variable or a key source.
+    /// 7. The environment variable names for clients with imported keys must be unique.
+    /// 8. The key versions of each key server object must be unique, and deprecated keys cannot be
+    ///    exported.
+    ///
+    /// For a committee, the threshold must be between 1 and the number of members, and the share
+    /// indices must be unique and non-zero.
     pub fn validate(&self) -> Result<()> {
         info!(
             "Validating KeyServerOptions:\n{}",
-            serde_yaml::to_string(self).expect("should serialize")
+            serde_yaml::to_string(&self.redacted()).expect("should serialize")
         );
 
         if let ServerMode::Permissioned { client_configs } = &self.server_mode {
@@ -213,24 +506,66 @@ impl KeyServerOptions {
                 if !names.insert(config.name.clone()) {
                     return Err(anyhow!("Duplicate client name: {}", config.name));
                 }
-                match &config.client_master_key {
-                    ClientKeyType::Derived { derivation_index }
-                    | ClientKeyType::Exported {
-                        deprecated_derivation_index: derivation_index,
-                    } => {
-                        if !derivation_indices.insert(*derivation_index) {
-                            return Err(anyhow!(
-                                "Duplicate derivation index: {}",
-                                derivation_index
-                            ));
+                check_key_versions(
+                    config.key_version,
+                    config.deprecated_keys.iter().map(|k| k.version),
+                    &config.name,
+                )?;
+                if config
+                    .deprecated_keys
+                    .iter()
+                    .any(|k| matches!(k.client_master_key, ClientKeyType::Exported { .. }))
+                {
+                    return Err(anyhow!(
+                        "Deprecated keys cannot be exported: {}",
+                        config.name
+                    ));
+                }
+                let deprecated_keys = config.deprecated_keys.iter().map(|k| &k.client_master_key);
+                for key in std::iter::once(&config.client_master_key).chain(deprecated_keys) {
+                    match key {
+                        ClientKeyType::Derived { derivation_index }
+                        | ClientKeyType::Exported {
+                            deprecated_derivation_index: derivation_index,
+                        } => {
+                            if !derivation_indices.insert(*derivation_index) {
+                                return Err(anyhow!(
+                                    "Duplicate derivation index: {}",
+                                    derivation_index
+                                ));
+                            }
                         }
-                    }
-                    ClientKeyType::Imported { env_var } => {
-                        if !env_vars.insert(env_var.clone()) {
-                            return Err(anyhow!("Duplicate environment variable: {}", env_var));
+                        ClientKeyType::Imported {
+                            env_var,
+                            key_source,
+                        } => {
+                            let env_var = match (env_var, key_source) {
+                                (Some(env_var), None)
+                                | (None, Some(KeySource::Env { env_var })) => Some(env_var),
+                                (None, Some(_)) => None,
+                                _ => {
+                                    return Err(anyhow!(
+                                    "Imported key must have either an env_var or a key_source: {}",
+                                    config.name
+                                ))
+                                }
+                            };
+                            if let Some(env_var) = env_var {
+                                if !env_vars.insert(env_var.clone()) {
+                                    return Err(anyhow!(
+                                        "Duplicate environment variable: {}",
+                                        env_var
+                                    ));
+                                }
+                            }
                         }
                     }
                 }
+                if let Some(env_var) = &config.api_key_env_var {
+                    if !env_vars.insert(env_var.clone()) {
+                        return Err(anyhow!("Duplicate environment variable: {}", env_var));
+                    }
+                }
                 if !obj_ids.insert(config.key_server_object_id) {
                     return Err(anyhow!(
                         "Duplicate key server object ID: {}",
@@ -253,16 +588,136 @@ impl KeyServerOptions {
                 ));
             }
         }
+
+        if let ServerMode::Open {
+            master_key_version,
+            deprecated_master_keys,
+            ..
+        } = &self.server_mode
+        {
+            check_key_versions(
+                *master_key_version,
+                deprecated_master_keys.iter().map(|k| k.version),
+                "open mode",
+            )?;
+        }
+
+        match &self.server_mode {
+            ServerMode::Committee {
+                threshold,
+                members,
+                token_env_var,
+                ..
+            } => {
+                if *threshold == 0 || *threshold as usize > members.len() {
+                    return Err(anyhow!(
+                        "The threshold of a committee must be between 1 and the number of members"
+                    ));
+                }
+                let mut indices = std::collections::HashSet::new();
+                for member in members {
+                    if member.index == 0 || !indices.insert(member.index) {
+                        return Err(anyhow!(
+                            "Share indices must be unique and non-zero: {}",
+                            member.index
+                        ));
+                    }
+                    // Partial keys and the committee token must not be sent in the clear
+                    if !member.url.starts_with("https://") {
+                        return Err(anyhow!(
+                            "The URL of committee member {} must use https",
+                            member.index
+                        ));
+                    }
+                }
+                load_token(token_env_var)?;
+            }
+            ServerMode::CommitteeMember { index: 0, .. } => {
+                return Err(anyhow!("Share indices must be unique and non-zero: 0"));
+            }
+            ServerMode::CommitteeMember { token_env_var, .. } => {
+                load_token(token_env_var)?;
+            }
+            _ => {}
+        }
+
+        for limit in [
+            &self.rate_limits.per_user,
+            &self.rate_limits.per_ip,
+            &self.rate_limits.per_package,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !limit.requests_per_second.is_finite()
+                || limit.requests_per_second <= 0.0
+                || limit.burst == 0
+            {
+                return Err(anyhow!(
+                    "Rate limits must have a positive rate and burst: {:?}",
+                    limit
+                ));
+            }
+        }
+
+        if let Some(admin) = &self.admin {
+            if admin.host_port == self.metrics_host_port {
+                return Err(anyhow!(
+                    "The admin API and metrics must be served on different ports"
+                ));
+            }
+        }
+
+        if let Some(AuditLogConfig {
+            sink:
+                AuditLogSink::File {
+                    max_size_bytes,
+                    max_files,
+                    ..
+                },
+            ..
+        }) = &self.audit_log
+        {
+            if *max_size_bytes == 0 || *max_files == 0 {
+                return Err(anyhow!(
+                    "The audit log must have a positive maximum size and number of files"
+                ));
+            }
+        }
+
+        if let Some(AuditLogConfig {
+            sink: AuditLogSink::Stdout | AuditLogSink::Syslog { .. },
+            hash_chain: true,
+        }) = &self.audit_log
+        {
+            return Err(anyhow!("The audit log hash chain requires a file sink"));
+        }
         Ok(())
     }
 
+    /// A copy of the options with secrets replaced, such that it can be logged or exposed.
+    pub fn redacted(&self) -> Self {
+        let mut options = self.clone();
+        if let Some(push_config) = &mut options.metrics_push_config {
+            push_config.bearer_token = "<redacted>".to_string();
+        }
+        options
+    }
+
     pub(crate) fn get_supported_key_server_object_ids(&self) -> Vec<ObjectID> {
         match &self.server_mode {
             ServerMode::Open {
                 key_server_object_id,
+                ..
+            }
+            | ServerMode::Committee {
+                key_server_object_id,
+                ..
             } => {
                 vec![*key_server_object_id]
             }
+            // Members only serve the coordinator
+            ServerMode::CommitteeMember { .. } => vec![],
             ServerMode::Permissioned { client_configs } => client_configs
                 .iter()
                 .filter(|c| {
@@ -277,6 +732,21 @@ impl KeyServerOptions {
     }
 }
 
+/// Check that the versions of the keys of a key server object are unique.
+fn check_key_versions(
+    current_version: u32,
+    deprecated_versions: impl Iterator<Item = u32>,
+    name: &str,
+) -> Result<()> {
+    let mut versions = std::collections::HashSet::from([current_version]);
+    for version in deprecated_versions {
+        if !versions.insert(version) {
+            return Err(anyhow!("Duplicate key version {} for {}", version, name));
+        }
+    }
+    Ok(())
+}
+
 fn default_checkpoint_update_interval() -> Duration {
     Duration::from_secs(10)
 }
@@ -293,10 +763,42 @@ fn default_allowed_staleness() -> Duration {
     from_mins(2)
 }
 
+fn default_policy_cache_ttl() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_policy_cache_size() -> u64 {
+    10_000
+}
+
 fn default_metrics_host_port() -> u16 {
     9184
 }
 
+fn default_admin_host() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+fn default_admin_host_port() -> u16 {
+    9185
+}
+
+fn default_committee_member_host_port() -> u16 {
+    9186
+}
+
+fn default_audit_log_max_size_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_audit_log_max_files() -> usize {
+    10
+}
+
+fn default_syslog_socket_path() -> PathBuf {
+    PathBuf::from("/dev/log")
+}
+
 fn default_sdk_version_requirement() -> VersionReq {
     VersionReq::parse(">=0.4.5").expect("Failed to parse default SDK version requirement")
 }
@@ -327,9 +829,30 @@ session_key_ttl_max: '60s'
             "0x0000000000000000000000000000000000000000000000000000000000000002",
         )
         .unwrap(),
+        master_key_source: None,
+        master_key_version: 0,
+        deprecated_master_keys: vec![],
     };
     assert_eq!(options.server_mode, expected_server_mode);
 
+    let valid_configuration_key_source = r#"
+network: Mainnet
+server_mode: !Open
+  key_server_object_id: '0x2'
+  master_key_source: !Keystore
+    path: /etc/seal/master-key.json
+    passphrase_env_var: KEYSTORE_PASSPHRASE
+"#;
+    let options: KeyServerOptions = serde_yaml::from_str(valid_configuration_key_source)
+        .expect("Failed to parse valid configuration");
+    assert!(matches!(
+        options.server_mode,
+        ServerMode::Open {
+            master_key_source: Some(KeySource::Keystore { .. }),
+            ..
+        }
+    ));
+
     assert_eq!(options.checkpoint_update_interval, Duration::from_secs(13));
 
     let valid_configuration_custom_network = r#"
@@ -380,6 +903,13 @@ server_mode: !Permissioned
       key_server_object_id: "0xcccc000000000000000000000000000000000000000000000000000000000003"
       package_ids:
       - "0x3333333333333333333333333333333333333333333333333333333333333333"
+    - name: "dave"
+      client_master_key: !Imported
+        key_source: !File
+          path: "/etc/seal/dave.key"
+      key_server_object_id: "0xdddd000000000000000000000000000000000000000000000000000000000004"
+      package_ids:
+      - "0x4444444444444444444444444444444444444444444444444444444444444444"
 checkpoint_update_interval: '13s'
 rgp_update_interval: '5s'
 allowed_staleness: '2s'
@@ -400,6 +930,10 @@ session_key_ttl_max: '60s'
                 "0xbbbb000000000000000000000000000000000000000000000000000000000002"
             )
             .unwrap(),
+            ObjectID::from_str(
+                "0xdddd000000000000000000000000000000000000000000000000000000000004"
+            )
+            .unwrap(),
         ]
     );
 }
@@ -481,6 +1015,87 @@ server_mode: !Permissioned
 "#;
     let dup_env_var_expected_error = "Duplicate environment variable: BOB_BLS_KEY";
 
+    let dup_key_source_env_var = r#"
+network: Mainnet
+server_mode: !Permissioned
+  client_configs:
+    - name: "alice"
+      client_master_key: !Imported
+        key_source: !Env
+          env_var: "BOB_BLS_KEY"
+      key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+      package_ids:
+        - "0x2222222222222222222222222222222222222222222222222222222222222220"
+    - name: "bob"
+      client_master_key: !Imported
+        env_var: "BOB_BLS_KEY"
+      key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000002"
+      package_ids:
+        - "0x2222222222222222222222222222222222222222222222222222222222222222"
+"#;
+
+    let ambiguous_imported_key = r#"
+network: Mainnet
+server_mode: !Permissioned
+  client_configs:
+    - name: "alice"
+      client_master_key: !Imported
+        env_var: "ALICE_BLS_KEY"
+        key_source: !File
+          path: "/etc/seal/alice.key"
+      key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+      package_ids:
+        - "0x2222222222222222222222222222222222222222222222222222222222222220"
+"#;
+    let ambiguous_imported_key_expected_error =
+        "Imported key must have either an env_var or a key_source: alice";
+
+    let dup_key_version = r#"
+network: Mainnet
+server_mode: !Permissioned
+  client_configs:
+    - name: "alice"
+      client_master_key: !Derived
+        derivation_index: 1
+      key_version: 1
+      deprecated_keys:
+        - version: 1
+          client_master_key: !Derived
+            derivation_index: 0
+      key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+      package_ids:
+        - "0x2222222222222222222222222222222222222222222222222222222222222220"
+"#;
+    let dup_key_version_expected_error = "Duplicate key version 1 for alice";
+
+    let dup_deprecated_derivation_index = r#"
+network: Mainnet
+server_mode: !Permissioned
+  client_configs:
+    - name: "alice"
+      client_master_key: !Derived
+        derivation_index: 0
+      key_version: 1
+      deprecated_keys:
+        - version: 0
+          client_master_key: !Derived
+            derivation_index: 0
+      key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+      package_ids:
+        - "0x2222222222222222222222222222222222222222222222222222222222222220"
+"#;
+
+    let dup_open_key_version = r#"
+network: Mainnet
+server_mode: !Open
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+  deprecated_master_keys:
+    - version: 0
+      key_source: !Env
+        env_var: "OLD_MASTER_KEY"
+"#;
+    let dup_open_key_version_expected_error = "Duplicate key version 0 for open mode";
+
     let dup_derivation_index = r#"
 network: Mainnet
 server_mode: !Permissioned
@@ -522,17 +1137,113 @@ server_mode: !Permissioned
     let non_incrementing_index_expected_error =
         "Derivation indexes must be incremental, starting from 0";
 
+    let committee_threshold = r#"
+network: Mainnet
+server_mode: !Committee
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+  threshold: 3
+  token_env_var: "COMMITTEE_TOKEN"
+  members:
+    - index: 1
+      url: "https://member1.example.com"
+      public_key_share: "0x01"
+    - index: 2
+      url: "https://member2.example.com"
+      public_key_share: "0x02"
+"#;
+    let committee_threshold_expected_error =
+        "The threshold of a committee must be between 1 and the number of members";
+
+    let dup_share_index = r#"
+network: Mainnet
+server_mode: !Committee
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+  threshold: 2
+  token_env_var: "COMMITTEE_TOKEN"
+  members:
+    - index: 1
+      url: "https://member1.example.com"
+      public_key_share: "0x01"
+    - index: 1
+      url: "https://member2.example.com"
+      public_key_share: "0x02"
+"#;
+    let dup_share_index_expected_error = "Share indices must be unique and non-zero: 1";
+
+    let http_member_url = r#"
+network: Mainnet
+server_mode: !Committee
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+  threshold: 1
+  token_env_var: "COMMITTEE_TOKEN"
+  members:
+    - index: 1
+      url: "http://member1.example.com"
+      public_key_share: "0x01"
+"#;
+    let http_member_url_expected_error = "The URL of committee member 1 must use https";
+
+    let zero_share_index = r#"
+network: Mainnet
+server_mode: !CommitteeMember
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+  index: 0
+  token_env_var: "COMMITTEE_TOKEN"
+  share_source: !File
+    path: "/etc/seal/share.key"
+"#;
+    let zero_share_index_expected_error = "Share indices must be unique and non-zero: 0";
+
+    let empty_audit_log = r#"
+network: Mainnet
+server_mode: !Open
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+audit_log:
+  sink: !File
+    path: "/var/log/seal/audit.log"
+    max_files: 0
+"#;
+    let empty_audit_log_expected_error =
+        "The audit log must have a positive maximum size and number of files";
+
+    let hash_chain_audit_log = r#"
+network: Mainnet
+server_mode: !Open
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+audit_log:
+  sink: !Stdout
+  hash_chain: true
+"#;
+    let hash_chain_audit_log_expected_error = "The audit log hash chain requires a file sink";
+
     // load each of those yaml and call validate
     let test_cases = vec![
         (empty_pkg, empty_pkg_expected_error),
         (dup_ks_oid, dup_ks_oid_expected_error),
         (dup_pkg_id, dup_pkg_id_expected_error),
         (dup_env_var, dup_env_var_expected_error),
+        (dup_key_source_env_var, dup_env_var_expected_error),
+        (
+            ambiguous_imported_key,
+            ambiguous_imported_key_expected_error,
+        ),
         (dup_derivation_index, dup_derivation_index_expected_error),
+        (dup_key_version, dup_key_version_expected_error),
+        (
+            dup_deprecated_derivation_index,
+            dup_derivation_index_expected_error,
+        ),
+        (dup_open_key_version, dup_open_key_version_expected_error),
         (
             non_incrementing_derivation_index,
             non_incrementing_index_expected_error,
         ),
+        (committee_threshold, committee_threshold_expected_error),
+        (dup_share_index, dup_share_index_expected_error),
+        (http_member_url, http_member_url_expected_error),
+        (zero_share_index, zero_share_index_expected_error),
+        (empty_audit_log, empty_audit_log_expected_error),
+        (hash_chain_audit_log, hash_chain_audit_log_expected_error),
     ];
     for (yaml, expected_error) in test_cases {
         let options: KeyServerOptions =
@@ -542,3 +1253,49 @@ server_mode: !Permissioned
         assert_eq!(result.unwrap_err().to_string(), expected_error);
     }
 }
+
+#[test]
+fn test_validate_committee_token() {
+    let committee = r#"
+network: Mainnet
+server_mode: !Committee
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+  threshold: 1
+  token_env_var: "TEST_COMMITTEE_TOKEN"
+  members:
+    - index: 1
+      url: "https://member1.example.com"
+      public_key_share: "0x01"
+"#;
+    let member = r#"
+network: Mainnet
+server_mode: !CommitteeMember
+  key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+  index: 1
+  token_env_var: "TEST_COMMITTEE_TOKEN"
+  share_source: !File
+    path: "/etc/seal/share.key"
+"#;
+    for yaml in [committee, member] {
+        let options: KeyServerOptions =
+            serde_yaml::from_str(yaml).expect("Failed to parse valid configuration");
+        let validate = |token: Option<&str>| {
+            temp_env::with_var("TEST_COMMITTEE_TOKEN", token, || {
+                options.validate().map_err(|e| e.to_string())
+            })
+        };
+        assert!(validate(Some(&"a".repeat(32))).is_ok());
+        assert_eq!(
+            validate(Some(&"a".repeat(31))).unwrap_err(),
+            "Committee token TEST_COMMITTEE_TOKEN must have at least 32 bytes"
+        );
+        assert_eq!(
+            validate(Some("")).unwrap_err(),
+            "Committee token TEST_COMMITTEE_TOKEN must have at least 32 bytes"
+        );
+        assert_eq!(
+            validate(None).unwrap_err(),
+            "Committee token TEST_COMMITTEE_TOKEN must be set"
+        );
+    }
+}
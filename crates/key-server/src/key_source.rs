@@ -0,0 +1,239 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Loading of master keys from the sources configured by [KeySource].
+
+use crate::key_server_options::KeySource;
+use crate::types::IbeMasterKey;
+use crate::utils::decode_master_key;
+use crate::DefaultEncoding;
+use anyhow::{anyhow, Context};
+use crypto::ibe::MASTER_KEY_LENGTH;
+use crypto::keystore::Keystore;
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::error::{Error as CryptokiError, RvError};
+use cryptoki::object::{Attribute, AttributeType};
+use cryptoki::session::UserType;
+use cryptoki::types::AuthPin;
+use fastcrypto::encoding::Encoding;
+use fastcrypto::serde_helpers::ToFromByteArray;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+impl KeySource {
+    /// Load the master key from this source.
+    pub(crate) fn load_master_key(&self) -> anyhow::Result<IbeMasterKey> {
+        let bytes = match self {
+            KeySource::Env { env_var } => return decode_master_key::<DefaultEncoding>(env_var),
+            KeySource::File { path } => {
+                let content = read_secret_file(path)?;
+                DefaultEncoding::decode(content.trim())
+                    .map_err(|_| anyhow!("Key file {} should be hex encoded", path.display()))?
+            }
+            KeySource::Keystore {
+                path,
+                passphrase_env_var,
+            } => {
+                let keystore: Keystore = serde_json::from_str(&read_secret_file(path)?)
+                    .with_context(|| format!("Cannot parse keystore {}", path.display()))?;
+                let passphrase = std::env::var(passphrase_env_var).map_err(|_| {
+                    anyhow!("Environment variable {} must be set", passphrase_env_var)
+                })?;
+                keystore
+                    .decrypt(&passphrase)
+                    .map_err(|e| anyhow!("Cannot unlock keystore {}: {}", path.display(), e))?
+            }
+            KeySource::Pkcs11 {
+                module,
+                token_label,
+                key_label,
+                pin_env_var,
+            } => read_pkcs11_object(module, token_label, key_label, pin_env_var)?,
+        };
+        let bytes: [u8; MASTER_KEY_LENGTH] = bytes.try_into().map_err(|_| {
+            anyhow!("Invalid key length from {self:?}. Must be {MASTER_KEY_LENGTH} bytes long")
+        })?;
+        IbeMasterKey::from_byte_array(&bytes)
+            .map_err(|_| anyhow!("Invalid master key from {self:?}"))
+    }
+}
+
+/// Read a file holding a secret. The file must be a regular file which is not accessible by group
+/// or others.
+fn read_secret_file(path: &Path) -> anyhow::Result<String> {
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("Cannot read key file {}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(anyhow!(
+            "Key file {} must be a regular file",
+            path.display()
+        ));
+    }
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        return Err(anyhow!(
+            "Key file {} must not be accessible by group or others (mode {:o}), use chmod 600",
+            path.display(),
+            mode
+        ));
+    }
+    std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read key file {}", path.display()))
+}
+
+/// Read the value of the object with the given label on a PKCS#11 token.
+fn read_pkcs11_object(
+    module: &Path,
+    token_label: &str,
+    key_label: &str,
+    pin_env_var: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let pin = std::env::var(pin_env_var)
+        .map_err(|_| anyhow!("Environment variable {} must be set", pin_env_var))?;
+    let pkcs11 = Pkcs11::new(module)
+        .with_context(|| format!("Cannot load PKCS#11 module {}", module.display()))?;
+    // The module is already initialized if a key was loaded from it before, e.g., before a reload
+    match pkcs11.initialize(CInitializeArgs::OsThreads) {
+        Ok(()) | Err(CryptokiError::Pkcs11(RvError::CryptokiAlreadyInitialized, ..)) => {}
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Cannot initialize PKCS#11 module {}", module.display()))
+        }
+    }
+    let slot = pkcs11
+        .get_slots_with_token()?
+        .into_iter()
+        .find(|slot| {
+            pkcs11
+                .get_token_info(*slot)
+                .is_ok_and(|info| info.label() == token_label)
+        })
+        .ok_or_else(|| anyhow!("PKCS#11 token {} not found", token_label))?;
+    let session = pkcs11.open_ro_session(slot)?;
+    session
+        .login(UserType::User, Some(&AuthPin::new(pin)))
+        .with_context(|| format!("Cannot log in to PKCS#11 token {}", token_label))?;
+    let objects = session.find_objects(&[Attribute::Label(key_label.as_bytes().to_vec())])?;
+    let [object] = objects[..] else {
+        return Err(anyhow!(
+            "Expected one object with label {} on PKCS#11 token {}, found {}",
+            key_label,
+            token_label,
+            objects.len()
+        ));
+    };
+    match session.get_attributes(object, &[AttributeType::Value])?.pop() {
+        Some(Attribute::Value(value)) => Ok(value),
+        _ => Err(anyhow!(
+            "The value of object {} cannot be read, it must be a data object or an extractable, non-sensitive secret key",
+            key_label
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::groups::GroupElement;
+    use rand::thread_rng;
+    use std::path::PathBuf;
+    use sui_types::base_types::ObjectID;
+
+    fn write_secret(dir: &Path, name: &str, content: &str, mode: u32) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_key_sources() {
+        let dir = std::env::temp_dir().join(format!("key-source-{}", ObjectID::random()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sk = IbeMasterKey::generator();
+        let encoded = DefaultEncoding::encode(sk.to_byte_array());
+
+        // Files must be private
+        let path = write_secret(&dir, "key", &format!("{}\n", encoded), 0o600);
+        assert_eq!(KeySource::File { path }.load_master_key().unwrap(), sk);
+        let path = write_secret(&dir, "shared-key", &encoded, 0o640);
+        assert!(KeySource::File { path }.load_master_key().is_err());
+        let path = dir.clone();
+        assert!(KeySource::File { path }.load_master_key().is_err());
+
+        // Keystores are unlocked by the passphrase
+        let keystore = Keystore::encrypt(&sk.to_byte_array(), "secret", &mut thread_rng()).unwrap();
+        let keystore = serde_json::to_string(&keystore).unwrap();
+        let source = KeySource::Keystore {
+            path: write_secret(&dir, "keystore.json", &keystore, 0o600),
+            passphrase_env_var: "TEST_KEYSTORE_PASSPHRASE".to_string(),
+        };
+        temp_env::with_var("TEST_KEYSTORE_PASSPHRASE", Some("secret"), || {
+            assert_eq!(source.load_master_key().unwrap(), sk);
+        });
+        temp_env::with_var("TEST_KEYSTORE_PASSPHRASE", Some("Secret"), || {
+            assert!(source.load_master_key().is_err());
+        });
+        temp_env::with_var("TEST_KEYSTORE_PASSPHRASE", None::<&str>, || {
+            assert!(source.load_master_key().is_err());
+        });
+
+        // Environment variables
+        let source = KeySource::Env {
+            env_var: "TEST_MASTER_KEY".to_string(),
+        };
+        temp_env::with_var("TEST_MASTER_KEY", Some(&encoded), || {
+            assert_eq!(source.load_master_key().unwrap(), sk);
+        });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Requires a SoftHSM token labeled `seal-test` with user PIN `1234`, e.g., created with
+    /// `softhsm2-util --init-token --free --label seal-test --pin 1234 --so-pin 1234`, and the path
+    /// of the SoftHSM library in `SOFTHSM2_MODULE`.
+    #[test]
+    #[ignore = "requires SoftHSM"]
+    fn test_pkcs11_key_source() {
+        use cryptoki::object::ObjectClass;
+
+        let module = PathBuf::from(std::env::var("SOFTHSM2_MODULE").unwrap());
+        let key_label = format!("seal-test-{}", ObjectID::random());
+        let sk = IbeMasterKey::generator();
+        {
+            let pkcs11 = Pkcs11::new(&module).unwrap();
+            pkcs11.initialize(CInitializeArgs::OsThreads).unwrap();
+            let slot = pkcs11
+                .get_slots_with_token()
+                .unwrap()
+                .into_iter()
+                .find(|slot| pkcs11.get_token_info(*slot).unwrap().label() == "seal-test")
+                .unwrap();
+            let session = pkcs11.open_rw_session(slot).unwrap();
+            session
+                .login(UserType::User, Some(&AuthPin::new("1234".to_string())))
+                .unwrap();
+            session
+                .create_object(&[
+                    Attribute::Class(ObjectClass::DATA),
+                    Attribute::Token(true),
+                    Attribute::Private(true),
+                    Attribute::Label(key_label.as_bytes().to_vec()),
+                    Attribute::Value(sk.to_byte_array().to_vec()),
+                ])
+                .unwrap();
+        }
+
+        let source = KeySource::Pkcs11 {
+            module,
+            token_label: "seal-test".to_string(),
+            key_label,
+            pin_env_var: "TEST_PKCS11_PIN".to_string(),
+        };
+        temp_env::with_var("TEST_PKCS11_PIN", Some("1234"), || {
+            assert_eq!(source.load_master_key().unwrap(), sk);
+        });
+        temp_env::with_var("TEST_PKCS11_PIN", Some("4321"), || {
+            assert!(source.load_master_key().is_err());
+        });
+    }
+}
@@ -0,0 +1,733 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Local evaluation of policies, see [LocalPolicyEvaluator].
+//!
+//! The `seal_approve*` functions are executed by the Move VM of the key server against objects
+//! fetched from the full node, which avoids a dry run on the full node for every request. The
+//! input objects and packages of a PTB are loaded before execution. Dynamic fields cannot be known
+//! in advance, so child objects that are read during execution but are not loaded yet are
+//! recorded, fetched and the PTB is executed again.
+
+use crate::cache::{lru_cache, DEFAULT_SIZE, DEFAULT_TTL_IN_MILLIS};
+use crate::errors::InternalError;
+use crate::policy_evaluator::PolicyEvaluator;
+use crate::sui_rpc_client::SuiRpcClient;
+use crate::types::Network;
+use crate::GAS_BUDGET;
+use async_trait::async_trait;
+use moka::sync::Cache;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use sui_protocol_config::{Chain, ProtocolConfig, ProtocolVersion};
+use sui_sdk::rpc_types::{SuiExecutionStatus, SuiObjectDataOptions};
+use sui_types::base_types::{
+    EpochId, ObjectID, ObjectRef, SequenceNumber, SuiAddress, OBJECT_START_VERSION,
+};
+use sui_types::digests::TransactionDigest;
+use sui_types::effects::TransactionEffectsAPI;
+use sui_types::error::{SuiError, SuiResult};
+use sui_types::execution_status::ExecutionStatus;
+use sui_types::gas::SuiGasStatus;
+use sui_types::metrics::LimitsMetrics;
+use sui_types::object::{MoveObject, Object, Owner};
+use sui_types::storage::{
+    BackingPackageStore, ChildObjectResolver, ObjectStore as SuiObjectStore, PackageObject,
+    ParentSync,
+};
+use sui_types::transaction::{
+    CheckedInputObjects, GasData, InputObjectKind, InputObjects, ObjectReadResult,
+    ObjectReadResultKind, ProgrammableTransaction, TransactionKind,
+};
+use tracing::{debug, warn};
+
+/// The maximum number of times a PTB is executed while loading the child objects it reads.
+const MAX_EXECUTIONS: usize = 5;
+
+/// The epoch information needed to execute a PTB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct EpochInfo {
+    pub epoch: EpochId,
+    pub epoch_start_timestamp_ms: u64,
+    pub protocol_version: u64,
+    pub reference_gas_price: u64,
+}
+
+/// Source of the objects and epoch information used for local evaluation.
+#[async_trait]
+pub(crate) trait ObjectStore: Send + Sync {
+    /// Returns the latest version of an object, or None if it does not exist or was deleted.
+    async fn get_object(&self, id: &ObjectID) -> Result<Option<Object>, InternalError>;
+
+    /// Returns information about the current epoch.
+    async fn get_epoch_info(&self) -> Result<EpochInfo, InternalError>;
+}
+
+/// Fetches objects from the full node.
+pub(crate) struct RpcObjectStore {
+    sui_rpc_client: SuiRpcClient,
+}
+
+impl RpcObjectStore {
+    pub fn new(sui_rpc_client: SuiRpcClient) -> Self {
+        Self { sui_rpc_client }
+    }
+}
+
+#[async_trait]
+impl ObjectStore for RpcObjectStore {
+    async fn get_object(&self, id: &ObjectID) -> Result<Option<Object>, InternalError> {
+        let response = self
+            .sui_rpc_client
+            .get_object_with_options(*id, SuiObjectDataOptions::bcs_lossless())
+            .await
+            .map_err(|e| {
+                warn!("Failed to fetch object {}: {:?}", id, e);
+                InternalError::Failure
+            })?;
+        let Some(data) = response.data else {
+            return Ok(None);
+        };
+        data.try_into().map(Some).map_err(|e| {
+            warn!("Failed to convert object {}: {:?}", id, e);
+            InternalError::Failure
+        })
+    }
+
+    async fn get_epoch_info(&self) -> Result<EpochInfo, InternalError> {
+        let state = self
+            .sui_rpc_client
+            .get_latest_sui_system_state()
+            .await
+            .map_err(|e| {
+                warn!("Failed to fetch the latest system state: {:?}", e);
+                InternalError::Failure
+            })?;
+        Ok(EpochInfo {
+            epoch: state.epoch,
+            epoch_start_timestamp_ms: state.epoch_start_timestamp_ms,
+            protocol_version: state.protocol_version,
+            reference_gas_price: state.reference_gas_price,
+        })
+    }
+}
+
+/// How long the epoch information is cached.
+const EPOCH_INFO_TTL_IN_MILLIS: u64 = 1000;
+
+/// Caches the objects of another [ObjectStore]. Packages and immutable objects cannot change and
+/// are cached for [DEFAULT_TTL_IN_MILLIS]. Shared and owned objects are not cached, since policies
+/// such as time locks or allowlists must be evaluated against their latest version. The epoch
+/// information is cached for a short time.
+pub(crate) struct CachedObjectStore<S> {
+    inner: S,
+    immutable_objects: Cache<ObjectID, Object>,
+    epoch_info: Cache<(), EpochInfo>,
+}
+
+impl<S> CachedObjectStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            immutable_objects: lru_cache(DEFAULT_TTL_IN_MILLIS, DEFAULT_SIZE),
+            epoch_info: lru_cache(EPOCH_INFO_TTL_IN_MILLIS, 1),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: ObjectStore> ObjectStore for CachedObjectStore<S> {
+    async fn get_object(&self, id: &ObjectID) -> Result<Option<Object>, InternalError> {
+        if let Some(object) = self.immutable_objects.get(id) {
+            return Ok(Some(object));
+        }
+        let object = self.inner.get_object(id).await?;
+        if let Some(object) = &object {
+            if object.is_package() || object.is_immutable() {
+                self.immutable_objects.insert(*id, object.clone());
+            }
+        }
+        Ok(object)
+    }
+
+    async fn get_epoch_info(&self) -> Result<EpochInfo, InternalError> {
+        if let Some(epoch_info) = self.epoch_info.get(&()) {
+            return Ok(epoch_info);
+        }
+        let epoch_info = self.inner.get_epoch_info().await?;
+        self.epoch_info.insert((), epoch_info);
+        Ok(epoch_info)
+    }
+}
+
+/// Evaluates policies by executing the PTB locally against the objects of an [ObjectStore].
+pub(crate) struct LocalPolicyEvaluator<S> {
+    store: S,
+    chain: Chain,
+    limits_metrics: Arc<LimitsMetrics>,
+}
+
+impl<S: ObjectStore> LocalPolicyEvaluator<S> {
+    pub fn new(store: S, network: &Network) -> Self {
+        let chain = match network {
+            Network::Mainnet => Chain::Mainnet,
+            Network::Testnet => Chain::Testnet,
+            _ => Chain::Unknown,
+        };
+        Self {
+            store,
+            chain,
+            limits_metrics: Arc::new(LimitsMetrics::new(&prometheus::Registry::new())),
+        }
+    }
+
+    /// Load the input objects of a PTB, the packages it calls and their dependencies. Owned input
+    /// objects must be owned by the sender, as checked by the full node before execution.
+    async fn load_inputs(
+        &self,
+        sender: SuiAddress,
+        ptb: &ProgrammableTransaction,
+    ) -> Result<(LoadedObjects, Vec<ObjectReadResult>), InternalError> {
+        let kinds = TransactionKind::ProgrammableTransaction(ptb.clone())
+            .input_objects()
+            .map_err(|e| InternalError::InvalidPTB(e.to_string()))?;
+
+        let mut objects = LoadedObjects::default();
+        let mut inputs = Vec::with_capacity(kinds.len());
+        for kind in kinds {
+            let object = self
+                .store
+                .get_object(&kind.object_id())
+                .await?
+                .ok_or_else(|| {
+                    debug!("Input object {} does not exist", kind.object_id());
+                    InternalError::InvalidParameter
+                })?;
+            match kind {
+                InputObjectKind::MovePackage(_) if !object.is_package() => {
+                    return Err(InternalError::InvalidPTB(format!(
+                        "{} is not a package",
+                        object.id()
+                    )));
+                }
+                InputObjectKind::ImmOrOwnedMoveObject((_, version, _))
+                    if object.version() != version =>
+                {
+                    debug!(
+                        "Input object {} has version {} but {} was given",
+                        object.id(),
+                        object.version(),
+                        version
+                    );
+                    return Err(InternalError::InvalidParameter);
+                }
+                InputObjectKind::ImmOrOwnedMoveObject(_) => match object.owner {
+                    Owner::Immutable => {}
+                    Owner::AddressOwner(owner) if owner == sender => {}
+                    _ => {
+                        debug!(
+                            "Input object {} is not owned by the sender {}",
+                            object.id(),
+                            sender
+                        );
+                        return Err(InternalError::InvalidParameter);
+                    }
+                },
+                _ => {}
+            }
+            inputs.push(ObjectReadResult::new(
+                kind,
+                ObjectReadResultKind::Object(object.clone()),
+            ));
+            objects.insert(object);
+        }
+
+        // The linkage table of a package contains all its transitive dependencies
+        let dependencies: HashSet<ObjectID> = objects
+            .objects
+            .values()
+            .filter_map(|object| object.data.try_as_package())
+            .flat_map(|package| package.linkage_table().values())
+            .map(|upgrade_info| upgrade_info.upgraded_id)
+            .filter(|id| !objects.objects.contains_key(id))
+            .collect();
+        for id in dependencies {
+            let package = self.store.get_object(&id).await?.ok_or_else(|| {
+                warn!("Dependency {} does not exist", id);
+                InternalError::Failure
+            })?;
+            objects.insert(package);
+        }
+        Ok((objects, inputs))
+    }
+}
+
+#[async_trait]
+impl<S: ObjectStore + 'static> PolicyEvaluator for LocalPolicyEvaluator<S> {
+    async fn evaluate(
+        &self,
+        sender: SuiAddress,
+        ptb: &ProgrammableTransaction,
+        gas_price: u64,
+        req_id: Option<&str>,
+    ) -> Result<SuiExecutionStatus, InternalError> {
+        let epoch_info = self.store.get_epoch_info().await?;
+        let protocol_config = ProtocolConfig::get_for_version_if_supported(
+            ProtocolVersion::new(epoch_info.protocol_version),
+            self.chain,
+        )
+        .ok_or_else(|| {
+            warn!(
+                "Protocol version {} is not supported, the key server must be upgraded (req_id: {:?})",
+                epoch_info.protocol_version, req_id
+            );
+            InternalError::Failure
+        })?;
+        let (mut objects, inputs) = self.load_inputs(sender, ptb).await?;
+
+        for _ in 0..MAX_EXECUTIONS {
+            // Execution is CPU bound, so it is done outside the async runtime
+            let ptb = ptb.clone();
+            let inputs = inputs.clone();
+            let protocol_config = protocol_config.clone();
+            let limits_metrics = self.limits_metrics.clone();
+            let (status, returned_objects) = tokio::task::spawn_blocking(move || {
+                let status = execute(
+                    objects.clone(),
+                    inputs,
+                    ptb,
+                    sender,
+                    gas_price,
+                    &epoch_info,
+                    &protocol_config,
+                    limits_metrics,
+                );
+                (status, objects)
+            })
+            .await
+            .map_err(|e| {
+                warn!("Local execution failed: {:?} (req_id: {:?})", e, req_id);
+                InternalError::Failure
+            })?;
+            objects = returned_objects;
+
+            let missing = objects.take_missing();
+            if missing.is_empty() {
+                let status = status?;
+                debug!(
+                    "Local execution status: {:?} (req_id: {:?})",
+                    status, req_id
+                );
+                return Ok(status.into());
+            }
+            debug!("Loading child objects {:?} (req_id: {:?})", missing, req_id);
+            for id in missing {
+                match self.store.get_object(&id).await? {
+                    Some(object) => objects.insert(object),
+                    None => {
+                        objects.absent.insert(id);
+                    }
+                }
+            }
+        }
+        warn!(
+            "Policy reads too many nested child objects (req_id: {:?})",
+            req_id
+        );
+        Err(InternalError::Failure)
+    }
+}
+
+/// Execute a PTB with dev-inspect semantics against the given objects. A gas coin owned by the
+/// sender is added, so the sender does not need to own any gas.
+#[allow(clippy::too_many_arguments)]
+fn execute(
+    mut objects: LoadedObjects,
+    mut inputs: Vec<ObjectReadResult>,
+    ptb: ProgrammableTransaction,
+    sender: SuiAddress,
+    gas_price: u64,
+    epoch_info: &EpochInfo,
+    protocol_config: &ProtocolConfig,
+    limits_metrics: Arc<LimitsMetrics>,
+) -> Result<ExecutionStatus, InternalError> {
+    let executor = sui_execution::executor(protocol_config, true, None).map_err(|e| {
+        warn!("Failed to create executor: {:?}", e);
+        InternalError::Failure
+    })?;
+
+    let gas_object = Object::new_move(
+        MoveObject::new_gas_coin(OBJECT_START_VERSION, ObjectID::random(), GAS_BUDGET),
+        Owner::AddressOwner(sender),
+        TransactionDigest::genesis_marker(),
+    );
+    let gas_object_ref = gas_object.compute_object_reference();
+    inputs.push(ObjectReadResult::new(
+        InputObjectKind::ImmOrOwnedMoveObject(gas_object_ref),
+        ObjectReadResultKind::Object(gas_object.clone()),
+    ));
+    objects.insert(gas_object);
+
+    let gas_price = gas_price.max(epoch_info.reference_gas_price);
+    let gas_status = SuiGasStatus::new(
+        GAS_BUDGET,
+        gas_price,
+        epoch_info.reference_gas_price,
+        protocol_config,
+    )
+    .map_err(|e| {
+        warn!("Failed to create gas status: {:?}", e);
+        InternalError::Failure
+    })?;
+    let gas = GasData {
+        payment: vec![gas_object_ref],
+        owner: sender,
+        price: gas_price,
+        budget: GAS_BUDGET,
+    };
+
+    let (_, _, effects, _) = executor.dev_inspect_transaction(
+        &objects,
+        protocol_config,
+        limits_metrics,
+        false,
+        Ok(()),
+        &epoch_info.epoch,
+        epoch_info.epoch_start_timestamp_ms,
+        CheckedInputObjects::new_for_replay(InputObjects::new(inputs)),
+        gas,
+        gas_status,
+        TransactionKind::ProgrammableTransaction(ptb),
+        sender,
+        TransactionDigest::new(rand::random()),
+        false,
+    );
+    Ok(effects.status().clone())
+}
+
+/// The objects available to a local execution. Child objects that are read but neither loaded nor
+/// known to be absent are recorded in `missing`.
+#[derive(Clone, Default)]
+struct LoadedObjects {
+    objects: HashMap<ObjectID, Object>,
+    absent: HashSet<ObjectID>,
+    // Shared with the clones used for execution
+    missing: Arc<Mutex<Vec<ObjectID>>>,
+}
+
+impl LoadedObjects {
+    fn insert(&mut self, object: Object) {
+        self.objects.insert(object.id(), object);
+    }
+
+    fn take_missing(&self) -> Vec<ObjectID> {
+        let mut missing = std::mem::take(&mut *self.missing.lock().unwrap());
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+}
+
+impl BackingPackageStore for LoadedObjects {
+    fn get_package_object(&self, package_id: &ObjectID) -> SuiResult<Option<PackageObject>> {
+        Ok(self
+            .objects
+            .get(package_id)
+            .filter(|object| object.is_package())
+            .cloned()
+            .map(PackageObject::new))
+    }
+}
+
+impl ChildObjectResolver for LoadedObjects {
+    fn read_child_object(
+        &self,
+        _parent: &ObjectID,
+        child: &ObjectID,
+        child_version_upper_bound: SequenceNumber,
+    ) -> SuiResult<Option<Object>> {
+        if let Some(object) = self.objects.get(child) {
+            // Only the latest version is fetched, so a child that changed after its parent was
+            // loaded cannot be read in the version consistent with the parent
+            if object.version() > child_version_upper_bound {
+                return Err(SuiError::UnsupportedFeatureError {
+                    error: format!(
+                        "Child object {} has version {} which is newer than {}",
+                        child,
+                        object.version(),
+                        child_version_upper_bound
+                    ),
+                });
+            }
+            return Ok(Some(object.clone()));
+        }
+        if !self.absent.contains(child) {
+            self.missing.lock().unwrap().push(*child);
+        }
+        Ok(None)
+    }
+
+    fn get_object_received_at_version(
+        &self,
+        _owner: &ObjectID,
+        _receiving_object_id: &ObjectID,
+        _receive_object_at_version: SequenceNumber,
+        _epoch_id: EpochId,
+        _use_object_per_epoch_marker_table_v2: bool,
+    ) -> SuiResult<Option<Object>> {
+        // Receiving objects is not supported in seal_approve functions
+        Ok(None)
+    }
+}
+
+impl SuiObjectStore for LoadedObjects {
+    fn get_object(&self, object_id: &ObjectID) -> Option<Object> {
+        self.objects.get(object_id).cloned()
+    }
+
+    fn get_object_by_key(&self, object_id: &ObjectID, version: SequenceNumber) -> Option<Object> {
+        self.objects
+            .get(object_id)
+            .filter(|object| object.version() == version)
+            .cloned()
+    }
+}
+
+impl ParentSync for LoadedObjects {
+    fn get_latest_parent_entry_ref_deprecated(&self, _object_id: ObjectID) -> Option<ObjectRef> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use sui_types::move_package::{MovePackage, UpgradeInfo};
+    use sui_types::object::Data;
+    use sui_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+    use sui_types::transaction::ObjectArg;
+    use sui_types::Identifier;
+
+    /// An object store backed by a map that counts the number of fetches.
+    #[derive(Default)]
+    struct StubObjectStore {
+        objects: HashMap<ObjectID, Object>,
+        fetches: AtomicUsize,
+    }
+
+    impl StubObjectStore {
+        fn new(objects: impl IntoIterator<Item = Object>) -> Self {
+            Self {
+                objects: objects.into_iter().map(|o| (o.id(), o)).collect(),
+                fetches: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for StubObjectStore {
+        async fn get_object(&self, id: &ObjectID) -> Result<Option<Object>, InternalError> {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            Ok(self.objects.get(id).cloned())
+        }
+
+        async fn get_epoch_info(&self) -> Result<EpochInfo, InternalError> {
+            Ok(EpochInfo {
+                epoch: 1,
+                epoch_start_timestamp_ms: 0,
+                protocol_version: ProtocolVersion::MAX.as_u64(),
+                reference_gas_price: 1000,
+            })
+        }
+    }
+
+    /// A package without modules that depends on the given packages.
+    fn package(id: ObjectID, dependencies: &[ObjectID]) -> Object {
+        let linkage_table = dependencies
+            .iter()
+            .map(|dep| {
+                (
+                    *dep,
+                    UpgradeInfo {
+                        upgraded_id: *dep,
+                        upgraded_version: SequenceNumber::from_u64(1),
+                    },
+                )
+            })
+            .collect();
+        let package = MovePackage::new(
+            id,
+            SequenceNumber::from_u64(1),
+            BTreeMap::new(),
+            u64::MAX,
+            vec![],
+            linkage_table,
+        )
+        .unwrap();
+        Object::new_package_from_data(Data::Package(package), TransactionDigest::genesis_marker())
+    }
+
+    fn call_with_shared_object(package_id: ObjectID, shared: &Object) -> ProgrammableTransaction {
+        let mut builder = ProgrammableTransactionBuilder::new();
+        let arg = builder
+            .obj(ObjectArg::SharedObject {
+                id: shared.id(),
+                initial_shared_version: shared.version(),
+                mutable: false,
+            })
+            .unwrap();
+        builder.programmable_move_call(
+            package_id,
+            Identifier::new("policy").unwrap(),
+            Identifier::new("seal_approve").unwrap(),
+            vec![],
+            vec![arg],
+        );
+        builder.finish()
+    }
+
+    fn call_with_owned_object(package_id: ObjectID, owned: &Object) -> ProgrammableTransaction {
+        let mut builder = ProgrammableTransactionBuilder::new();
+        let arg = builder
+            .obj(ObjectArg::ImmOrOwnedObject(
+                owned.compute_object_reference(),
+            ))
+            .unwrap();
+        builder.programmable_move_call(
+            package_id,
+            Identifier::new("policy").unwrap(),
+            Identifier::new("seal_approve").unwrap(),
+            vec![],
+            vec![arg],
+        );
+        builder.finish()
+    }
+
+    #[tokio::test]
+    async fn test_cached_object_store() {
+        let package_id = ObjectID::random();
+        let immutable = Object::immutable_for_testing();
+        let store = CachedObjectStore::new(StubObjectStore::new([
+            package(package_id, &[]),
+            immutable.clone(),
+        ]));
+
+        // Packages and immutable objects are only fetched once
+        for _ in 0..2 {
+            assert!(store.get_object(&package_id).await.unwrap().is_some());
+            assert!(store.get_object(&immutable.id()).await.unwrap().is_some());
+        }
+        assert_eq!(store.inner.fetches.load(Ordering::Relaxed), 2);
+
+        // Shared and owned objects are fetched every time, such that policies see their latest
+        // version, e.g., the current time of the clock or a revoked allowlist entry
+        let shared = Object::shared_for_testing();
+        let owned = Object::with_owner_for_testing(SuiAddress::random_for_testing_only());
+        let store = CachedObjectStore::new(StubObjectStore::new([shared.clone(), owned.clone()]));
+        for _ in 0..2 {
+            assert!(store.get_object(&shared.id()).await.unwrap().is_some());
+            assert!(store.get_object(&owned.id()).await.unwrap().is_some());
+        }
+        assert_eq!(store.inner.fetches.load(Ordering::Relaxed), 4);
+
+        // Non-existing objects are not cached
+        let unknown = ObjectID::random();
+        assert!(store.get_object(&unknown).await.unwrap().is_none());
+        assert!(store.get_object(&unknown).await.unwrap().is_none());
+        assert_eq!(store.inner.fetches.load(Ordering::Relaxed), 6);
+    }
+
+    #[tokio::test]
+    async fn test_load_inputs() {
+        let dependency = ObjectID::random();
+        let package_id = ObjectID::random();
+        let shared = Object::shared_for_testing();
+        let evaluator = LocalPolicyEvaluator::new(
+            StubObjectStore::new([
+                package(package_id, &[dependency]),
+                package(dependency, &[]),
+                shared.clone(),
+            ]),
+            &Network::Testnet,
+        );
+
+        let sender = SuiAddress::random_for_testing_only();
+        let ptb = call_with_shared_object(package_id, &shared);
+        let (objects, inputs) = evaluator.load_inputs(sender, &ptb).await.unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert!(objects.get_package_object(&package_id).unwrap().is_some());
+        assert!(objects.get_package_object(&dependency).unwrap().is_some());
+        assert!(SuiObjectStore::get_object(&objects, &shared.id()).is_some());
+
+        // Child objects that are not loaded are recorded once
+        let child = ObjectID::random();
+        for _ in 0..2 {
+            assert!(objects
+                .read_child_object(&shared.id(), &child, SequenceNumber::MAX)
+                .unwrap()
+                .is_none());
+        }
+        assert_eq!(objects.take_missing(), vec![child]);
+        assert!(objects.take_missing().is_empty());
+
+        // Loaded child objects are only returned up to the given version
+        let mut objects = objects;
+        let loaded_child = Object::with_owner_for_testing(Owner::ObjectOwner(shared.id().into()));
+        objects.insert(loaded_child.clone());
+        assert!(objects
+            .read_child_object(&shared.id(), &loaded_child.id(), loaded_child.version())
+            .unwrap()
+            .is_some());
+        assert!(objects
+            .read_child_object(
+                &shared.id(),
+                &loaded_child.id(),
+                SequenceNumber::from_u64(loaded_child.version().value() - 1)
+            )
+            .is_err());
+
+        // Unknown input objects are rejected
+        let ptb = call_with_shared_object(package_id, &Object::shared_for_testing());
+        assert_eq!(
+            evaluator.load_inputs(sender, &ptb).await.err(),
+            Some(InternalError::InvalidParameter)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_rejects_objects_not_owned_by_sender() {
+        let package_id = ObjectID::random();
+        let sender = SuiAddress::random_for_testing_only();
+        let owned_by_sender = Object::with_owner_for_testing(sender);
+        let owned_by_other = Object::with_owner_for_testing(SuiAddress::random_for_testing_only());
+        let owned_by_object =
+            Object::with_owner_for_testing(Owner::ObjectOwner(ObjectID::random().into()));
+        let immutable = Object::immutable_for_testing();
+        let evaluator = LocalPolicyEvaluator::new(
+            StubObjectStore::new([
+                package(package_id, &[]),
+                owned_by_sender.clone(),
+                owned_by_other.clone(),
+                owned_by_object.clone(),
+                immutable.clone(),
+            ]),
+            &Network::Testnet,
+        );
+
+        for object in [&owned_by_other, &owned_by_object] {
+            let ptb = call_with_owned_object(package_id, object);
+            assert_eq!(
+                evaluator.evaluate(sender, &ptb, 1000, None).await.err(),
+                Some(InternalError::InvalidParameter)
+            );
+        }
+
+        // Objects owned by the sender and immutable objects are accepted
+        for object in [&owned_by_sender, &immutable] {
+            let ptb = call_with_owned_object(package_id, object);
+            assert!(evaluator.load_inputs(sender, &ptb).await.is_ok());
+        }
+    }
+}
@@ -1,17 +1,20 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::committee::{load_token, Committee};
 use crate::errors::InternalError;
 use crate::key_server_options::{ClientConfig, ClientKeyType, KeyServerOptions, ServerMode};
-use crate::types::IbeMasterKey;
+use crate::types::{IbeMasterKey, IbePublicKey};
 use crate::utils::{decode_byte_array, decode_master_key};
 use crate::DefaultEncoding;
 use anyhow::anyhow;
 use crypto::ibe;
 use crypto::ibe::SEED_LENGTH;
+use crypto::threshold::{public_key_share, MasterKeyShare};
 use fastcrypto::encoding::{Base64, Encoding};
 use fastcrypto::serde_helpers::ToFromByteArray;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use sui_types::base_types::ObjectID;
 use tracing::info;
 
@@ -21,58 +24,149 @@ const MASTER_KEY_ENV_VAR: &str = "MASTER_KEY";
 /// In Permissioned mode, the key server has a seed used to derive master keys for clients, which should be set in the environment variable `MASTER_SEED`.
 const MASTER_SEED_ENV_VAR: &str = "MASTER_SEED";
 
+/// The master keys of a key server object by version. There is more than one version while the key
+/// is being rotated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionedKeys {
+    pub key_server_object_id: ObjectID,
+    pub current_version: u32,
+    pub keys: BTreeMap<u32, IbeMasterKey>,
+}
+
+impl VersionedKeys {
+    /// The key of the current version.
+    pub fn current(&self) -> &IbeMasterKey {
+        &self.keys[&self.current_version]
+    }
+
+    pub fn get(&self, version: u32) -> Option<&IbeMasterKey> {
+        self.keys.get(&version)
+    }
+
+    /// The version of the key with the given public key, if any.
+    pub fn version_of(&self, public_key: &IbePublicKey) -> Option<u32> {
+        self.keys
+            .iter()
+            .find(|(_, key)| ibe::public_key_from_master_key(key) == *public_key)
+            .map(|(version, _)| *version)
+    }
+
+    /// The lowest version, which is served to requests that do not specify a version until the
+    /// public key on-chain is known.
+    pub fn oldest_version(&self) -> u32 {
+        *self
+            .keys
+            .keys()
+            .next()
+            .expect("should have at least one key")
+    }
+
+    pub fn is_rotating(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    fn log_public_keys(&self, owner: &str) {
+        for (version, key) in &self.keys {
+            info!(
+                "{} uses public key with version {}{}: {:?}",
+                owner,
+                version,
+                if *version == self.current_version {
+                    ""
+                } else {
+                    " (deprecated)"
+                },
+                DefaultEncoding::encode(ibe::public_key_from_master_key(key).to_byte_array())
+            );
+        }
+    }
+}
+
 /// Represents the set of master keys held by a key server.
 #[derive(Clone)]
 pub enum MasterKeys {
     /// In open mode, the key server has a single master key used for all packages.
-    Open { master_key: IbeMasterKey },
+    Open { master_keys: VersionedKeys },
     /// In permissioned mode, the key server has a mapping of package IDs to master keys.
     Permissioned {
-        pkg_id_to_key: HashMap<ObjectID, IbeMasterKey>,
-        key_server_oid_to_key: HashMap<ObjectID, IbeMasterKey>,
+        pkg_id_to_key: HashMap<ObjectID, VersionedKeys>,
+        key_server_oid_to_key: HashMap<ObjectID, VersionedKeys>,
+    },
+    /// In committee mode, keys are computed by the members of a committee, see [crate::committee].
+    Committee { committee: Arc<Committee> },
+    /// A committee member holds a share of the master key of the committee.
+    CommitteeMember {
+        key_server_object_id: ObjectID,
+        share: MasterKeyShare,
+        /// The token the coordinator must present.
+        token: String,
     },
 }
 
 impl MasterKeys {
     pub(crate) fn load(options: &KeyServerOptions) -> anyhow::Result<Self> {
-        info!("Loading keys from env variables");
+        info!("Loading keys");
         match &options.server_mode {
-            ServerMode::Open { .. } => {
-                let master_key = match decode_master_key::<DefaultEncoding>(MASTER_KEY_ENV_VAR) {
-                    Ok(master_key) => master_key,
+            ServerMode::Open {
+                key_server_object_id,
+                master_key_source,
+                master_key_version,
+                deprecated_master_keys,
+            } => {
+                let master_key = match master_key_source {
+                    Some(source) => source.load_master_key()?,
+                    None => match decode_master_key::<DefaultEncoding>(MASTER_KEY_ENV_VAR) {
+                        Ok(master_key) => master_key,
 
-                    // TODO: Fallback to Base64 encoding for backward compatibility.
-                    Err(_) => crate::utils::decode_master_key::<Base64>(MASTER_KEY_ENV_VAR)?,
+                        // TODO: Fallback to Base64 encoding for backward compatibility.
+                        Err(_) => crate::utils::decode_master_key::<Base64>(MASTER_KEY_ENV_VAR)?,
+                    },
+                };
+                let mut keys = BTreeMap::from([(*master_key_version, master_key)]);
+                for deprecated in deprecated_master_keys {
+                    keys.insert(deprecated.version, deprecated.key_source.load_master_key()?);
+                }
+                let master_keys = VersionedKeys {
+                    key_server_object_id: *key_server_object_id,
+                    current_version: *master_key_version,
+                    keys,
                 };
-                Ok(MasterKeys::Open { master_key })
+                if master_keys.is_rotating() {
+                    master_keys.log_public_keys("Key server");
+                }
+                Ok(MasterKeys::Open { master_keys })
             }
             ServerMode::Permissioned { client_configs } => {
                 let mut pkg_id_to_key = HashMap::new();
                 let mut key_server_oid_to_key = HashMap::new();
                 let seed = decode_byte_array::<DefaultEncoding, SEED_LENGTH>(MASTER_SEED_ENV_VAR)?;
                 for config in client_configs {
-                    let master_key = match &config.client_master_key {
-                        ClientKeyType::Derived { derivation_index } => {
-                            ibe::derive_master_key(&seed, *derivation_index)
-                        }
-                        ClientKeyType::Imported { env_var } => {
-                            decode_master_key::<DefaultEncoding>(env_var)?
+                    let Some(master_key) =
+                        Self::load_client_key(&config.client_master_key, &seed, &config.name)?
+                    else {
+                        continue;
+                    };
+                    let mut keys = BTreeMap::from([(config.key_version, master_key)]);
+                    for deprecated in &config.deprecated_keys {
+                        if let Some(key) = Self::load_client_key(
+                            &deprecated.client_master_key,
+                            &seed,
+                            &config.name,
+                        )? {
+                            keys.insert(deprecated.version, key);
                         }
-                        ClientKeyType::Exported { .. } => continue,
+                    }
+                    let master_keys = VersionedKeys {
+                        key_server_object_id: config.key_server_object_id,
+                        current_version: config.key_version,
+                        keys,
                     };
-
-                    info!(
-                        "Client {:?} uses public key: {:?}",
-                        config.name,
-                        DefaultEncoding::encode(
-                            ibe::public_key_from_master_key(&master_key).to_byte_array()
-                        )
-                    );
+                    master_keys.log_public_keys(&format!("Client {:?}", config.name));
 
                     for pkg_id in &config.package_ids {
-                        pkg_id_to_key.insert(*pkg_id, master_key);
+                        pkg_id_to_key.insert(*pkg_id, master_keys.clone());
                     }
-                    key_server_oid_to_key.insert(config.key_server_object_id, master_key);
+                    key_server_oid_to_key.insert(config.key_server_object_id, master_keys);
                 }
 
                 Self::log_unassigned_public_keys(client_configs, &seed);
@@ -87,17 +181,89 @@ impl MasterKeys {
                     key_server_oid_to_key,
                 })
             }
+            ServerMode::Committee {
+                key_server_object_id,
+                threshold,
+                members,
+                token_env_var,
+            } => {
+                let committee =
+                    Committee::new(*key_server_object_id, *threshold, members, token_env_var)?;
+                info!(
+                    "Committee with threshold {} of {} uses public key: {:?}",
+                    threshold,
+                    members.len(),
+                    DefaultEncoding::encode(committee.public_key.to_byte_array())
+                );
+                Ok(MasterKeys::Committee {
+                    committee: Arc::new(committee),
+                })
+            }
+            ServerMode::CommitteeMember {
+                key_server_object_id,
+                index,
+                share_source,
+                token_env_var,
+                ..
+            } => {
+                let share = MasterKeyShare {
+                    index: *index,
+                    share: share_source.load_master_key()?,
+                };
+                info!(
+                    "Committee member {} uses public key share: {:?}",
+                    index,
+                    DefaultEncoding::encode(public_key_share(&share).value.to_byte_array())
+                );
+                Ok(MasterKeys::CommitteeMember {
+                    key_server_object_id: *key_server_object_id,
+                    share,
+                    token: load_token(token_env_var)?,
+                })
+            }
         }
     }
 
+    /// Load the key of a permissioned client. Returns None if the key was exported.
+    fn load_client_key(
+        key: &ClientKeyType,
+        seed: &[u8; SEED_LENGTH],
+        name: &str,
+    ) -> anyhow::Result<Option<IbeMasterKey>> {
+        Ok(Some(match key {
+            ClientKeyType::Derived { derivation_index } => {
+                ibe::derive_master_key(seed, *derivation_index)
+            }
+            ClientKeyType::Imported {
+                key_source: Some(source),
+                ..
+            } => source.load_master_key()?,
+            ClientKeyType::Imported {
+                env_var: Some(env_var),
+                ..
+            } => decode_master_key::<DefaultEncoding>(env_var)?,
+            ClientKeyType::Imported { .. } => {
+                return Err(anyhow!(
+                    "Imported key of client {} has no env_var or key_source",
+                    name
+                ))
+            }
+            ClientKeyType::Exported { .. } => return Ok(None),
+        }))
+    }
+
     /// Log the next 10 unassigned public keys.
     /// This is done to make it easier to find a public key of a derived key that's not yet assigned to a client.
-    /// Can be removed once an endpoint to get public keys from derivation indices is implemented.
+    /// Use `seal-cli derive-range` to get the public keys of arbitrary derivation indices.
     fn log_unassigned_public_keys(client_configs: &[ClientConfig], seed: &[u8; SEED_LENGTH]) {
         // The derivation indices are in incremental order, so the next free index is the max + 1 or 0 if no derivation indices are used.
         let next_free_derivation_index = client_configs
             .iter()
-            .filter_map(|c| match &c.client_master_key {
+            .flat_map(|c| {
+                std::iter::once(&c.client_master_key)
+                    .chain(c.deprecated_keys.iter().map(|k| &k.client_master_key))
+            })
+            .filter_map(|key| match key {
                 ClientKeyType::Derived { derivation_index } => Some(*derivation_index),
                 ClientKeyType::Exported {
                     deprecated_derivation_index,
@@ -118,40 +284,64 @@ impl MasterKeys {
     }
 
     pub(crate) fn has_key_for_package(&self, id: &ObjectID) -> anyhow::Result<(), InternalError> {
-        self.get_key_for_package(id).map(|_| ())
+        match self {
+            // Like in open mode, all packages are supported
+            MasterKeys::Committee { .. } => Ok(()),
+            _ => self.get_key_for_package(id).map(|_| ()),
+        }
     }
 
+    /// The keys of the key server object serving the given package. A committee has no keys, see
+    /// [Committee::extract].
     pub(crate) fn get_key_for_package(
         &self,
         package_id: &ObjectID,
-    ) -> anyhow::Result<&IbeMasterKey, InternalError> {
+    ) -> anyhow::Result<&VersionedKeys, InternalError> {
         match self {
-            MasterKeys::Open { master_key } => Ok(master_key),
+            MasterKeys::Open { master_keys } => Ok(master_keys),
             MasterKeys::Permissioned { pkg_id_to_key, .. } => pkg_id_to_key
                 .get(package_id)
                 .ok_or(InternalError::UnsupportedPackageId),
+            MasterKeys::Committee { .. } | MasterKeys::CommitteeMember { .. } => {
+                Err(InternalError::UnsupportedPackageId)
+            }
         }
     }
 
     pub(crate) fn get_key_for_key_server(
         &self,
         key_server_object_id: &ObjectID,
-    ) -> anyhow::Result<&IbeMasterKey, InternalError> {
+    ) -> anyhow::Result<&VersionedKeys, InternalError> {
         match self {
-            MasterKeys::Open { master_key } => Ok(master_key),
+            MasterKeys::Open { master_keys } => Ok(master_keys),
             MasterKeys::Permissioned {
                 key_server_oid_to_key,
                 ..
             } => key_server_oid_to_key
                 .get(key_server_object_id)
                 .ok_or(InternalError::InvalidServiceId),
+            MasterKeys::Committee { .. } | MasterKeys::CommitteeMember { .. } => {
+                Err(InternalError::InvalidServiceId)
+            }
+        }
+    }
+
+    /// The keys of all key server objects.
+    pub(crate) fn all(&self) -> Vec<&VersionedKeys> {
+        match self {
+            MasterKeys::Open { master_keys } => vec![master_keys],
+            MasterKeys::Permissioned {
+                key_server_oid_to_key,
+                ..
+            } => key_server_oid_to_key.values().collect(),
+            MasterKeys::Committee { .. } | MasterKeys::CommitteeMember { .. } => vec![],
         }
     }
 }
 
 #[test]
 fn test_master_keys_open_mode() {
-    use crate::key_server_options::KeyServerOptions;
+    use crate::key_server_options::{DeprecatedMasterKey, KeyServerOptions, KeySource};
     use crate::types::{IbeMasterKey, Network};
     use crate::DefaultEncoding;
     use fastcrypto::encoding::Encoding;
@@ -175,10 +365,49 @@ fn test_master_keys_open_mode() {
         assert_eq!(
             mk.unwrap()
                 .get_key_for_package(&ObjectID::from_hex_literal("0x1").unwrap())
-                .unwrap(),
+                .unwrap()
+                .current(),
             &sk
         );
     });
+
+    // Deprecated keys are served during a rotation
+    let mut options = options;
+    options.server_mode = ServerMode::Open {
+        key_server_object_id: ObjectID::from_hex_literal("0x2").unwrap(),
+        master_key_source: None,
+        master_key_version: 1,
+        deprecated_master_keys: vec![DeprecatedMasterKey {
+            version: 0,
+            key_source: KeySource::Env {
+                env_var: "OLD_MASTER_KEY".to_string(),
+            },
+        }],
+    };
+    let old_sk = IbeMasterKey::generator() + IbeMasterKey::generator();
+    with_vars(
+        [
+            ("MASTER_KEY", Some(sk_as_bytes.clone())),
+            (
+                "OLD_MASTER_KEY",
+                Some(DefaultEncoding::encode(bcs::to_bytes(&old_sk).unwrap())),
+            ),
+        ],
+        || {
+            let mk = MasterKeys::load(&options).unwrap();
+            let keys = mk
+                .get_key_for_key_server(&ObjectID::from_hex_literal("0x2").unwrap())
+                .unwrap();
+            assert!(keys.is_rotating());
+            assert_eq!(keys.current(), &sk);
+            assert_eq!(keys.get(0), Some(&old_sk));
+            assert_eq!(
+                keys.version_of(&ibe::public_key_from_master_key(&old_sk)),
+                Some(0)
+            );
+            assert_eq!(keys.get(2), None);
+        },
+    );
 }
 
 #[test]
@@ -200,8 +429,13 @@ fn test_master_keys_permissioned_mode() {
                 package_ids: vec![ObjectID::from_hex_literal("0x1").unwrap()],
                 key_server_object_id: ObjectID::from_hex_literal("0x2").unwrap(),
                 client_master_key: ClientKeyType::Imported {
-                    env_var: "ALICE_KEY".to_string(),
+                    env_var: Some("ALICE_KEY".to_string()),
+                    key_source: None,
                 },
+                api_key_env_var: None,
+                quota: None,
+                key_version: 0,
+                deprecated_keys: vec![],
             },
             ClientConfig {
                 name: "bob".to_string(),
@@ -210,6 +444,10 @@ fn test_master_keys_permissioned_mode() {
                 client_master_key: ClientKeyType::Derived {
                     derivation_index: 100,
                 },
+                api_key_env_var: None,
+                quota: None,
+                key_version: 0,
+                deprecated_keys: vec![],
             },
             ClientConfig {
                 name: "dan".to_string(),
@@ -218,6 +456,10 @@ fn test_master_keys_permissioned_mode() {
                 client_master_key: ClientKeyType::Derived {
                     derivation_index: 200,
                 },
+                api_key_env_var: None,
+                quota: None,
+                key_version: 0,
+                deprecated_keys: vec![],
             },
         ],
     };
@@ -231,8 +473,12 @@ fn test_master_keys_permissioned_mode() {
         ],
         || {
             let mk = MasterKeys::load(&options).unwrap();
-            let k1 = mk.get_key_for_key_server(&ObjectID::from_hex_literal("0x4").unwrap());
-            let k2 = mk.get_key_for_key_server(&ObjectID::from_hex_literal("0x6").unwrap());
+            let k1 = mk
+                .get_key_for_key_server(&ObjectID::from_hex_literal("0x4").unwrap())
+                .map(VersionedKeys::current);
+            let k2 = mk
+                .get_key_for_key_server(&ObjectID::from_hex_literal("0x6").unwrap())
+                .map(VersionedKeys::current);
             assert!(k1.is_ok());
             assert_ne!(k1, k2);
         },
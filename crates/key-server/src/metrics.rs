@@ -1,6 +1,7 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::policy::MoveAbort;
 use axum::{extract::State, middleware};
 use prometheus::{
     register_histogram_vec_with_registry, register_histogram_with_registry,
@@ -10,6 +11,7 @@ use prometheus::{
 };
 use std::sync::Arc;
 use std::time::Instant;
+use sui_types::base_types::ObjectID;
 
 #[derive(Debug)]
 pub(crate) struct Metrics {
@@ -22,6 +24,9 @@ pub(crate) struct Metrics {
     /// Total number of internal errors by type
     errors: IntCounterVec,
 
+    /// Total number of Move aborts when evaluating policies by abort code, see [abort_code_label]
+    policy_aborts: IntCounterVec,
+
     /// Delay of timestamp of the latest checkpoint
     pub checkpoint_timestamp_delay: Histogram,
 
@@ -37,12 +42,41 @@ pub(crate) struct Metrics {
     /// Duration of check_policy
     pub check_policy_duration: Histogram,
 
+    /// Total number of lookups in the policy decision cache by result (hit or miss)
+    policy_cache_lookups: IntCounterVec,
+
+    /// Total number of requests rejected by a rate limit by limit type
+    rate_limited_requests: IntCounterVec,
+
+    /// Total number of requests of permissioned clients by client name
+    client_requests: IntCounterVec,
+
+    /// Total number of requests rejected by a client quota by client name and period
+    client_quota_exceeded: IntCounterVec,
+
+    /// Total number of configuration reloads by status (success or failure)
+    config_reloads: IntCounterVec,
+
+    /// Total number of requests served with a deprecated master key by key server object and key
+    /// version
+    deprecated_key_requests: IntCounterVec,
+
+    /// The version of the master key whose public key is on-chain by key server object, or -1 if it
+    /// does not match any key
+    onchain_key_version: IntGaugeVec,
+
+    /// Total number of audit records that could not be written
+    pub audit_log_failures: IntCounter,
+
     /// Duration of fetch_pkg_ids
     pub fetch_pkg_ids_duration: Histogram,
 
     /// Total number of requests per number of ids
     pub requests_per_number_of_ids: Histogram,
 
+    /// Total number of batch requests per number of bundles
+    pub requests_per_number_of_bundles: Histogram,
+
     /// HTTP request latency by route and status code
     pub http_request_duration_millis: HistogramVec,
 
@@ -72,6 +106,13 @@ impl Metrics {
                 registry
             )
             .unwrap(),
+            policy_aborts: register_int_counter_vec_with_registry!(
+                "policy_aborts",
+                "Total number of Move aborts when evaluating policies by abort code",
+                &["code"],
+                registry
+            )
+            .unwrap(),
             service_requests: register_int_counter_with_registry!(
                 "service_requests",
                 "Total number of service requests received",
@@ -113,6 +154,61 @@ impl Metrics {
                 registry
             )
             .unwrap(),
+            policy_cache_lookups: register_int_counter_vec_with_registry!(
+                "policy_cache_lookups",
+                "Total number of lookups in the policy decision cache by result",
+                &["result"],
+                registry
+            )
+            .unwrap(),
+            rate_limited_requests: register_int_counter_vec_with_registry!(
+                "rate_limited_requests",
+                "Total number of requests rejected by a rate limit by limit type",
+                &["limit_type"],
+                registry
+            )
+            .unwrap(),
+            client_requests: register_int_counter_vec_with_registry!(
+                "client_requests",
+                "Total number of requests of permissioned clients by client name",
+                &["client"],
+                registry
+            )
+            .unwrap(),
+            client_quota_exceeded: register_int_counter_vec_with_registry!(
+                "client_quota_exceeded",
+                "Total number of requests rejected by a client quota by client name and period",
+                &["client", "period"],
+                registry
+            )
+            .unwrap(),
+            config_reloads: register_int_counter_vec_with_registry!(
+                "config_reloads",
+                "Total number of configuration reloads by status (success or failure)",
+                &["status"],
+                registry
+            )
+            .unwrap(),
+            deprecated_key_requests: register_int_counter_vec_with_registry!(
+                "deprecated_key_requests",
+                "Total number of requests served with a deprecated master key by key server object and key version",
+                &["key_server_object_id", "version"],
+                registry
+            )
+            .unwrap(),
+            onchain_key_version: register_int_gauge_vec_with_registry!(
+                "onchain_key_version",
+                "The version of the master key whose public key is on-chain by key server object",
+                &["key_server_object_id"],
+                registry
+            )
+            .unwrap(),
+            audit_log_failures: register_int_counter_with_registry!(
+                "audit_log_failures",
+                "Total number of audit records that could not be written",
+                registry
+            )
+            .unwrap(),
             get_reference_gas_price_status: register_int_counter_vec_with_registry!(
                 "get_reference_gas_price_status",
                 "Status of requests of getting the reference gas price",
@@ -127,6 +223,13 @@ impl Metrics {
                 registry
             )
             .unwrap(),
+            requests_per_number_of_bundles: register_histogram_with_registry!(
+                "requests_per_number_of_bundles",
+                "Total number of batch requests per number of bundles",
+                buckets(1.0, 10.0, 1.0),
+                registry
+            )
+            .unwrap(),
             http_request_duration_millis: register_histogram_vec_with_registry!(
                 "http_request_duration_millis",
                 "HTTP request duration in milliseconds",
@@ -163,6 +266,69 @@ impl Metrics {
     pub(crate) fn observe_error(&self, error_type: &str) {
         self.errors.with_label_values(&[error_type]).inc();
     }
+
+    pub(crate) fn observe_policy_abort(&self, abort: &MoveAbort) {
+        self.policy_aborts
+            .with_label_values(&[abort_code_label(abort.code).as_str()])
+            .inc();
+    }
+
+    pub(crate) fn observe_policy_cache_lookup(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.policy_cache_lookups.with_label_values(&[result]).inc();
+    }
+
+    pub(crate) fn observe_rate_limited(&self, limit_type: &str) {
+        self.rate_limited_requests
+            .with_label_values(&[limit_type])
+            .inc();
+    }
+
+    pub(crate) fn observe_client_request(&self, client: &str) {
+        self.client_requests.with_label_values(&[client]).inc();
+    }
+
+    pub(crate) fn observe_quota_exceeded(&self, client: &str, period: &str) {
+        self.client_quota_exceeded
+            .with_label_values(&[client, period])
+            .inc();
+    }
+
+    pub(crate) fn observe_config_reload(&self, success: bool) {
+        let status = if success { "success" } else { "failure" };
+        self.config_reloads.with_label_values(&[status]).inc();
+    }
+
+    pub(crate) fn observe_deprecated_key_request(
+        &self,
+        key_server_object_id: &ObjectID,
+        version: u32,
+    ) {
+        self.deprecated_key_requests
+            .with_label_values(&[&key_server_object_id.to_string(), &version.to_string()])
+            .inc();
+    }
+
+    /// The number of requests served with the given deprecated key since the server started.
+    pub(crate) fn deprecated_key_requests(
+        &self,
+        key_server_object_id: &ObjectID,
+        version: u32,
+    ) -> u64 {
+        self.deprecated_key_requests
+            .with_label_values(&[&key_server_object_id.to_string(), &version.to_string()])
+            .get()
+    }
+
+    pub(crate) fn observe_onchain_key_version(
+        &self,
+        key_server_object_id: &ObjectID,
+        version: Option<u32>,
+    ) {
+        self.onchain_key_version
+            .with_label_values(&[&key_server_object_id.to_string()])
+            .set(version.map_or(-1, i64::from));
+    }
 }
 
 /// If metrics is Some, apply the closure and measure the duration of the closure and call set_duration with the duration.
@@ -200,6 +366,16 @@ pub(crate) fn status_callback(metrics: &IntCounterVec) -> impl Fn(bool) + use<>
     }
 }
 
+/// Abort codes are chosen by the policies, so only small codes get their own label to keep the
+/// number of time series bounded.
+fn abort_code_label(code: u64) -> String {
+    match code {
+        0..=9 => code.to_string(),
+        10..=99 => "10-99".to_string(),
+        _ => "100+".to_string(),
+    }
+}
+
 fn buckets(start: f64, end: f64, step: f64) -> Vec<f64> {
     let mut buckets = vec![];
     let mut current = start;
@@ -0,0 +1,153 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Interpretation of the execution errors of a policy evaluation.
+//!
+//! The full node reports the failure of a dry run or dev-inspect as the debug representation of
+//! the execution failure status, e.g.,
+//! `MoveAbort(MoveLocation { module: ModuleId { address: 0000..0002, name: Identifier("whitelist") }, function: 1, instruction: 12, function_name: Some("check_policy") }, 1) in command 0`.
+
+use crate::errors::InternalError;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// A Move abort raised while evaluating a `seal_approve*` function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct MoveAbort {
+    /// The name of the module that aborted.
+    pub module: String,
+    /// The name of the function that aborted, if known.
+    pub function: Option<String>,
+    /// The abort code.
+    pub code: u64,
+}
+
+impl MoveAbort {
+    /// Parse a Move abort from an execution error. Returns None if the error is not a Move abort.
+    pub fn parse(error: &str) -> Option<Self> {
+        let abort = error.strip_prefix("MoveAbort(")?;
+        let module = between(abort, "name: Identifier(\"", "\")")?.to_string();
+        let function = between(abort, "function_name: Some(\"", "\")").map(str::to_string);
+        let (_, code) = abort.rsplit_once("}, ")?;
+        let code = code.split(')').next()?.parse().ok()?;
+        Some(MoveAbort {
+            module,
+            function,
+            code,
+        })
+    }
+}
+
+impl Display for MoveAbort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}::{} aborted with code {}",
+            self.module,
+            self.function.as_deref().unwrap_or("<unknown>"),
+            self.code
+        )
+    }
+}
+
+/// Returns the part of `s` between the first occurrence of `start` and the following `end`.
+fn between<'a>(s: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let (_, rest) = s.split_once(start)?;
+    rest.split_once(end).map(|(inner, _)| inner)
+}
+
+/// The name of the execution failure, e.g., `MoveAbort` or `InsufficientGas`.
+fn failure_name(error: &str) -> &str {
+    error
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .next()
+        .unwrap_or_default()
+}
+
+/// Map the execution error of a failed policy evaluation to the error returned to the client.
+pub(crate) fn execution_error_to_internal_error(error: &str) -> InternalError {
+    match failure_name(error) {
+        // The policy denied access
+        "MoveAbort" => InternalError::NoAccess,
+        // The PTB does not call the seal_approve functions correctly
+        name @ ("FunctionNotFound"
+        | "NonEntryFunctionInvoked"
+        | "ArityMismatch"
+        | "TypeArityMismatch"
+        | "CommandArgumentError"
+        | "TypeArgumentError"
+        | "UnusedValueWithoutDrop"
+        | "InvalidPublicFunctionReturnType") => {
+            InternalError::InvalidPTB(format!("Invalid seal_approve call ({})", name))
+        }
+        // An object used by the PTB is no longer available
+        "InputObjectDeleted" => InternalError::InvalidParameter,
+        "InsufficientGas" => InternalError::InsufficientGas,
+        // Other errors raised while executing the policy, e.g., arithmetic errors
+        name => InternalError::PolicyExecutionError(name.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOVE_ABORT: &str = "MoveAbort(MoveLocation { module: ModuleId { address: 2d5e9b6a1c18e9f5a2c0a1f8f1c2e5d6b7a8c9d0e1f2a3b4c5d6e7f8a9b0c1d2, name: Identifier(\"whitelist\") }, function: 1, instruction: 12, function_name: Some(\"check_policy\") }, 77) in command 0";
+
+    #[test]
+    fn test_parse_move_abort() {
+        assert_eq!(
+            MoveAbort::parse(MOVE_ABORT),
+            Some(MoveAbort {
+                module: "whitelist".to_string(),
+                function: Some("check_policy".to_string()),
+                code: 77,
+            })
+        );
+
+        let without_function_name = MOVE_ABORT.replace("Some(\"check_policy\")", "None");
+        assert_eq!(
+            MoveAbort::parse(&without_function_name),
+            Some(MoveAbort {
+                module: "whitelist".to_string(),
+                function: None,
+                code: 77,
+            })
+        );
+
+        assert_eq!(
+            MoveAbort::parse(MOVE_ABORT).unwrap().to_string(),
+            "whitelist::check_policy aborted with code 77"
+        );
+        assert_eq!(MoveAbort::parse("InsufficientGas"), None);
+        assert_eq!(MoveAbort::parse("MoveAbort(garbage"), None);
+    }
+
+    #[test]
+    fn test_execution_error_to_internal_error() {
+        assert_eq!(
+            execution_error_to_internal_error(MOVE_ABORT),
+            InternalError::NoAccess
+        );
+        assert_eq!(
+            execution_error_to_internal_error("InsufficientGas"),
+            InternalError::InsufficientGas
+        );
+        assert_eq!(
+            execution_error_to_internal_error(
+                "CommandArgumentError { arg_idx: 1, kind: TypeMismatch } in command 0"
+            ),
+            InternalError::InvalidPTB("Invalid seal_approve call (CommandArgumentError)".into())
+        );
+        assert_eq!(
+            execution_error_to_internal_error("InputObjectDeleted"),
+            InternalError::InvalidParameter
+        );
+        assert_eq!(
+            execution_error_to_internal_error(
+                "MovePrimitiveRuntimeError(MoveLocationOpt(None)) in command 0"
+            ),
+            InternalError::PolicyExecutionError("MovePrimitiveRuntimeError".to_string())
+        );
+    }
+}
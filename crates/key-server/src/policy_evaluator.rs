@@ -0,0 +1,316 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Backends for evaluating the `seal_approve*` functions of a request.
+//!
+//! [Server::check_policy](crate::Server) only interprets the execution status returned by a
+//! [PolicyEvaluator], so the evaluation can either be delegated to the full node
+//! ([RpcPolicyEvaluator]) or done by the key server itself
+//! ([LocalPolicyEvaluator](crate::local_policy_evaluator::LocalPolicyEvaluator)).
+
+use crate::cache::lru_cache;
+use crate::errors::InternalError;
+use crate::key_server_options::{KeyServerOptions, PolicyCacheConfig, PolicyEvaluation};
+use crate::local_policy_evaluator::{CachedObjectStore, LocalPolicyEvaluator, RpcObjectStore};
+use crate::metrics::Metrics;
+use crate::sui_rpc_client::SuiRpcClient;
+use crate::GAS_BUDGET;
+use async_trait::async_trait;
+use jsonrpsee::core::ClientError;
+use jsonrpsee::types::error::{INVALID_PARAMS_CODE, METHOD_NOT_FOUND_CODE};
+use moka::sync::Cache;
+use std::sync::Arc;
+use sui_sdk::error::Error;
+use sui_sdk::rpc_types::{SuiExecutionStatus, SuiTransactionBlockEffectsAPI};
+use sui_types::base_types::SuiAddress;
+use sui_types::transaction::{ProgrammableTransaction, TransactionKind};
+use tokio::sync::watch::Receiver;
+use tracing::{debug, warn};
+
+/// Evaluates the `seal_approve*` functions of a PTB on behalf of a sender.
+#[async_trait]
+pub(crate) trait PolicyEvaluator: Send + Sync {
+    /// Execute the PTB and return its execution status. An error is only returned if the PTB could
+    /// not be evaluated, e.g., because an input object does not exist or the full node is not
+    /// available. A failing policy is reported as [SuiExecutionStatus::Failure].
+    async fn evaluate(
+        &self,
+        sender: SuiAddress,
+        ptb: &ProgrammableTransaction,
+        gas_price: u64,
+        req_id: Option<&str>,
+    ) -> Result<SuiExecutionStatus, InternalError>;
+}
+
+/// Create the policy evaluator configured by [KeyServerOptions::policy_evaluation], wrapped in a
+/// [CachedPolicyEvaluator] if [KeyServerOptions::policy_cache] is set.
+pub(crate) fn create_policy_evaluator(
+    options: &KeyServerOptions,
+    sui_rpc_client: &SuiRpcClient,
+    latest_checkpoint_sequence_number: Receiver<u64>,
+) -> Arc<dyn PolicyEvaluator> {
+    let evaluator: Arc<dyn PolicyEvaluator> = match options.policy_evaluation {
+        PolicyEvaluation::DryRun => {
+            Arc::new(RpcPolicyEvaluator::new(sui_rpc_client.clone(), false))
+        }
+        PolicyEvaluation::DevInspect => {
+            Arc::new(RpcPolicyEvaluator::new(sui_rpc_client.clone(), true))
+        }
+        PolicyEvaluation::Local => Arc::new(LocalPolicyEvaluator::new(
+            CachedObjectStore::new(RpcObjectStore::new(sui_rpc_client.clone())),
+            &options.network,
+        )),
+    };
+    match &options.policy_cache {
+        Some(config) => Arc::new(CachedPolicyEvaluator::new(
+            evaluator,
+            config,
+            latest_checkpoint_sequence_number,
+            sui_rpc_client.get_metrics(),
+        )),
+        None => evaluator,
+    }
+}
+
+/// Evaluates policies on the full node, either by a dry run or by dev-inspect.
+pub(crate) struct RpcPolicyEvaluator {
+    sui_rpc_client: SuiRpcClient,
+    dev_inspect: bool,
+}
+
+impl RpcPolicyEvaluator {
+    pub fn new(sui_rpc_client: SuiRpcClient, dev_inspect: bool) -> Self {
+        Self {
+            sui_rpc_client,
+            dev_inspect,
+        }
+    }
+}
+
+#[async_trait]
+impl PolicyEvaluator for RpcPolicyEvaluator {
+    async fn evaluate(
+        &self,
+        sender: SuiAddress,
+        ptb: &ProgrammableTransaction,
+        gas_price: u64,
+        req_id: Option<&str>,
+    ) -> Result<SuiExecutionStatus, InternalError> {
+        let tx_kind = TransactionKind::ProgrammableTransaction(ptb.clone());
+        if self.dev_inspect {
+            let dev_inspect_res = self
+                .sui_rpc_client
+                .dev_inspect_transaction_block(sender, tx_kind)
+                .await
+                .map_err(|e| rpc_error_to_internal_error(e, req_id))?;
+            debug!(
+                "Dev inspect response: {:?} (req_id: {:?})",
+                dev_inspect_res, req_id
+            );
+            Ok(dev_inspect_res.effects.status().clone())
+        } else {
+            let tx_data = self
+                .sui_rpc_client
+                .sui_client()
+                .transaction_builder()
+                .tx_data_for_dry_run(sender, tx_kind, GAS_BUDGET, gas_price, None, None)
+                .await;
+            let dry_run_res = self
+                .sui_rpc_client
+                .dry_run_transaction_block(tx_data)
+                .await
+                .map_err(|e| rpc_error_to_internal_error(e, req_id))?;
+            debug!("Dry run response: {:?} (req_id: {:?})", dry_run_res, req_id);
+            Ok(dry_run_res.effects.status().clone())
+        }
+    }
+}
+
+/// The key of a cached policy decision. The PTB is identified by its BCS encoding.
+#[derive(Hash, PartialEq, Eq)]
+struct PolicyCacheKey {
+    sender: SuiAddress,
+    ptb: Vec<u8>,
+    checkpoint: u64,
+}
+
+/// Caches the successful decisions of another [PolicyEvaluator].
+///
+/// Besides expiring after the TTL, decisions are only reused while the latest checkpoint known to
+/// the server is the same, so a decision is never reused once a newer checkpoint has been seen.
+/// Denied requests are not cached, so access granted onchain is reflected immediately.
+pub(crate) struct CachedPolicyEvaluator {
+    inner: Arc<dyn PolicyEvaluator>,
+    cache: Cache<PolicyCacheKey, ()>,
+    latest_checkpoint_sequence_number: Receiver<u64>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl CachedPolicyEvaluator {
+    pub fn new(
+        inner: Arc<dyn PolicyEvaluator>,
+        config: &PolicyCacheConfig,
+        latest_checkpoint_sequence_number: Receiver<u64>,
+        metrics: Option<Arc<Metrics>>,
+    ) -> Self {
+        Self {
+            inner,
+            cache: lru_cache(config.ttl.as_millis() as u64, config.size),
+            latest_checkpoint_sequence_number,
+            metrics,
+        }
+    }
+}
+
+#[async_trait]
+impl PolicyEvaluator for CachedPolicyEvaluator {
+    async fn evaluate(
+        &self,
+        sender: SuiAddress,
+        ptb: &ProgrammableTransaction,
+        gas_price: u64,
+        req_id: Option<&str>,
+    ) -> Result<SuiExecutionStatus, InternalError> {
+        let key = PolicyCacheKey {
+            sender,
+            ptb: bcs::to_bytes(ptb).expect("serialization should not fail"),
+            checkpoint: *self.latest_checkpoint_sequence_number.borrow(),
+        };
+        let hit = self.cache.contains_key(&key);
+        if let Some(m) = &self.metrics {
+            m.observe_policy_cache_lookup(hit);
+        }
+        if hit {
+            debug!("Policy decision found in cache (req_id: {:?})", req_id);
+            return Ok(SuiExecutionStatus::Success);
+        }
+
+        let status = self.inner.evaluate(sender, ptb, gas_price, req_id).await?;
+        if status.is_ok() {
+            self.cache.insert(key, ());
+        }
+        Ok(status)
+    }
+}
+
+/// Map an error from evaluating a policy on the full node to an [InternalError].
+fn rpc_error_to_internal_error(e: Error, req_id: Option<&str>) -> InternalError {
+    if let Error::RpcError(ClientError::Call(ref e)) = e {
+        match e.code() {
+            INVALID_PARAMS_CODE => {
+                // A dry run will fail if called with a newly created object parameter that the FN has not yet seen.
+                // In that case, the user gets a FORBIDDEN status response.
+                debug!(
+                    "Invalid parameter: This could be because the FN has not yet seen the object."
+                );
+                return InternalError::InvalidParameter;
+            }
+            METHOD_NOT_FOUND_CODE => {
+                // This means that the seal_approve function is not found on the given module.
+                debug!("Function not found: {:?}", e);
+                return InternalError::InvalidPTB(
+                    "The seal_approve function was not found on the module".to_string(),
+                );
+            }
+            _ => {}
+        }
+    }
+    warn!("Policy evaluation failed ({:?}) (req_id: {:?})", e, req_id);
+    InternalError::Failure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use sui_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+    use tokio::sync::watch::channel;
+
+    /// Grants access to a fixed sender and counts the number of evaluations.
+    struct StubPolicyEvaluator {
+        allowed: SuiAddress,
+        evaluations: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PolicyEvaluator for StubPolicyEvaluator {
+        async fn evaluate(
+            &self,
+            sender: SuiAddress,
+            _ptb: &ProgrammableTransaction,
+            _gas_price: u64,
+            _req_id: Option<&str>,
+        ) -> Result<SuiExecutionStatus, InternalError> {
+            self.evaluations.fetch_add(1, Ordering::Relaxed);
+            Ok(if sender == self.allowed {
+                SuiExecutionStatus::Success
+            } else {
+                SuiExecutionStatus::Failure {
+                    error: "MoveAbort".to_string(),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_policy_evaluator() {
+        let allowed = SuiAddress::random_for_testing_only();
+        let denied = SuiAddress::random_for_testing_only();
+        let inner = Arc::new(StubPolicyEvaluator {
+            allowed,
+            evaluations: AtomicUsize::new(0),
+        });
+        let (checkpoint, checkpoint_receiver) = channel(1);
+        let evaluator = CachedPolicyEvaluator::new(
+            inner.clone(),
+            &PolicyCacheConfig {
+                ttl: Duration::from_secs(60),
+                size: 10,
+            },
+            checkpoint_receiver,
+            None,
+        );
+        let evaluations = || inner.evaluations.load(Ordering::Relaxed);
+
+        let ptb = ProgrammableTransactionBuilder::new().finish();
+        let mut builder = ProgrammableTransactionBuilder::new();
+        builder.pure(1u64).unwrap();
+        let other_ptb = builder.finish();
+
+        // Successful decisions are cached per sender and PTB
+        for _ in 0..2 {
+            assert!(evaluator
+                .evaluate(allowed, &ptb, 1000, None)
+                .await
+                .unwrap()
+                .is_ok());
+        }
+        assert_eq!(evaluations(), 1);
+        assert!(evaluator
+            .evaluate(allowed, &other_ptb, 1000, None)
+            .await
+            .unwrap()
+            .is_ok());
+        assert_eq!(evaluations(), 2);
+
+        // Denied requests are always evaluated
+        for _ in 0..2 {
+            assert!(!evaluator
+                .evaluate(denied, &ptb, 1000, None)
+                .await
+                .unwrap()
+                .is_ok());
+        }
+        assert_eq!(evaluations(), 4);
+
+        // Decisions are not reused once a new checkpoint is seen
+        checkpoint.send_replace(2);
+        assert!(evaluator
+            .evaluate(allowed, &ptb, 1000, None)
+            .await
+            .unwrap()
+            .is_ok());
+        assert_eq!(evaluations(), 5);
+    }
+}
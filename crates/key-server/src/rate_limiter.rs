@@ -0,0 +1,220 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Token bucket rate limiting of requests per user address, source IP and package.
+
+use crate::errors::InternalError;
+use crate::key_server_options::{RateLimit, RateLimitConfig};
+use crate::metrics::Metrics;
+use moka::sync::Cache;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sui_types::base_types::{ObjectID, SuiAddress};
+use tracing::debug;
+
+/// The maximum number of tracked keys per limit. Least recently used keys are evicted first.
+const MAX_TRACKED_KEYS: u64 = 100_000;
+
+/// A token bucket that holds up to `burst` tokens and is refilled with `requests_per_second`.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            last_refill: now,
+        }
+    }
+
+    /// Take a token if one is available.
+    fn try_acquire(&mut self, limit: &RateLimit, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.requests_per_second).min(limit.burst as f64);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The token buckets of a single limit by key.
+#[derive(Clone)]
+struct KeyedLimiter<K> {
+    limit: RateLimit,
+    buckets: Cache<K, Arc<Mutex<TokenBucket>>>,
+}
+
+impl<K: Hash + Eq + Send + Sync + 'static> KeyedLimiter<K> {
+    fn new(limit: &RateLimit) -> Self {
+        // An idle bucket is full again after burst / requests_per_second, so it can be evicted
+        let time_to_full = Duration::from_secs_f64(limit.burst as f64 / limit.requests_per_second);
+        Self {
+            limit: limit.clone(),
+            buckets: Cache::builder()
+                .time_to_idle(time_to_full.max(Duration::from_secs(1)))
+                .max_capacity(MAX_TRACKED_KEYS)
+                .build(),
+        }
+    }
+
+    fn try_acquire(&self, key: K, now: Instant) -> bool {
+        self.buckets
+            .get_with(key, || {
+                Arc::new(Mutex::new(TokenBucket::new(&self.limit, now)))
+            })
+            .lock()
+            .unwrap()
+            .try_acquire(&self.limit, now)
+    }
+}
+
+/// Rate limits requests per user address, source IP and first package ID. Limits that are not
+/// configured are not enforced.
+#[derive(Clone)]
+pub(crate) struct RateLimiter {
+    per_user: Option<KeyedLimiter<SuiAddress>>,
+    per_ip: Option<KeyedLimiter<IpAddr>>,
+    per_package: Option<KeyedLimiter<ObjectID>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            per_user: config.per_user.as_ref().map(KeyedLimiter::new),
+            per_ip: config.per_ip.as_ref().map(KeyedLimiter::new),
+            per_package: config.per_package.as_ref().map(KeyedLimiter::new),
+        }
+    }
+
+    pub fn check_user(
+        &self,
+        user: SuiAddress,
+        metrics: Option<&Metrics>,
+        req_id: Option<&str>,
+    ) -> Result<(), InternalError> {
+        check(&self.per_user, user, "user", metrics, req_id)
+    }
+
+    pub fn check_ip(
+        &self,
+        ip: IpAddr,
+        metrics: Option<&Metrics>,
+        req_id: Option<&str>,
+    ) -> Result<(), InternalError> {
+        check(&self.per_ip, ip, "ip", metrics, req_id)
+    }
+
+    pub fn check_package(
+        &self,
+        first_pkg_id: ObjectID,
+        metrics: Option<&Metrics>,
+        req_id: Option<&str>,
+    ) -> Result<(), InternalError> {
+        check(&self.per_package, first_pkg_id, "package", metrics, req_id)
+    }
+}
+
+fn check<K: Hash + Eq + Send + Sync + 'static>(
+    limiter: &Option<KeyedLimiter<K>>,
+    key: K,
+    limit_type: &str,
+    metrics: Option<&Metrics>,
+    req_id: Option<&str>,
+) -> Result<(), InternalError> {
+    let Some(limiter) = limiter else {
+        return Ok(());
+    };
+    if limiter.try_acquire(key, Instant::now()) {
+        return Ok(());
+    }
+    debug!("Rate limited by {} (req_id: {:?})", limit_type, req_id);
+    if let Some(m) = metrics {
+        m.observe_rate_limited(limit_type);
+    }
+    Err(InternalError::RateLimited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limit = RateLimit {
+            requests_per_second: 2.0,
+            burst: 3,
+        };
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(&limit, start);
+
+        // The full burst is available initially
+        for _ in 0..3 {
+            assert!(bucket.try_acquire(&limit, start));
+        }
+        assert!(!bucket.try_acquire(&limit, start));
+
+        // One token is refilled every 500ms
+        assert!(!bucket.try_acquire(&limit, start + Duration::from_millis(400)));
+        assert!(bucket.try_acquire(&limit, start + Duration::from_millis(500)));
+        assert!(!bucket.try_acquire(&limit, start + Duration::from_millis(500)));
+
+        // The bucket never holds more than the burst
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(bucket.try_acquire(&limit, later));
+        }
+        assert!(!bucket.try_acquire(&limit, later));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limit = RateLimit {
+            requests_per_second: 0.001,
+            burst: 2,
+        };
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            per_user: Some(limit.clone()),
+            per_package: Some(limit),
+            ..Default::default()
+        });
+
+        // Limits are tracked per key
+        let user = SuiAddress::random_for_testing_only();
+        for _ in 0..2 {
+            assert!(limiter.check_user(user, None, None).is_ok());
+        }
+        assert_eq!(
+            limiter.check_user(user, None, None),
+            Err(InternalError::RateLimited)
+        );
+        assert!(limiter
+            .check_user(SuiAddress::random_for_testing_only(), None, None)
+            .is_ok());
+
+        let package = ObjectID::random();
+        for _ in 0..2 {
+            assert!(limiter.check_package(package, None, None).is_ok());
+        }
+        assert_eq!(
+            limiter.check_package(package, None, None),
+            Err(InternalError::RateLimited)
+        );
+
+        // Limits that are not configured are not enforced
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        for _ in 0..10 {
+            assert!(limiter.check_ip(ip, None, None).is_ok());
+        }
+    }
+}
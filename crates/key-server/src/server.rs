@@ -8,63 +8,78 @@ use crate::metrics::{call_with_duration, observation_callback, status_callback,
 use crate::metrics_push::create_push_client;
 use crate::mvr::mvr_forward_resolution;
 use crate::periodic_updater::spawn_periodic_updater;
-use crate::signed_message::{signed_message, signed_request};
+use crate::signed_message::{signed_batch_request, signed_message, signed_request};
 use crate::time::checked_duration_since;
+use crate::time::current_epoch_time;
 use crate::time::from_mins;
 use crate::time::{duration_since_as_f64, saturating_duration_since};
 use crate::types::{MasterKeyPOP, Network};
+use admin::start_admin_server;
 use anyhow::{Context, Result};
-use axum::extract::{Query, Request};
+use arc_swap::ArcSwap;
+use audit_log::{AuditLog, AuditRecord};
+use axum::extract::{ConnectInfo, Query, Request};
 use axum::http::{HeaderMap, HeaderValue};
 use axum::middleware::{from_fn_with_state, map_response, Next};
 use axum::response::Response;
 use axum::routing::{get, post};
 use axum::{extract::State, Json, Router};
+use client_access::API_KEY_HEADER;
+use committee::start_committee_member_server;
+use config_reload::{read_config, spawn_config_reloader, KeyState};
 use core::time::Duration;
+use crypto::create_full_id;
 use crypto::elgamal::encrypt;
 use crypto::ibe;
-use crypto::ibe::create_proof_of_possession;
 use crypto::prefixed_hex::PrefixedHex;
-use errors::InternalError;
-use externals::get_latest_checkpoint_timestamp;
+use errors::{ErrorResponse, InternalError};
+use externals::get_latest_checkpoint;
 use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
 use fastcrypto::traits::VerifyingKey;
-use futures::future::pending;
-use jsonrpsee::core::ClientError;
-use jsonrpsee::types::error::{INVALID_PARAMS_CODE, METHOD_NOT_FOUND_CODE};
-use key_server_options::KeyServerOptions;
+use futures::future::{join_all, pending, select_all};
+use key_rotation::{spawn_onchain_key_updater, OnchainPublicKeys};
+use key_server_options::{KeyServerOptions, ServerMode};
 use master_keys::MasterKeys;
 use metrics::metrics_middleware;
 use mysten_service::get_mysten_service;
 use mysten_service::metrics::start_prometheus_server;
 use mysten_service::package_name;
 use mysten_service::package_version;
-use mysten_service::serve;
+use policy::{execution_error_to_internal_error, MoveAbort};
+use policy_evaluator::{create_policy_evaluator, PolicyEvaluator};
 use rand::thread_rng;
+use rate_limiter::RateLimiter;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
 use std::env;
+use std::future::IntoFuture;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
 use sui_rpc_client::SuiRpcClient;
-use sui_sdk::error::Error;
-use sui_sdk::rpc_types::SuiTransactionBlockEffectsAPI;
+use sui_sdk::rpc_types::SuiExecutionStatus;
 use sui_sdk::types::base_types::{ObjectID, SuiAddress};
 use sui_sdk::types::signature::GenericSignature;
-use sui_sdk::types::transaction::{ProgrammableTransaction, TransactionKind};
+use sui_sdk::types::transaction::ProgrammableTransaction;
 use sui_sdk::verify_personal_message_signature::verify_personal_message_signature;
 use sui_sdk::SuiClientBuilder;
 use tap::tap::TapFallible;
-use tokio::sync::watch::Receiver;
+use tokio::net::TcpListener;
+use tokio::sync::watch::{channel, Receiver, Sender};
 use tokio::task::JoinHandle;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::{debug, error, info, warn};
 use types::{ElGamalPublicKey, ElgamalEncryption, ElgamalVerificationKey};
-use valid_ptb::ValidPtb;
+use valid_ptb::{ptb_from_base64, ValidPtb};
 
+mod admin;
+mod audit_log;
 mod cache;
+mod client_access;
+mod committee;
+mod config_reload;
 mod errors;
 mod externals;
 mod signed_message;
@@ -73,12 +88,18 @@ mod types;
 mod utils;
 mod valid_ptb;
 
+mod key_rotation;
 mod key_server_options;
+mod key_source;
+mod local_policy_evaluator;
 mod master_keys;
 mod metrics;
 mod metrics_push;
 mod mvr;
 mod periodic_updater;
+mod policy;
+mod policy_evaluator;
+mod rate_limiter;
 #[cfg(test)]
 pub mod tests;
 mod time;
@@ -86,6 +107,15 @@ mod time;
 const GAS_BUDGET: u64 = 500_000_000;
 const GIT_VERSION: &str = utils::git_version!();
 
+/// The port on which the key server is served.
+const SERVER_PORT: u16 = 2024;
+
+/// The maximum number of bundles in a batch fetch key request.
+const MAX_BATCH_SIZE: usize = 10;
+
+/// The interval at which the usage counters of the clients are persisted.
+const USAGE_PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Default encoding used for master and public keys for the key server.
 type DefaultEncoding = PrefixedHex;
 
@@ -113,6 +143,18 @@ struct FetchKeyRequest {
     request_signature: Ed25519Signature,
 
     certificate: Certificate,
+
+    // If set, the ids are evaluated individually and keys are returned for the approved ids even
+    // if other ids are denied. Otherwise, keys are only returned if all ids are approved.
+    // Note that the commands of each id are evaluated as a separate PTB, so they do not see the
+    // side effects of the commands for other ids, see [ValidPtb::split_by_id].
+    #[serde(default)]
+    partial_success: bool,
+
+    // If set, the key with this version is used. Otherwise, the key registered on-chain is used,
+    // see [key_rotation].
+    #[serde(default)]
+    key_version: Option<u32>,
 }
 
 type KeyId = Vec<u8>;
@@ -126,16 +168,68 @@ struct DecryptionKey {
     encrypted_key: ElgamalEncryption,
 }
 
+/// The reason why the key for an id was not returned in a partial success response.
+#[derive(Serialize, Deserialize, Debug)]
+struct KeyIdError {
+    id: KeyId,
+    error: InternalError,
+}
+
 #[derive(Serialize, Deserialize)]
 struct FetchKeyResponse {
     decryption_keys: Vec<DecryptionKey>,
+    // Only set for partial success requests
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<KeyIdError>,
+}
+
+/// A PTB together with the certificate for the package it calls. All certificates in a batch must
+/// use the same session key.
+#[derive(Serialize, Deserialize)]
+struct FetchKeyBundle {
+    ptb: String, // must adhere specific structure, see ValidPtb
+    certificate: Certificate,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchFetchKeyRequest {
+    bundles: Vec<FetchKeyBundle>,
+    enc_key: ElGamalPublicKey,
+    enc_verification_key: ElgamalVerificationKey,
+    // Signature by the session key over all PTBs and the encryption keys, see signed_batch_request
+    request_signature: Ed25519Signature,
+    // See FetchKeyRequest
+    #[serde(default)]
+    partial_success: bool,
+    // See FetchKeyRequest
+    #[serde(default)]
+    key_version: Option<u32>,
+}
+
+/// The result of a single bundle in a batch, in the same order as the bundles of the request.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BundleResult {
+    Ok(FetchKeyResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchFetchKeyResponse {
+    results: Vec<BundleResult>,
 }
 
 #[derive(Clone)]
 struct Server {
     sui_rpc_client: SuiRpcClient,
-    master_keys: MasterKeys,
-    key_server_oid_to_pop: HashMap<ObjectID, MasterKeyPOP>,
+    /// Swapped when the configuration is reloaded, see [config_reload].
+    key_state: Arc<ArcSwap<KeyState>>,
+    policy_evaluator: Arc<dyn PolicyEvaluator>,
+    rate_limiter: RateLimiter,
+    /// The public keys of the key server objects on-chain, see [key_rotation].
+    onchain_public_keys: OnchainPublicKeys,
+    /// The sequence number of the latest checkpoint, updated together with its timestamp.
+    latest_checkpoint_sequence_number: Arc<Sender<u64>>,
     options: KeyServerOptions,
 }
 
@@ -154,26 +248,24 @@ impl Server {
         );
         info!("Server started with network: {:?}", options.network);
 
-        let master_keys = MasterKeys::load(&options).unwrap_or_else(|e| {
+        let key_state = KeyState::load(&options, None).unwrap_or_else(|e| {
             panic!("Failed to load master keys: {}", e);
         });
 
-        let key_server_oid_to_pop = options
-            .get_supported_key_server_object_ids()
-            .into_iter()
-            .map(|ks_oid| {
-                let key = master_keys
-                    .get_key_for_key_server(&ks_oid)
-                    .expect("checked already");
-                let pop = create_proof_of_possession(key, &ks_oid.into_bytes());
-                (ks_oid, pop)
-            })
-            .collect();
+        let (latest_checkpoint_sequence_number, _) = channel(0);
+        let policy_evaluator = create_policy_evaluator(
+            &options,
+            &sui_rpc_client,
+            latest_checkpoint_sequence_number.subscribe(),
+        );
 
         Server {
             sui_rpc_client,
-            master_keys,
-            key_server_oid_to_pop,
+            key_state: Arc::new(ArcSwap::from_pointee(key_state)),
+            policy_evaluator,
+            rate_limiter: RateLimiter::new(&options.rate_limits),
+            onchain_public_keys: OnchainPublicKeys::default(),
+            latest_checkpoint_sequence_number: Arc::new(latest_checkpoint_sequence_number),
             options,
         }
     }
@@ -189,8 +281,18 @@ impl Server {
         package_name: String,
         req_id: Option<&str>,
     ) -> Result<(), InternalError> {
-        // Check certificate
+        self.check_certificate(cert, package_name, req_id).await?;
+        let signed_msg = signed_request(ptb, enc_key, enc_verification_key);
+        Self::check_session_signature(&signed_msg, session_sig, &cert.session_vk, req_id)
+    }
 
+    /// Check that the certificate is valid at this time and signed by the user for the given package.
+    async fn check_certificate(
+        &self,
+        cert: &Certificate,
+        package_name: String,
+        req_id: Option<&str>,
+    ) -> Result<(), InternalError> {
         // TTL of the session key must be smaller than the allowed max
         let ttl = from_mins(cert.ttl_min);
         if ttl > self.options.session_key_ttl_max {
@@ -241,19 +343,23 @@ impl Server {
                 e, req_id
             );
         })
-        .map_err(|_| InternalError::InvalidSignature)?;
+        .map_err(|_| InternalError::InvalidSignature)
+    }
 
-        // Check session signature
-        let signed_msg = signed_request(ptb, enc_key, enc_verification_key);
-        cert.session_vk
-            .verify(&signed_msg, session_sig)
-            .map_err(|_| {
-                debug!(
-                    "Session signature verification failed (req_id: {:?})",
-                    req_id
-                );
-                InternalError::InvalidSessionSignature
-            })
+    /// Check the signature of the session key on a request.
+    fn check_session_signature(
+        signed_msg: &[u8],
+        session_sig: &Ed25519Signature,
+        session_vk: &Ed25519PublicKey,
+        req_id: Option<&str>,
+    ) -> Result<(), InternalError> {
+        session_vk.verify(signed_msg, session_sig).map_err(|_| {
+            debug!(
+                "Session signature verification failed (req_id: {:?})",
+                req_id
+            );
+            InternalError::InvalidSessionSignature
+        })
     }
 
     async fn check_policy(
@@ -261,6 +367,7 @@ impl Server {
         sender: SuiAddress,
         vptb: &ValidPtb,
         gas_price: u64,
+        metrics: Option<&Metrics>,
         req_id: Option<&str>,
     ) -> Result<(), InternalError> {
         debug!(
@@ -269,53 +376,78 @@ impl Server {
             req_id
         );
         // Evaluate the `seal_approve*` function
-        let tx_data = self
-            .sui_rpc_client
-            .sui_client()
-            .transaction_builder()
-            .tx_data_for_dry_run(
-                sender,
-                TransactionKind::ProgrammableTransaction(vptb.ptb().clone()),
-                GAS_BUDGET,
-                gas_price,
-                None,
-                None,
-            )
-            .await;
-        let dry_run_res = self.sui_rpc_client
-                .dry_run_transaction_block(tx_data.clone())
-                .await
-            .map_err(|e| {
-                if let Error::RpcError(ClientError::Call(ref e)) = e {
-                    match e.code() {
-                        INVALID_PARAMS_CODE => {
-                            // A dry run will fail if called with a newly created object parameter that the FN has not yet seen.
-                            // In that case, the user gets a FORBIDDEN status response.
-                            debug!("Invalid parameter: This could be because the FN has not yet seen the object.");
-                            return InternalError::InvalidParameter;
-                        }
-                        METHOD_NOT_FOUND_CODE => {
-                            // This means that the seal_approve function is not found on the given module.
-                            debug!("Function not found: {:?}", e);
-                            return InternalError::InvalidPTB("The seal_approve function was not found on the module".to_string());
-                        }
-                        _ => {}
-                    }
-                }
-                warn!("Dry run execution failed ({:?}) (req_id: {:?})", e, req_id);
-                InternalError::Failure
-            })?;
-        debug!("Dry run response: {:?} (req_id: {:?})", dry_run_res, req_id);
-        if dry_run_res.effects.status().is_err() {
-            debug!("Dry run execution asserted (req_id: {:?})", req_id);
-            // TODO: Should we return a different error per status, e.g., InsufficientGas?
-            return Err(InternalError::NoAccess);
+        let status = self
+            .policy_evaluator
+            .evaluate(sender, vptb.ptb(), gas_price, req_id)
+            .await?;
+        if let SuiExecutionStatus::Failure { error } = status {
+            let Some(abort) = MoveAbort::parse(&error) else {
+                debug!("Policy evaluation failed: {} (req_id: {:?})", error, req_id);
+                return Err(execution_error_to_internal_error(&error));
+            };
+            debug!("Policy denied: {} (req_id: {:?})", abort, req_id);
+            if let Some(m) = metrics {
+                m.observe_policy_abort(&abort);
+            }
+            return Err(if self.options.expose_abort_details {
+                InternalError::NoAccessWithAbort(abort)
+            } else {
+                InternalError::NoAccess
+            });
         }
 
         // all good!
         Ok(())
     }
 
+    /// Evaluate the `seal_approve*` functions for each id individually, each as a separate PTB with
+    /// only the commands for that id. An id is thus only approved if all its commands succeed. If
+    /// the commands may depend on each other, the PTB is evaluated as a whole like in
+    /// [Server::check_policy], see [ValidPtb::split_by_id].
+    ///
+    /// At most [MAX_BATCH_SIZE] ids are evaluated individually, since each requires a dry run.
+    ///
+    /// @return The distinct inner ids of the PTB together with the result of their evaluation.
+    async fn check_policy_per_id(
+        &self,
+        sender: SuiAddress,
+        vptb: &ValidPtb,
+        gas_price: u64,
+        metrics: Option<&Metrics>,
+        req_id: Option<&str>,
+    ) -> Result<Vec<(KeyId, Result<(), InternalError>)>, InternalError> {
+        let Some(ptbs) = vptb.split_by_id() else {
+            debug!(
+                "PTB cannot be split, evaluating it as a whole (req_id: {:?})",
+                req_id
+            );
+            self.check_policy(sender, vptb, gas_price, metrics, req_id)
+                .await?;
+            let mut ids = vptb.inner_ids();
+            ids.sort();
+            ids.dedup();
+            return Ok(ids.into_iter().map(|id| (id, Ok(()))).collect());
+        };
+        if ptbs.len() > MAX_BATCH_SIZE {
+            debug!(
+                "Too many ids to evaluate individually: {} (req_id: {:?})",
+                ptbs.len(),
+                req_id
+            );
+            return Err(InternalError::InvalidPTB(format!(
+                "Too many ids to evaluate individually (more than {})",
+                MAX_BATCH_SIZE
+            )));
+        }
+        Ok(join_all(ptbs.into_iter().map(|(id, ptb)| async move {
+            let result = self
+                .check_policy(sender, &ptb, gas_price, metrics, req_id)
+                .await;
+            (id, result)
+        }))
+        .await)
+    }
+
     #[allow(clippy::too_many_arguments)]
     async fn check_request(
         &self,
@@ -329,6 +461,43 @@ impl Server {
         req_id: Option<&str>,
         mvr_name: Option<String>,
     ) -> Result<(ObjectID, Vec<KeyId>), InternalError> {
+        self.check_bundle(
+            valid_ptb,
+            certificate,
+            Some((enc_key, enc_verification_key, request_signature)),
+            gas_price,
+            metrics,
+            req_id,
+            mvr_name,
+            None,
+            false,
+        )
+        .await
+        .map(|(first_pkg_id, ids, _)| (first_pkg_id, ids))
+    }
+
+    /// Check a single PTB and its certificate. If `session_signature` is given, the session
+    /// signature on the request is checked as well. Otherwise, the caller must have checked it.
+    ///
+    /// @return The first package id, the full ids of the approved keys and, if `partial_success`
+    /// is set, the errors for the denied ids. Otherwise, an error is returned if any id is denied.
+    #[allow(clippy::too_many_arguments)]
+    async fn check_bundle(
+        &self,
+        valid_ptb: &ValidPtb,
+        certificate: &Certificate,
+        session_signature: Option<(
+            &ElGamalPublicKey,
+            &ElgamalVerificationKey,
+            &Ed25519Signature,
+        )>,
+        gas_price: u64,
+        metrics: Option<&Metrics>,
+        req_id: Option<&str>,
+        mvr_name: Option<String>,
+        api_key: Option<&str>,
+        partial_success: bool,
+    ) -> Result<(ObjectID, Vec<KeyId>, Vec<KeyIdError>), InternalError> {
         // Handle package upgrades: Use the first as the namespace
         let first_pkg_id =
             call_with_duration(metrics.map(|m| &m.fetch_pkg_ids_duration), || async {
@@ -337,7 +506,10 @@ impl Server {
             .await?;
 
         // Make sure that the package is supported.
-        self.master_keys.has_key_for_package(&first_pkg_id)?;
+        self.key_state
+            .load()
+            .master_keys
+            .has_key_for_package(&first_pkg_id)?;
 
         // Check if the package id that MVR name points matches the first package ID, if provided.
         externals::check_mvr_package_id(
@@ -350,64 +522,143 @@ impl Server {
         .await?;
 
         // Check all conditions
-        self.check_signature(
-            valid_ptb.ptb(),
-            enc_key,
-            enc_verification_key,
-            request_signature,
-            certificate,
-            mvr_name.unwrap_or(first_pkg_id.to_hex_uncompressed()),
+        let package_name = mvr_name.unwrap_or(first_pkg_id.to_hex_uncompressed());
+        match session_signature {
+            Some((enc_key, enc_verification_key, request_signature)) => {
+                self.check_signature(
+                    valid_ptb.ptb(),
+                    enc_key,
+                    enc_verification_key,
+                    request_signature,
+                    certificate,
+                    package_name,
+                    req_id,
+                )
+                .await?
+            }
+            None => {
+                self.check_certificate(certificate, package_name, req_id)
+                    .await?
+            }
+        }
+
+        // Only limit by user and package once the certificate is verified, such that requests with
+        // invalid signatures cannot exhaust the limits of others
+        self.rate_limiter
+            .check_package(first_pkg_id, metrics, req_id)?;
+        self.rate_limiter
+            .check_user(certificate.user, metrics, req_id)?;
+
+        // Check the API key and quota of permissioned clients. The quota is only charged once the
+        // policy grants access.
+        let client_access = self.key_state.load().client_access.clone();
+        client_access.check(
+            &first_pkg_id,
+            api_key,
+            current_epoch_time(),
+            metrics,
             req_id,
-        )
-        .await?;
+        )?;
+
+        if !partial_success {
+            call_with_duration(metrics.map(|m| &m.check_policy_duration), || async {
+                self.check_policy(certificate.user, valid_ptb, gas_price, metrics, req_id)
+                    .await
+            })
+            .await?;
+            client_access.charge(&first_pkg_id, current_epoch_time(), metrics);
 
-        call_with_duration(metrics.map(|m| &m.check_policy_duration), || async {
-            self.check_policy(certificate.user, valid_ptb, gas_price, req_id)
+            // return the full id with the first package id as prefix
+            return Ok((first_pkg_id, valid_ptb.full_ids(&first_pkg_id), vec![]));
+        }
+
+        let results = call_with_duration(metrics.map(|m| &m.check_policy_duration), || async {
+            self.check_policy_per_id(certificate.user, valid_ptb, gas_price, metrics, req_id)
                 .await
         })
         .await?;
-
-        // return the full id with the first package id as prefix
-        Ok((first_pkg_id, valid_ptb.full_ids(&first_pkg_id)))
+        let mut ids = vec![];
+        let mut errors = vec![];
+        for (inner_id, result) in results {
+            let id = create_full_id(&first_pkg_id.into_bytes(), &inner_id);
+            match result {
+                Ok(()) => ids.push(id),
+                Err(error) => {
+                    if let Some(m) = metrics {
+                        m.observe_error(error.as_str());
+                    }
+                    errors.push(KeyIdError { id, error })
+                }
+            }
+        }
+        if !ids.is_empty() {
+            client_access.charge(&first_pkg_id, current_epoch_time(), metrics);
+        }
+        Ok((first_pkg_id, ids, errors))
     }
 
-    fn create_response(
+    async fn create_response(
         &self,
         first_pkg_id: ObjectID,
         ids: &[KeyId],
         enc_key: &ElGamalPublicKey,
-    ) -> FetchKeyResponse {
+        key_version: Option<u32>,
+        metrics: Option<&Metrics>,
+    ) -> Result<FetchKeyResponse, InternalError> {
         debug!("Creating response for ids: {:?}", ids);
-        let master_key = self
-            .master_keys
-            .get_key_for_package(&first_pkg_id)
-            .expect("checked already");
+        // The package may have been removed by a configuration reload since it was checked
+        let key_state = self.key_state.load_full();
+        // Requested keys, ElGamal encrypted under the user's public key
+        let encrypted_keys = match &key_state.master_keys {
+            MasterKeys::Committee { committee } => {
+                // Committees have a single key version
+                if key_version.is_some_and(|version| version != 0) {
+                    return Err(InternalError::UnknownKeyVersion);
+                }
+                committee.extract(ids, enc_key).await?
+            }
+            master_keys => {
+                let keys = master_keys.get_key_for_package(&first_pkg_id)?;
+                let (_, master_key) = self.select_key(keys, key_version, metrics)?;
+                ids.iter()
+                    .map(|id| encrypt(&mut thread_rng(), &ibe::extract(master_key, id), enc_key))
+                    .collect()
+            }
+        };
         let decryption_keys = ids
             .iter()
-            .map(|id| {
-                // Requested key
-                let key = ibe::extract(master_key, id);
-                // ElGamal encryption of key under the user's public key
-                let encrypted_key = encrypt(&mut thread_rng(), &key, enc_key);
-                DecryptionKey {
-                    id: id.to_owned(),
-                    encrypted_key,
-                }
+            .zip(encrypted_keys)
+            .map(|(id, encrypted_key)| DecryptionKey {
+                id: id.to_owned(),
+                encrypted_key,
             })
             .collect();
-        FetchKeyResponse { decryption_keys }
+        Ok(FetchKeyResponse {
+            decryption_keys,
+            errors: vec![],
+        })
     }
 
     /// Spawns a thread that fetches the latest checkpoint timestamp and sends it to a [Receiver] once per `update_interval`.
+    /// The sequence number of the checkpoint is published to [Server::latest_checkpoint_sequence_number].
     /// Returns the [Receiver].
     async fn spawn_latest_checkpoint_timestamp_updater(
         &self,
         metrics: Option<&Metrics>,
     ) -> (Receiver<Timestamp>, JoinHandle<()>) {
+        let latest_checkpoint_sequence_number = self.latest_checkpoint_sequence_number.clone();
         spawn_periodic_updater(
             &self.sui_rpc_client,
             self.options.checkpoint_update_interval,
-            get_latest_checkpoint_timestamp,
+            move |sui_rpc_client| {
+                let latest_checkpoint_sequence_number = latest_checkpoint_sequence_number.clone();
+                async move {
+                    let (sequence_number, timestamp) =
+                        get_latest_checkpoint(sui_rpc_client).await?;
+                    latest_checkpoint_sequence_number.send_replace(sequence_number);
+                    Ok(timestamp)
+                }
+            },
             "latest checkpoint timestamp",
             metrics.map(|m| {
                 observation_callback(&m.checkpoint_timestamp_delay, |ts| {
@@ -442,6 +693,25 @@ impl Server {
         .await
     }
 
+    /// Spawn a background job that periodically persists the usage counters of the clients.
+    fn spawn_usage_persist_job(&self) -> JoinHandle<()> {
+        let key_state = self.key_state.clone();
+        if self.options.usage_store_path.is_some() {
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(USAGE_PERSIST_INTERVAL);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    if let Err(error) = key_state.load().client_access.persist() {
+                        warn!(?error, "unable to persist client usage");
+                    }
+                }
+            })
+        } else {
+            tokio::spawn(pending())
+        }
+    }
+
     /// Spawn a metrics push background jobs that push metrics to seal-proxy
     fn spawn_metrics_push_job(&self, registry: prometheus::Registry) -> JoinHandle<()> {
         let push_config = self.options.metrics_push_config.clone();
@@ -480,7 +750,8 @@ async fn handle_fetch_key_internal(
     payload: &FetchKeyRequest,
     req_id: Option<&str>,
     sdk_version: &str,
-) -> Result<(ObjectID, Vec<KeyId>), InternalError> {
+    api_key: Option<&str>,
+) -> Result<(ObjectID, Vec<KeyId>, Vec<KeyIdError>), InternalError> {
     app_state.check_full_node_is_fresh()?;
 
     let valid_ptb = ValidPtb::try_from_base64(&payload.ptb)?;
@@ -493,16 +764,20 @@ async fn handle_fetch_key_internal(
 
     app_state
         .server
-        .check_request(
+        .check_bundle(
             &valid_ptb,
-            &payload.enc_key,
-            &payload.enc_verification_key,
-            &payload.request_signature,
             &payload.certificate,
+            Some((
+                &payload.enc_key,
+                &payload.enc_verification_key,
+                &payload.request_signature,
+            )),
             app_state.reference_gas_price(),
             Some(&app_state.metrics),
             req_id,
             payload.certificate.mvr_name.clone(),
+            api_key,
+            payload.partial_success,
         )
         .await.tap_ok(|_| info!(
             "Valid request: {}",
@@ -522,6 +797,7 @@ async fn handle_fetch_key(
         .get("Client-Sdk-Version")
         .and_then(|v| v.to_str().ok())
         .unwrap_or_default();
+    let api_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
 
     app_state.metrics.requests.inc();
 
@@ -530,22 +806,233 @@ async fn handle_fetch_key(
         payload.ptb, payload.certificate, req_id
     );
 
-    handle_fetch_key_internal(&app_state, &payload, req_id, sdk_version)
+    async {
+        let result = async {
+            let decision =
+                handle_fetch_key_internal(&app_state, &payload, req_id, sdk_version, api_key)
+                    .await?;
+            let response = app_state
+                .server
+                .create_response(
+                    decision.0,
+                    &decision.1,
+                    &payload.enc_key,
+                    payload.key_version,
+                    Some(&app_state.metrics),
+                )
+                .await?;
+            Ok::<_, InternalError>((decision, response))
+        }
+        .await;
+        // Recorded once the response is created, since that may still fail
+        app_state.audit(
+            &payload.certificate,
+            &payload.ptb,
+            result.as_ref().map(|(decision, _)| decision),
+            req_id,
+            sdk_version,
+            false,
+        );
+        let ((_, _, errors), response) = result?;
+        Ok(Json(FetchKeyResponse { errors, ..response }))
+    }
+    .await
+    .tap_err(|e| app_state.metrics.observe_error(e.as_str()))
+}
+
+async fn handle_batch_fetch_key_internal(
+    app_state: &MyState,
+    payload: &BatchFetchKeyRequest,
+    req_id: Option<&str>,
+    sdk_version: &str,
+    api_key: Option<&str>,
+) -> Result<Vec<Result<(ObjectID, Vec<KeyId>, Vec<KeyIdError>), InternalError>>, InternalError> {
+    app_state.check_full_node_is_fresh()?;
+
+    let Some(first_bundle) = payload.bundles.first() else {
+        debug!("Empty batch (req_id: {:?})", req_id);
+        return Err(InternalError::InvalidBatch("Empty batch".to_string()));
+    };
+    if payload.bundles.len() > MAX_BATCH_SIZE {
+        debug!(
+            "Too many bundles in batch: {} (req_id: {:?})",
+            payload.bundles.len(),
+            req_id
+        );
+        return Err(InternalError::InvalidBatch(format!(
+            "Too many bundles in batch (more than {})",
+            MAX_BATCH_SIZE
+        )));
+    }
+
+    // All certificates must be for the session key that signed the request.
+    let session_vk = &first_bundle.certificate.session_vk;
+    if payload
+        .bundles
+        .iter()
+        .any(|bundle| bundle.certificate.session_vk != *session_vk)
+    {
+        debug!(
+            "Certificates use different session keys (req_id: {:?})",
+            req_id
+        );
+        return Err(InternalError::InvalidBatch(
+            "Certificates use different session keys".to_string(),
+        ));
+    }
+
+    // The session signature covers all PTBs, so they must all be decoded to check it.
+    let ptbs = payload
+        .bundles
+        .iter()
+        .map(|bundle| ptb_from_base64(&bundle.ptb))
+        .collect::<Result<Vec<_>, _>>()?;
+    let signed_msg = signed_batch_request(&ptbs, &payload.enc_key, &payload.enc_verification_key);
+    Server::check_session_signature(&signed_msg, &payload.request_signature, session_vk, req_id)?;
+
+    app_state
+        .metrics
+        .requests_per_number_of_bundles
+        .observe(payload.bundles.len() as f64);
+
+    // Check the bundles concurrently. A failing bundle does not affect the others.
+    Ok(
+        join_all(payload.bundles.iter().zip(ptbs).map(|(bundle, ptb)| {
+            handle_bundle(
+                app_state,
+                bundle,
+                ptb,
+                req_id,
+                sdk_version,
+                api_key,
+                payload.partial_success,
+            )
+        }))
         .await
-        .tap_err(|e| app_state.metrics.observe_error(e.as_str()))
-        .map(|(first_pkg_id, full_ids)| {
-            Json(
-                app_state
-                    .server
-                    .create_response(first_pkg_id, &full_ids, &payload.enc_key),
+        .into_iter()
+        .map(|result| result.tap_err(|e| app_state.metrics.observe_error(e.as_str())))
+        .collect(),
+    )
+}
+
+/// Check a single bundle of a batch request whose session signature has already been verified.
+async fn handle_bundle(
+    app_state: &MyState,
+    bundle: &FetchKeyBundle,
+    ptb: ProgrammableTransaction,
+    req_id: Option<&str>,
+    sdk_version: &str,
+    api_key: Option<&str>,
+    partial_success: bool,
+) -> Result<(ObjectID, Vec<KeyId>, Vec<KeyIdError>), InternalError> {
+    let valid_ptb = ValidPtb::try_from(ptb)?;
+
+    // Report the number of id's in the bundle to the metrics.
+    app_state
+        .metrics
+        .requests_per_number_of_ids
+        .observe(valid_ptb.inner_ids().len() as f64);
+
+    app_state
+        .server
+        .check_bundle(
+            &valid_ptb,
+            &bundle.certificate,
+            None,
+            app_state.reference_gas_price(),
+            Some(&app_state.metrics),
+            req_id,
+            bundle.certificate.mvr_name.clone(),
+            api_key,
+            partial_success,
+        )
+        .await.tap_ok(|_| info!(
+            "Valid request: {}",
+            json!({ "user": bundle.certificate.user, "package_id": valid_ptb.pkg_id(), "req_id": req_id, "sdk_version": sdk_version, "batch": true })
+        ))
+}
+
+async fn handle_batch_fetch_key(
+    State(app_state): State<MyState>,
+    headers: HeaderMap,
+    Json(payload): Json<BatchFetchKeyRequest>,
+) -> Result<Json<BatchFetchKeyResponse>, InternalError> {
+    let req_id = headers
+        .get("Request-Id")
+        .map(|v| v.to_str().unwrap_or_default());
+    let sdk_version = headers
+        .get("Client-Sdk-Version")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let api_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+
+    app_state.metrics.requests.inc();
+
+    debug!(
+        "Checking batch request with {} bundles (req_id: {:?})",
+        payload.bundles.len(),
+        req_id
+    );
+
+    let results =
+        match handle_batch_fetch_key_internal(&app_state, &payload, req_id, sdk_version, api_key)
+            .await
+        {
+            Ok(results) => results,
+            Err(e) => {
+                app_state.audit_denied_batch(&payload.bundles, &e, req_id, sdk_version);
+                app_state.metrics.observe_error(e.as_str());
+                return Err(e);
+            }
+        };
+    let results = join_all(results.into_iter().map(|result| async {
+        let decision = result?;
+        let response = app_state
+            .server
+            .create_response(
+                decision.0,
+                &decision.1,
+                &payload.enc_key,
+                payload.key_version,
+                Some(&app_state.metrics),
             )
-        })
+            .await?;
+        Ok::<_, InternalError>((decision, response))
+    }))
+    .await;
+    Ok(Json(BatchFetchKeyResponse {
+        results: payload
+            .bundles
+            .iter()
+            .zip(results)
+            .map(|(bundle, result)| {
+                // Recorded once the response is created, since that may still fail
+                app_state.audit(
+                    &bundle.certificate,
+                    &bundle.ptb,
+                    result.as_ref().map(|(decision, _)| decision),
+                    req_id,
+                    sdk_version,
+                    true,
+                );
+                match result {
+                    Ok(((_, _, errors), response)) => {
+                        BundleResult::Ok(FetchKeyResponse { errors, ..response })
+                    }
+                    Err(e) => BundleResult::Err(e.into()),
+                }
+            })
+            .collect(),
+    }))
 }
 
 #[derive(Serialize, Deserialize)]
 struct GetServiceResponse {
     service_id: ObjectID,
     pop: MasterKeyPOP,
+    // The version of the key the proof of possession is for
+    #[serde(default)]
+    key_version: u32,
 }
 
 async fn handle_get_service(
@@ -561,13 +1048,45 @@ async fn handle_get_service(
             ObjectID::from_hex_literal(id).map_err(|_| InternalError::InvalidServiceId)
         })?;
 
-    let pop = *app_state
-        .server
+    // Optional, see FetchKeyRequest
+    let requested_version = params
+        .get("key_version")
+        .map(|v| {
+            v.parse::<u32>()
+                .map_err(|_| InternalError::UnknownKeyVersion)
+        })
+        .transpose()?;
+
+    let key_state = app_state.server.key_state.load_full();
+    if let MasterKeys::Committee { committee } = &key_state.master_keys {
+        if committee.key_server_object_id != service_id {
+            return Err(InternalError::InvalidServiceId);
+        }
+        if requested_version.is_some_and(|version| version != 0) {
+            return Err(InternalError::UnknownKeyVersion);
+        }
+        return Ok(Json(GetServiceResponse {
+            service_id,
+            pop: committee.proof_of_possession().await?,
+            key_version: 0,
+        }));
+    }
+
+    let pops = key_state
         .key_server_oid_to_pop
         .get(&service_id)
         .ok_or(InternalError::InvalidServiceId)?;
-
-    Ok(Json(GetServiceResponse { service_id, pop }))
+    let keys = key_state.master_keys.get_key_for_key_server(&service_id)?;
+    let (key_version, _) = app_state.server.select_key(keys, requested_version, None)?;
+    let pop = *pops
+        .get(&key_version)
+        .ok_or(InternalError::UnknownKeyVersion)?;
+
+    Ok(Json(GetServiceResponse {
+        service_id,
+        pop,
+        key_version,
+    }))
 }
 
 #[derive(Clone)]
@@ -576,9 +1095,53 @@ struct MyState {
     server: Arc<Server>,
     latest_checkpoint_timestamp_receiver: Receiver<Timestamp>,
     reference_gas_price_receiver: Receiver<u64>,
+    audit_log: Option<Arc<AuditLog>>,
 }
 
 impl MyState {
+    /// Record the decision on a fetch key request, or a bundle of a batch request, in the audit
+    /// log if it is enabled.
+    fn audit(
+        &self,
+        certificate: &Certificate,
+        ptb: &str,
+        result: Result<&(ObjectID, Vec<KeyId>, Vec<KeyIdError>), &InternalError>,
+        req_id: Option<&str>,
+        sdk_version: &str,
+        batch: bool,
+    ) {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(AuditRecord::new(
+                certificate.user,
+                ptb,
+                result,
+                req_id,
+                sdk_version,
+                batch,
+            ));
+        }
+    }
+
+    /// Record a batch request that was denied as a whole in the audit log if it is enabled. Empty
+    /// batches request no keys and are not recorded.
+    fn audit_denied_batch(
+        &self,
+        bundles: &[FetchKeyBundle],
+        error: &InternalError,
+        req_id: Option<&str>,
+        sdk_version: &str,
+    ) {
+        if let (Some(audit_log), Some(first_bundle)) = (&self.audit_log, bundles.first()) {
+            audit_log.record(AuditRecord::new_for_denied_batch(
+                first_bundle.certificate.user,
+                bundles.iter().map(|bundle| bundle.ptb.as_str()),
+                error,
+                req_id,
+                sdk_version,
+            ));
+        }
+    }
+
     fn check_full_node_is_fresh(&self) -> Result<(), InternalError> {
         // Compute the staleness of the latest checkpoint timestamp.
         let staleness =
@@ -642,6 +1205,50 @@ async fn handle_request_headers(
     Ok(next.run(request).await)
 }
 
+/// The source IP of a request. If `ip_header` is set, the right-most address in the header is used
+/// since it is appended by the proxy in front of the key server, whereas the addresses before it
+/// are set by the client. Otherwise, or if the header is missing, the address of the peer is used.
+fn source_ip(
+    headers: &HeaderMap,
+    ip_header: Option<&str>,
+    peer_ip: Option<IpAddr>,
+) -> Option<IpAddr> {
+    ip_header
+        .and_then(|name| headers.get(name))
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+        .or(peer_ip)
+}
+
+/// Middleware to rate limit requests per source IP, see [key_server_options::RateLimitConfig].
+async fn handle_rate_limit_by_ip(
+    state: State<MyState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, InternalError> {
+    let source_ip = source_ip(
+        request.headers(),
+        state.server.options.rate_limits.ip_header.as_deref(),
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip()),
+    );
+    if let Some(ip) = source_ip {
+        let req_id = request
+            .headers()
+            .get("Request-Id")
+            .map(|v| v.to_str().unwrap_or_default());
+        state
+            .server
+            .rate_limiter
+            .check_ip(ip, Some(&state.metrics), req_id)
+            .tap_err(|e| state.metrics.observe_error(e.as_str()))?;
+    }
+    Ok(next.run(request).await)
+}
+
 /// Middleware to add headers to all responses.
 async fn add_response_headers(mut response: Response) -> Response {
     let headers = response.headers_mut();
@@ -678,12 +1285,15 @@ fn uptime_metric(version: &str) -> Box<dyn prometheus::core::Collector> {
 ///  - background checkpoint downloader
 ///  - reference gas price updater.
 ///  - optional metrics pusher (if configured).
+///  - optional configuration reloader (if the configuration is read from `config_path`).
+///  - updater of the public keys on-chain of key server objects whose keys are rotated.
 ///
 /// The returned JoinHandle can be used to catch any tasks error or panic.
 async fn start_server_background_tasks(
     server: Arc<Server>,
     metrics: Arc<Metrics>,
     registry: prometheus::Registry,
+    config_path: Option<PathBuf>,
 ) -> (
     Receiver<Timestamp>,
     Receiver<u64>,
@@ -702,6 +1312,18 @@ async fn start_server_background_tasks(
     // Spawn metrics push task
     let metrics_push_handle = server.spawn_metrics_push_job(registry);
 
+    // Spawn task persisting the usage of clients
+    let usage_persist_handle = server.spawn_usage_persist_job();
+
+    // Spawn task reloading the configuration when it changes
+    let config_reload_handle = match config_path {
+        Some(path) => spawn_config_reloader(server.clone(), path, metrics.clone()),
+        None => tokio::spawn(pending()),
+    };
+
+    // Spawn task fetching the public keys on-chain while master keys are rotated
+    let onchain_key_handle = spawn_onchain_key_updater(server.clone(), metrics.clone());
+
     // Spawn a monitor task that will exit the program if any updater task panics
     let handle: JoinHandle<anyhow::Result<()>> = tokio::spawn(async move {
         tokio::select! {
@@ -732,6 +1354,33 @@ async fn start_server_background_tasks(
                     return Err(e.into());
                 }
             }
+            result = usage_persist_handle => {
+                if let Err(e) = result {
+                    error!("Usage persist task panicked: {:?}", e);
+                    if e.is_panic() {
+                        std::panic::resume_unwind(e.into_panic());
+                    }
+                    return Err(e.into());
+                }
+            }
+            result = config_reload_handle => {
+                if let Err(e) = result {
+                    error!("Configuration reload task panicked: {:?}", e);
+                    if e.is_panic() {
+                        std::panic::resume_unwind(e.into_panic());
+                    }
+                    return Err(e.into());
+                }
+            }
+            result = onchain_key_handle => {
+                if let Err(e) = result {
+                    error!("On-chain key updater panicked: {:?}", e);
+                    if e.is_panic() {
+                        std::panic::resume_unwind(e.into_panic());
+                    }
+                    return Err(e.into());
+                }
+            }
         }
 
         unreachable!("One of the background tasks should have returned an error");
@@ -748,8 +1397,19 @@ async fn start_server_background_tasks(
 async fn main() -> Result<()> {
     let (monitor_handle, app) = app().await?;
 
+    // Served with the address of the peer, which is used to limit requests per source IP
+    let listener = TcpListener::bind(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        SERVER_PORT,
+    ))
+    .await?;
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    );
+
     tokio::select! {
-        server_result = serve(app) => {
+        server_result = server.into_future() => {
             error!("Server stopped with status {:?}", server_result);
             std::process::exit(1);
         }
@@ -765,16 +1425,13 @@ pub(crate) async fn app() -> Result<(JoinHandle<Result<()>>, Router)> {
 
     // If CONFIG_PATH is set, read the configuration from the file.
     // Otherwise, use the local environment variables.
-    let options = match env::var("CONFIG_PATH") {
-        Ok(config_path) => {
-            info!("Loading config file: {}", config_path);
-            serde_yaml::from_reader(
-                std::fs::File::open(&config_path)
-                    .context(format!("Cannot open configuration file {config_path}"))?,
-            )
-            .expect("Failed to parse configuration file")
+    let config_path = env::var("CONFIG_PATH").ok().map(PathBuf::from);
+    let options = match &config_path {
+        Some(config_path) => {
+            info!("Loading config file: {}", config_path.display());
+            read_config(config_path)?
         }
-        Err(_) => {
+        None => {
             info!("Using local environment variables for configuration, should only be used for testing");
             let network = env::var("NETWORK")
                 .map(|n| Network::from_str(&n))
@@ -814,15 +1471,45 @@ pub(crate) async fn app() -> Result<(JoinHandle<Result<()>>, Router)> {
     let server = Arc::new(Server::new(options, Some(metrics.clone())).await);
 
     let (latest_checkpoint_timestamp_receiver, reference_gas_price_receiver, monitor_handle) =
-        start_server_background_tasks(server.clone(), metrics.clone(), registry.clone()).await;
+        start_server_background_tasks(
+            server.clone(),
+            metrics.clone(),
+            registry.clone(),
+            config_path.clone(),
+        )
+        .await;
+
+    let audit_log = server
+        .options
+        .audit_log
+        .as_ref()
+        .map(|config| AuditLog::new(config, Some(metrics.clone())).map(Arc::new))
+        .transpose()
+        .context("Failed to open the audit log")?;
 
     let state = MyState {
         metrics,
         server,
         latest_checkpoint_timestamp_receiver,
         reference_gas_price_receiver,
+        audit_log,
     };
 
+    // APIs served on separate ports stop the server like the background tasks
+    let mut handles = vec![monitor_handle];
+    if let Some(admin_config) = &state.server.options.admin {
+        handles.push(start_admin_server(admin_config, state.clone(), config_path).await?);
+    }
+    if let ServerMode::CommitteeMember { host_port, .. } = &state.server.options.server_mode {
+        // Partial keys for the coordinator of a committee, see [committee]
+        handles
+            .push(start_committee_member_server(*host_port, state.server.key_state.clone()).await?);
+    }
+    let monitor_handle = tokio::spawn(async move {
+        let (result, _, _) = select_all(handles).await;
+        result?
+    });
+
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_origin(Any)
@@ -833,8 +1520,10 @@ pub(crate) async fn app() -> Result<(JoinHandle<Result<()>>, Router)> {
         .merge(
             axum::Router::new()
                 .route("/v1/fetch_key", post(handle_fetch_key))
+                .route("/v1/batch_fetch_key", post(handle_batch_fetch_key))
                 .route("/v1/service", get(handle_get_service))
                 .layer(from_fn_with_state(state.clone(), handle_request_headers))
+                .layer(from_fn_with_state(state.clone(), handle_rate_limit_by_ip))
                 .layer(map_response(add_response_headers))
                 // Outside most middlewares that tracks metrics for HTTP requests and response
                 // status.
@@ -842,7 +1531,7 @@ pub(crate) async fn app() -> Result<(JoinHandle<Result<()>>, Router)> {
                     state.metrics.clone(),
                     metrics_middleware,
                 ))
-                .with_state(state),
+                .with_state(state.clone()),
         )
         .layer(cors);
     Ok((monitor_handle, app))
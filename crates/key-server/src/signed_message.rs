@@ -46,9 +46,39 @@ pub fn signed_request(
     bcs::to_bytes(&req).expect("should serialize")
 }
 
+/// Prefix of batch requests, so they cannot be confused with single requests.
+const BATCH_REQUEST_DOMAIN: &[u8] = b"SEAL_BATCH_REQUEST";
+
+#[derive(Serialize, Deserialize)]
+struct BatchRequestFormat {
+    domain: Vec<u8>,
+    ptbs: Vec<Vec<u8>>,
+    enc_key: Vec<u8>,
+    enc_verification_key: Vec<u8>,
+}
+
+/// The message signed by the session key for a batch request. The order of the PTBs is the order of
+/// the bundles in the request.
+pub fn signed_batch_request(
+    ptbs: &[ProgrammableTransaction],
+    enc_key: &ElGamalPublicKey,
+    enc_verification_key: &ElgamalVerificationKey,
+) -> Vec<u8> {
+    let req = BatchRequestFormat {
+        domain: BATCH_REQUEST_DOMAIN.to_vec(),
+        ptbs: ptbs
+            .iter()
+            .map(|ptb| bcs::to_bytes(ptb).expect("should serialize"))
+            .collect(),
+        enc_key: bcs::to_bytes(&enc_key).expect("should serialize"),
+        enc_verification_key: bcs::to_bytes(&enc_verification_key).expect("should serialize"),
+    };
+    bcs::to_bytes(&req).expect("should serialize")
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::signed_message::{signed_message, signed_request};
+    use crate::signed_message::{signed_batch_request, signed_message, signed_request};
     use crypto::elgamal::genkey;
     use fastcrypto::ed25519::Ed25519KeyPair;
     use fastcrypto::traits::KeyPair;
@@ -118,5 +148,14 @@ mod tests {
 
         let result = signed_request(&ptb, &eg_keys.1, &eg_keys.2);
         assert_eq!(hex::encode(result), expected_output);
+
+        // A batch with a single PTB is prefixed by the domain and the number of PTBs
+        let expected_batch_output = format!(
+            "12{}01{}",
+            hex::encode(b"SEAL_BATCH_REQUEST"),
+            expected_output
+        );
+        let result = signed_batch_request(&[ptb], &eg_keys.1, &eg_keys.2);
+        assert_eq!(hex::encode(result), expected_batch_output);
     }
 }
@@ -7,12 +7,17 @@ use crypto::ObjectID;
 use sui_sdk::{
     error::SuiRpcResult,
     rpc_types::{
-        Checkpoint, CheckpointId, DryRunTransactionBlockResponse, SuiObjectDataOptions,
-        SuiObjectResponse,
+        Checkpoint, CheckpointId, DevInspectArgs, DevInspectResults,
+        DryRunTransactionBlockResponse, SuiObjectDataOptions, SuiObjectResponse,
     },
     SuiClient,
 };
-use sui_types::{dynamic_field::DynamicFieldName, transaction::TransactionData};
+use sui_types::{
+    base_types::SuiAddress,
+    dynamic_field::DynamicFieldName,
+    sui_system_state::sui_system_state_summary::SuiSystemStateSummary,
+    transaction::{TransactionData, TransactionKind},
+};
 
 use crate::{key_server_options::RetryConfig, metrics::Metrics};
 
@@ -165,6 +170,37 @@ impl SuiRpcClient {
         .await
     }
 
+    /// Dev inspects a transaction block. The reference gas price is used and no gas budget is needed.
+    /// Unlike the default of the full node, the transaction checks are not skipped, so e.g. owned
+    /// input objects must be owned by the sender.
+    pub async fn dev_inspect_transaction_block(
+        &self,
+        sender: SuiAddress,
+        tx: TransactionKind,
+    ) -> SuiRpcResult<DevInspectResults> {
+        sui_rpc_with_retries(
+            &self.rpc_retry_config,
+            "dev_inspect_transaction_block",
+            self.metrics.clone(),
+            || async {
+                self.sui_client
+                    .read_api()
+                    .dev_inspect_transaction_block(
+                        sender,
+                        tx.clone(),
+                        None,
+                        None,
+                        Some(DevInspectArgs {
+                            skip_checks: Some(false),
+                            ..Default::default()
+                        }),
+                    )
+                    .await
+            },
+        )
+        .await
+    }
+
     /// Returns an object with the given options.
     pub async fn get_object_with_options(
         &self,
@@ -228,6 +264,22 @@ impl SuiRpcClient {
         .await
     }
 
+    /// Returns a summary of the latest Sui system state, e.g., the current epoch and protocol version.
+    pub async fn get_latest_sui_system_state(&self) -> SuiRpcResult<SuiSystemStateSummary> {
+        sui_rpc_with_retries(
+            &self.rpc_retry_config,
+            "get_latest_sui_system_state",
+            self.metrics.clone(),
+            || async {
+                self.sui_client
+                    .governance_api()
+                    .get_latest_sui_system_state()
+                    .await
+            },
+        )
+        .await
+    }
+
     /// Returns an object with the given dynamic field name.
     pub async fn get_dynamic_field_object(
         &self,
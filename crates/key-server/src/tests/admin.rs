@@ -0,0 +1,113 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::admin::admin_router;
+use crate::tests::SealTestCluster;
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use http_body_util::BodyExt;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde_json::Value;
+use std::net::SocketAddr;
+use sui_types::base_types::ObjectID;
+use tokio::net::TcpListener;
+
+async fn admin_request(
+    client: &Client<HttpConnector, Body>,
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    token: Option<&str>,
+) -> (StatusCode, Value) {
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(format!("http://{addr}{path}"));
+    if let Some(token) = token {
+        builder = builder.header("Authorization", format!("Bearer {token}"));
+    }
+    let response = client
+        .request(builder.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn test_admin_api() {
+    let mut tc = SealTestCluster::new(0).await;
+    tc.add_open_server().await;
+
+    let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = admin_router(tc.state().await, "secret".to_string(), None);
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    let client = Client::builder(TokioExecutor::new()).build_http();
+    let request = |method, path, token| admin_request(&client, addr, method, path, token);
+
+    // Requests without a valid token are rejected
+    assert_eq!(
+        request("GET", "/v1/clients", None).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        request("GET", "/v1/clients", Some("wrong")).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let (status, clients) = request("GET", "/v1/clients", Some("secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    let clients = clients.as_array().unwrap();
+    assert_eq!(clients.len(), 1);
+    assert_eq!(
+        ObjectID::from_hex_literal(clients[0]["key_server_object_id"].as_str().unwrap()).unwrap(),
+        tc.get_services()[0]
+    );
+    assert!(clients[0]["public_key"].is_string());
+    assert_eq!(clients[0]["enabled"], Value::Bool(true));
+
+    let (status, keys) = request("GET", "/v1/keys", Some("secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    let keys = keys.as_array().unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0]["current_version"], 0);
+    assert_eq!(keys[0]["keys"][0]["public_key"], clients[0]["public_key"]);
+    assert_eq!(keys[0]["keys"][0]["deprecated"], Value::Bool(false));
+
+    let (status, config) = request("GET", "/v1/config", Some("secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(config["server_mode"]["Open"].is_object());
+
+    let (status, checkpoint) = request("GET", "/v1/checkpoint", Some("secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(checkpoint["is_fresh"], Value::Bool(true));
+
+    let (status, caches) = request("GET", "/v1/caches", Some("secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(caches["packages"].is_u64());
+    assert_eq!(
+        request("POST", "/v1/caches/flush", Some("secret")).await.0,
+        StatusCode::OK
+    );
+
+    // There are no named clients in open mode and the configuration is not read from a file
+    assert_eq!(
+        request("POST", "/v1/clients/alice/disable", Some("secret"))
+            .await
+            .0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        request("POST", "/v1/config/reload", Some("secret")).await.0,
+        StatusCode::BAD_REQUEST
+    );
+}
@@ -0,0 +1,196 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::client_access::ClientAccess;
+use crate::committee::{committee_member_router, Committee};
+use crate::config_reload::KeyState;
+use crate::errors::InternalError;
+use crate::key_server_options::{CommitteeMemberConfig, KeyServerOptions};
+use crate::master_keys::MasterKeys;
+use crate::types::Network;
+use crate::types::{ElGamalPublicKey, ElgamalEncryption};
+use crate::{DefaultEncoding, MAX_BATCH_SIZE};
+use arc_swap::ArcSwap;
+use crypto::elgamal::{decrypt, genkey, SecretKey};
+use crypto::ibe;
+use crypto::threshold::{public_key_share, split, MasterKeyShare};
+use fastcrypto::encoding::Encoding;
+use fastcrypto::groups::bls12381::{G1Element, G2Element};
+use fastcrypto::serde_helpers::ToFromByteArray;
+use rand::thread_rng;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use sui_types::base_types::ObjectID;
+use tokio::net::TcpListener;
+
+const TOKEN_ENV_VAR: &str = "COMMITTEE_TOKEN";
+const TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+fn member_key_state(key_server_object_id: ObjectID, share: MasterKeyShare) -> KeyState {
+    let options = KeyServerOptions::new_for_testing(Network::Testnet);
+    KeyState {
+        server_mode: options.server_mode.clone(),
+        master_keys: MasterKeys::CommitteeMember {
+            key_server_object_id,
+            share,
+            token: TOKEN.to_string(),
+        },
+        key_server_oid_to_pop: HashMap::new(),
+        client_access: Arc::new(ClientAccess::load(&options).unwrap()),
+    }
+}
+
+/// Start a committee member in-process and return its URL and key state.
+async fn start_member(
+    key_server_object_id: ObjectID,
+    share: MasterKeyShare,
+) -> (String, Arc<ArcSwap<KeyState>>) {
+    let key_state = Arc::new(ArcSwap::from_pointee(member_key_state(
+        key_server_object_id,
+        share,
+    )));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = committee_member_router(key_state.clone());
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    (format!("http://{addr}"), key_state)
+}
+
+fn member_config(url: &str, share: &MasterKeyShare) -> CommitteeMemberConfig {
+    CommitteeMemberConfig {
+        index: share.index,
+        url: url.to_string(),
+        public_key_share: DefaultEncoding::encode(public_key_share(share).value.to_byte_array()),
+    }
+}
+
+fn new_committee(
+    key_server_object_id: ObjectID,
+    threshold: u16,
+    members: &[CommitteeMemberConfig],
+    token: &str,
+) -> anyhow::Result<Committee> {
+    temp_env::with_var(TOKEN_ENV_VAR, Some(token), || {
+        Committee::new(key_server_object_id, threshold, members, TOKEN_ENV_VAR)
+    })
+}
+
+fn decrypt_all(sk: &SecretKey<G1Element>, keys: &[ElgamalEncryption]) -> Vec<G1Element> {
+    keys.iter().map(|key| decrypt(sk, key)).collect()
+}
+
+#[tokio::test]
+async fn test_committee() {
+    let mut rng = thread_rng();
+    let (master_key, public_key) = ibe::generate_key_pair(&mut rng);
+    let key_server_object_id = ObjectID::random();
+    let shares = split(&mut rng, &master_key, 2, 3).unwrap();
+
+    let mut members = vec![];
+    let mut configs = vec![];
+    for share in &shares {
+        let (url, key_state) = start_member(key_server_object_id, share.clone()).await;
+        configs.push(member_config(&url, share));
+        members.push(key_state);
+    }
+
+    let committee = new_committee(key_server_object_id, 2, &configs, TOKEN).unwrap();
+    assert_eq!(committee.public_key, public_key);
+
+    // The combined keys are encryptions of the keys of the master key
+    let (sk, enc_key, _): (_, ElGamalPublicKey, _) = genkey::<_, G2Element, _>(&mut rng);
+    let ids = vec![b"id1".to_vec(), b"id2".to_vec()];
+    let keys = decrypt_all(&sk, &committee.extract(&ids, &enc_key).await.unwrap());
+    assert_eq!(
+        keys,
+        ids.iter()
+            .map(|id| ibe::extract(&master_key, id))
+            .collect::<Vec<_>>()
+    );
+
+    // Requests with more ids than members accept are split
+    let many_ids = (0..=MAX_BATCH_SIZE)
+        .map(|i| i.to_le_bytes().to_vec())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        decrypt_all(&sk, &committee.extract(&many_ids, &enc_key).await.unwrap()),
+        many_ids
+            .iter()
+            .map(|id| ibe::extract(&master_key, id))
+            .collect::<Vec<_>>()
+    );
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/partial_keys", configs[0].url))
+        .bearer_auth(TOKEN)
+        .json(&json!({ "ids": many_ids, "enc_key": enc_key }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let pop = committee.proof_of_possession().await.unwrap();
+    assert!(
+        ibe::verify_proof_of_possession(&pop, &public_key, &key_server_object_id.into_bytes())
+            .is_ok()
+    );
+
+    // A member with a wrong share is ignored as long as enough members are honest
+    let (other_master_key, _) = ibe::generate_key_pair(&mut rng);
+    let wrong_share = split(&mut rng, &other_master_key, 2, 3).unwrap()[2].clone();
+    members[2].store(Arc::new(member_key_state(
+        key_server_object_id,
+        wrong_share,
+    )));
+    assert_eq!(
+        decrypt_all(&sk, &committee.extract(&ids, &enc_key).await.unwrap()),
+        keys
+    );
+
+    // But fewer than threshold valid responses are not enough
+    let mut unreachable = configs.clone();
+    unreachable[0].url = "http://127.0.0.1:1".to_string();
+    let committee = new_committee(key_server_object_id, 2, &unreachable, TOKEN).unwrap();
+    assert_eq!(
+        committee.extract(&ids, &enc_key).await.err(),
+        Some(InternalError::Failure)
+    );
+
+    // Members reject requests with the wrong token
+    let committee =
+        new_committee(key_server_object_id, 2, &configs, &TOKEN.to_uppercase()).unwrap();
+    assert_eq!(
+        committee.extract(&ids, &enc_key).await.err(),
+        Some(InternalError::Failure)
+    );
+}
+
+#[test]
+fn test_committee_config() {
+    let mut rng = thread_rng();
+    let (master_key, _) = ibe::generate_key_pair(&mut rng);
+    let key_server_object_id = ObjectID::random();
+    let shares = split(&mut rng, &master_key, 2, 3).unwrap();
+    let configs = shares
+        .iter()
+        .map(|share| member_config("http://localhost", share))
+        .collect::<Vec<_>>();
+    assert!(new_committee(key_server_object_id, 2, &configs, TOKEN).is_ok());
+    assert!(new_committee(key_server_object_id, 4, &configs, TOKEN).is_err());
+
+    // Public key shares must be consistent
+    let (other_master_key, _) = ibe::generate_key_pair(&mut rng);
+    let mut inconsistent = configs.clone();
+    inconsistent[2] = member_config(
+        "http://localhost",
+        &split(&mut rng, &other_master_key, 2, 3).unwrap()[2],
+    );
+    assert!(new_committee(key_server_object_id, 2, &inconsistent, TOKEN).is_err());
+
+    // The token must be set and have at least 32 bytes
+    assert!(Committee::new(key_server_object_id, 2, &configs, "UNSET_TOKEN_ENV_VAR").is_err());
+    assert!(new_committee(key_server_object_id, 2, &configs, "").is_err());
+    assert!(new_committee(key_server_object_id, 2, &configs, &TOKEN[1..]).is_err());
+}
@@ -1,32 +1,53 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::client_access::ClientAccess;
+use crate::config_reload::KeyState;
 use crate::errors::InternalError::UnsupportedPackageId;
+use crate::key_rotation::OnchainPublicKeys;
 use crate::key_server_options::{
-    ClientConfig, ClientKeyType, KeyServerOptions, RetryConfig, RpcConfig, ServerMode,
+    ClientConfig, ClientKeyType, KeyServerOptions, PolicyEvaluation, RateLimitConfig, RetryConfig,
+    RpcConfig, ServerMode,
 };
-use crate::master_keys::MasterKeys;
+use crate::master_keys::{MasterKeys, VersionedKeys};
+use crate::metrics::Metrics;
+use crate::policy_evaluator::create_policy_evaluator;
+use crate::rate_limiter::RateLimiter;
 use crate::sui_rpc_client::SuiRpcClient;
 use crate::tests::externals::get_key;
 use crate::tests::whitelist::{add_user_to_whitelist, create_whitelist, whitelist_create_ptb};
+use crate::tests::KeyServerType::Open;
 use crate::tests::SealTestCluster;
 use crate::time::from_mins;
 use crate::types::Network;
-use crate::{DefaultEncoding, Server};
+use crate::{handle_fetch_key, handle_get_service, DefaultEncoding, Server};
+use arc_swap::ArcSwap;
+use axum::routing::{get, post};
+use axum::Router;
+use clap::Parser;
 use crypto::ibe::{generate_seed, public_key_from_master_key};
-use crypto::{ibe, seal_decrypt, seal_encrypt, EncryptionInput, IBEPublicKeys, IBEUserSecretKeys};
+use crypto::{
+    create_full_id, ibe, seal_decrypt, seal_encrypt, EncryptionInput, IBEPublicKeys,
+    IBEUserSecretKeys,
+};
 use fastcrypto::encoding::Encoding;
 use fastcrypto::serde_helpers::ToFromByteArray;
+use fastcrypto::traits::KeyPair;
 use futures::future::join_all;
+use prometheus::Registry;
 use rand::thread_rng;
+use seal_cli::remote::RemoteArgs;
 use semver::VersionReq;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use sui_sdk::SuiClient;
 use sui_types::base_types::ObjectID;
-use sui_types::crypto::get_key_pair_from_rng;
+use sui_types::crypto::{get_key_pair_from_rng, EncodeDecodeBase64, SuiKeyPair};
 use test_cluster::TestClusterBuilder;
+use tokio::net::TcpListener;
+use tokio::sync::watch::channel;
 use tracing_test::traced_test;
 
 #[traced_test]
@@ -132,6 +153,10 @@ async fn test_e2e_permissioned() {
                 },
                 key_server_object_id,
                 package_ids: vec![ObjectID::random(), (*package_id).into()],
+                api_key_env_var: None,
+                quota: None,
+                key_version: 0,
+                deprecated_keys: vec![],
             },
             ClientConfig {
                 name: "Client 2 on server 1".to_string(),
@@ -140,6 +165,10 @@ async fn test_e2e_permissioned() {
                 },
                 key_server_object_id: ObjectID::random(),
                 package_ids: vec![ObjectID::random()],
+                api_key_env_var: None,
+                quota: None,
+                key_version: 0,
+                deprecated_keys: vec![],
             },
         ],
         [("MASTER_SEED", seed.as_slice())],
@@ -156,6 +185,10 @@ async fn test_e2e_permissioned() {
             },
             key_server_object_id: ObjectID::random(),
             package_ids: vec![ObjectID::random()],
+            api_key_env_var: None,
+            quota: None,
+            key_version: 0,
+            deprecated_keys: vec![],
         }],
         [("MASTER_SEED", [0u8; 32].as_slice())],
     )
@@ -251,6 +284,10 @@ async fn test_e2e_imported_key() {
             },
             key_server_object_id,
             package_ids: vec![package_id],
+            api_key_env_var: None,
+            quota: None,
+            key_version: 0,
+            deprecated_keys: vec![],
         }],
         [("MASTER_SEED", seed.as_slice())],
     )
@@ -313,10 +350,15 @@ async fn test_e2e_imported_key() {
         vec![ClientConfig {
             name: "Key server client 2".to_string(),
             client_master_key: ClientKeyType::Imported {
-                env_var: "IMPORTED_MASTER_KEY".to_string(),
+                env_var: Some("IMPORTED_MASTER_KEY".to_string()),
+                key_source: None,
             },
             key_server_object_id: ObjectID::random(),
             package_ids: vec![package_id],
+            api_key_env_var: None,
+            quota: None,
+            key_version: 0,
+            deprecated_keys: vec![],
         }],
         [
             (
@@ -354,6 +396,10 @@ async fn test_e2e_imported_key() {
                 },
                 key_server_object_id,
                 package_ids: vec![package_id],
+                api_key_env_var: None,
+                quota: None,
+                key_version: 0,
+                deprecated_keys: vec![],
             },
             ClientConfig {
                 name: "Key server client 3.1".to_string(),
@@ -362,6 +408,10 @@ async fn test_e2e_imported_key() {
                 },
                 key_server_object_id: ObjectID::random(),
                 package_ids: vec![ObjectID::random()],
+                api_key_env_var: None,
+                quota: None,
+                key_version: 0,
+                deprecated_keys: vec![],
             },
         ],
         [("MASTER_SEED", seed.as_slice())],
@@ -388,6 +438,13 @@ async fn create_server(
         allowed_staleness: Duration::from_secs(120),
         session_key_ttl_max: from_mins(30),
         rpc_config: RpcConfig::default(),
+        policy_evaluation: PolicyEvaluation::default(),
+        expose_abort_details: false,
+        policy_cache: None,
+        rate_limits: RateLimitConfig::default(),
+        usage_store_path: None,
+        admin: None,
+        audit_log: None,
         metrics_push_config: None,
     };
 
@@ -397,10 +454,151 @@ async fn create_server(
         .map(|(k, v)| (k.to_string(), Some(DefaultEncoding::encode(v))))
         .collect::<Vec<_>>();
 
+    let sui_rpc_client = SuiRpcClient::new(sui_client, RetryConfig::default(), None);
+    let (latest_checkpoint_sequence_number, _) = channel(0);
     Server {
-        sui_rpc_client: SuiRpcClient::new(sui_client, RetryConfig::default(), None),
-        master_keys: temp_env::with_vars(vars, || MasterKeys::load(&options)).unwrap(),
-        key_server_oid_to_pop: HashMap::new(),
+        policy_evaluator: create_policy_evaluator(
+            &options,
+            &sui_rpc_client,
+            latest_checkpoint_sequence_number.subscribe(),
+        ),
+        rate_limiter: RateLimiter::new(&options.rate_limits),
+        key_state: Arc::new(ArcSwap::from_pointee(KeyState {
+            server_mode: options.server_mode.clone(),
+            master_keys: temp_env::with_vars(vars, || MasterKeys::load(&options)).unwrap(),
+            key_server_oid_to_pop: HashMap::new(),
+            client_access: Arc::new(ClientAccess::load(&options).unwrap()),
+        })),
+        sui_rpc_client,
+        onchain_public_keys: OnchainPublicKeys::default(),
+        latest_checkpoint_sequence_number: Arc::new(latest_checkpoint_sequence_number),
         options,
     }
 }
+
+#[traced_test]
+#[tokio::test]
+async fn test_e2e_key_rotation_follows_onchain_key() {
+    let mut tc = SealTestCluster::new(0).await;
+    let old_key = ibe::generate_key_pair(&mut thread_rng()).0;
+    tc.add_server(Open(old_key), "rotating").await;
+    let (key_server_object_id, server) = &tc.servers[0];
+    let metrics = Metrics::new(&Registry::default());
+
+    // Rotate the master key while the old public key is still registered on-chain
+    let new_key = ibe::generate_key_pair(&mut thread_rng()).0;
+    let key_state = server.key_state.load();
+    server.key_state.store(Arc::new(KeyState {
+        server_mode: key_state.server_mode.clone(),
+        master_keys: MasterKeys::Open {
+            master_keys: VersionedKeys {
+                key_server_object_id: *key_server_object_id,
+                current_version: 1,
+                keys: BTreeMap::from([(0, old_key), (1, new_key)]),
+            },
+        },
+        key_server_oid_to_pop: HashMap::new(),
+        client_access: key_state.client_access.clone(),
+    }));
+    let select_key = |requested_version| {
+        let key_state = server.key_state.load();
+        let keys = key_state
+            .master_keys
+            .get_key_for_key_server(key_server_object_id)
+            .unwrap();
+        server
+            .select_key(keys, requested_version, None)
+            .map(|(version, key)| (version, *key))
+    };
+
+    // Requests without a version use the key registered on-chain
+    server.update_onchain_public_keys(&metrics).await;
+    assert_eq!(select_key(None), Ok((0, old_key)));
+    assert_eq!(select_key(Some(1)), Ok((1, new_key)));
+
+    // Including after the new public key is registered
+    tc.update_key_server_pk(*key_server_object_id, public_key_from_master_key(&new_key))
+        .await;
+    server.update_onchain_public_keys(&metrics).await;
+    assert_eq!(select_key(None), Ok((1, new_key)));
+    assert_eq!(select_key(Some(0)), Ok((0, old_key)));
+}
+
+/// The arguments of `seal-cli fetch-keys` that configure how key servers are reached.
+#[derive(Parser)]
+struct FetchKeysArgs {
+    #[command(flatten)]
+    remote_args: RemoteArgs,
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_e2e_cli_fetch_keys() {
+    let mut tc = SealTestCluster::new(1).await;
+
+    // A key server served over HTTP and registered on-chain with its URL
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let master_key = ibe::generate_key_pair(&mut thread_rng()).0;
+    tc.add_server_with_url(Open(master_key), "local", &url)
+        .await;
+    let router = Router::new()
+        .route("/v1/fetch_key", post(handle_fetch_key))
+        .route("/v1/service", get(handle_get_service))
+        .with_state(tc.state().await);
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    let key_servers = tc.get_services();
+
+    let (package_id, _) = tc.publish("patterns").await;
+    let (whitelist, cap, _) = create_whitelist(tc.test_cluster(), package_id).await;
+    let (other_whitelist, _, _) = create_whitelist(tc.test_cluster(), package_id).await;
+    let user = &tc.users[0];
+    add_user_to_whitelist(tc.test_cluster(), package_id, whitelist, cap, user.address).await;
+
+    // The key of the user in a Sui keystore
+    let dir = std::env::temp_dir().join(format!("seal-cli-{}", ObjectID::random()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let keystore = dir.join("sui.keystore");
+    std::fs::write(
+        &keystore,
+        serde_json::to_string(&[SuiKeyPair::Ed25519(user.keypair.copy()).encode_base64()]).unwrap(),
+    )
+    .unwrap();
+
+    let fetch_keys = |whitelist: ObjectID| {
+        let args = FetchKeysArgs::parse_from([
+            "seal-cli",
+            "--rpc-url",
+            tc.test_cluster().rpc_url(),
+            "--keystore",
+            keystore.to_str().unwrap(),
+            "--address",
+            &user.address.to_string(),
+            "--module",
+            "whitelist",
+            "--object",
+            &whitelist.to_string(),
+        ]);
+        let key_servers = key_servers.clone();
+        async move {
+            args.remote_args
+                .fetch_keys(Some(package_id), Some(whitelist.to_vec()), &key_servers, 1)
+                .await
+        }
+    };
+
+    // The user is on the whitelist
+    let keys = fetch_keys(whitelist).await.unwrap();
+    let full_id = create_full_id(&package_id.into_bytes(), &whitelist.to_vec());
+    assert_eq!(
+        keys[&key_servers[0]][&full_id],
+        ibe::extract(&master_key, &full_id)
+    );
+
+    // But not on the other one
+    assert!(fetch_keys(other_whitelist).await.is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -66,7 +66,7 @@ pub(crate) async fn get_key(
 ) -> Result<G1Element, InternalError> {
     let (sk, pk, vk) = elgamal::genkey(&mut thread_rng());
     let (cert, req_sig) = sign(pkg_id, &ptb, &pk, &vk, kp, current_epoch_time(), 1);
-    server
+    let (pkg_id, ids) = server
         .check_request(
             &ValidPtb::try_from(ptb).unwrap(),
             &pk,
@@ -78,11 +78,14 @@ pub(crate) async fn get_key(
             None,
             None,
         )
-        .await
-        .map(|(pkg_id, ids)| {
-            elgamal::decrypt(
-                &sk,
-                &server.create_response(pkg_id, &ids, &pk).decryption_keys[0].encrypted_key,
-            )
-        })
+        .await?;
+    Ok(elgamal::decrypt(
+        &sk,
+        &server
+            .create_response(pkg_id, &ids, &pk, None, None)
+            .await
+            .unwrap()
+            .decryption_keys[0]
+            .encrypted_key,
+    ))
 }
@@ -1,14 +1,23 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::client_access::ClientAccess;
+use crate::config_reload::KeyState;
 use crate::externals::{add_package, add_upgraded_package};
-use crate::key_server_options::{KeyServerOptions, RetryConfig, RpcConfig, ServerMode};
-use crate::master_keys::MasterKeys;
+use crate::key_rotation::OnchainPublicKeys;
+use crate::key_server_options::{
+    KeyServerOptions, PolicyEvaluation, RateLimitConfig, RetryConfig, RpcConfig, ServerMode,
+};
+use crate::master_keys::{MasterKeys, VersionedKeys};
+use crate::metrics::Metrics;
+use crate::policy_evaluator::create_policy_evaluator;
+use crate::rate_limiter::RateLimiter;
 use crate::sui_rpc_client::SuiRpcClient;
 use crate::tests::KeyServerType::Open;
 use crate::time::from_mins;
 use crate::types::Network;
-use crate::{DefaultEncoding, Server};
+use crate::{start_server_background_tasks, DefaultEncoding, MyState, Server};
+use arc_swap::ArcSwap;
 use crypto::ibe;
 use crypto::ibe::public_key_from_master_key;
 use fastcrypto::ed25519::Ed25519KeyPair;
@@ -18,9 +27,10 @@ use futures::future::join_all;
 use rand::thread_rng;
 use semver::VersionReq;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use sui_move_build::BuildConfig;
 use sui_sdk::json::SuiJsonValue;
@@ -29,7 +39,10 @@ use sui_types::base_types::{ObjectID, SuiAddress};
 use sui_types::crypto::get_key_pair_from_rng;
 use sui_types::move_package::UpgradePolicy;
 use test_cluster::{TestCluster, TestClusterBuilder};
+use tokio::sync::watch::channel;
 
+mod admin;
+mod committee;
 mod e2e;
 mod externals;
 mod pd;
@@ -104,37 +117,71 @@ impl SealTestCluster {
     }
 
     pub async fn add_server(&mut self, server: KeyServerType, name: &str) {
+        // Dummy URL, only used if the key server is served over HTTP
+        self.add_server_with_url(server, name, "http://localhost:8080")
+            .await;
+    }
+
+    /// Add a server that is registered on-chain with the given URL.
+    pub async fn add_server_with_url(&mut self, server: KeyServerType, name: &str, url: &str) {
         match server {
             Open(master_key) => {
                 let key_server_object_id = self
-                    .register_key_server(
-                        name,
-                        "http://localhost:8080", // Dummy URL, not used in this test
-                        public_key_from_master_key(&master_key),
-                    )
+                    .register_key_server(name, url, public_key_from_master_key(&master_key))
                     .await;
+                let sui_rpc_client = SuiRpcClient::new(
+                    self.cluster.sui_client().clone(),
+                    RetryConfig::default(),
+                    None,
+                );
+                let (latest_checkpoint_sequence_number, _) = channel(0);
+                let options = KeyServerOptions {
+                    network: Network::TestCluster,
+                    server_mode: ServerMode::Open {
+                        key_server_object_id,
+                        master_key_source: None,
+                        master_key_version: 0,
+                        deprecated_master_keys: vec![],
+                    },
+                    metrics_host_port: 0,
+                    checkpoint_update_interval: Duration::from_secs(10),
+                    rgp_update_interval: Duration::from_secs(60),
+                    sdk_version_requirement: VersionReq::from_str(">=0.4.6").unwrap(),
+                    allowed_staleness: Duration::from_secs(120),
+                    session_key_ttl_max: from_mins(30),
+                    rpc_config: RpcConfig::default(),
+                    policy_evaluation: PolicyEvaluation::default(),
+                    expose_abort_details: false,
+                    policy_cache: None,
+                    rate_limits: RateLimitConfig::default(),
+                    usage_store_path: None,
+                    admin: None,
+                    audit_log: None,
+                    metrics_push_config: None,
+                };
                 let server = Server {
-                    sui_rpc_client: SuiRpcClient::new(
-                        self.cluster.sui_client().clone(),
-                        RetryConfig::default(),
-                        None,
+                    policy_evaluator: create_policy_evaluator(
+                        &options,
+                        &sui_rpc_client,
+                        latest_checkpoint_sequence_number.subscribe(),
                     ),
-                    master_keys: MasterKeys::Open { master_key },
-                    key_server_oid_to_pop: HashMap::new(),
-                    options: KeyServerOptions {
-                        network: Network::TestCluster,
-                        server_mode: ServerMode::Open {
-                            key_server_object_id,
+                    rate_limiter: RateLimiter::new(&options.rate_limits),
+                    key_state: Arc::new(ArcSwap::from_pointee(KeyState {
+                        server_mode: options.server_mode.clone(),
+                        master_keys: MasterKeys::Open {
+                            master_keys: VersionedKeys {
+                                key_server_object_id,
+                                current_version: 0,
+                                keys: BTreeMap::from([(0, master_key)]),
+                            },
                         },
-                        metrics_host_port: 0,
-                        checkpoint_update_interval: Duration::from_secs(10),
-                        rgp_update_interval: Duration::from_secs(60),
-                        sdk_version_requirement: VersionReq::from_str(">=0.4.6").unwrap(),
-                        allowed_staleness: Duration::from_secs(120),
-                        session_key_ttl_max: from_mins(30),
-                        rpc_config: RpcConfig::default(),
-                        metrics_push_config: None,
-                    },
+                        key_server_oid_to_pop: HashMap::new(),
+                        client_access: Arc::new(ClientAccess::load(&options).unwrap()),
+                    })),
+                    sui_rpc_client,
+                    onchain_public_keys: OnchainPublicKeys::default(),
+                    latest_checkpoint_sequence_number: Arc::new(latest_checkpoint_sequence_number),
+                    options,
                 };
                 self.servers.push((key_server_object_id, server));
             }
@@ -146,6 +193,34 @@ impl SealTestCluster {
         &self.servers[0].1
     }
 
+    /// Change how the first server evaluates policies.
+    pub fn set_policy_evaluation(&mut self, policy_evaluation: PolicyEvaluation) {
+        let server = &mut self.servers[0].1;
+        server.options.policy_evaluation = policy_evaluation;
+        server.policy_evaluator = create_policy_evaluator(
+            &server.options,
+            &server.sui_rpc_client,
+            server.latest_checkpoint_sequence_number.subscribe(),
+        );
+    }
+
+    /// Create the state used by the request handlers for the first server and start its
+    /// background tasks.
+    pub async fn state(&self) -> MyState {
+        let registry = prometheus::Registry::default();
+        let metrics = Arc::new(Metrics::new(&registry));
+        let server = Arc::new(self.server().clone());
+        let (latest_checkpoint_timestamp_receiver, reference_gas_price_receiver, _) =
+            start_server_background_tasks(server.clone(), metrics.clone(), registry, None).await;
+        MyState {
+            metrics,
+            server,
+            latest_checkpoint_timestamp_receiver,
+            reference_gas_price_receiver,
+            audit_log: None,
+        }
+    }
+
     /// Publish the Move module in /move/<module> and return the package id and upgrade cap.
     pub async fn publish(&self, module: &str) -> (ObjectID, ObjectID) {
         Self::publish_internal(&self.cluster, module).await
@@ -267,7 +342,7 @@ impl SealTestCluster {
                 vec![],
                 vec![
                     SuiJsonValue::from_str(description).unwrap(),
-                    SuiJsonValue::from_str(url).unwrap(), // Dummy url, not used in this test
+                    SuiJsonValue::from_str(url).unwrap(),
                     SuiJsonValue::from_str(&0u8.to_string()).unwrap(), // Fix to BF-IBE
                     SuiJsonValue::new(json!(pk.to_byte_array().to_vec())).unwrap(),
                 ],
@@ -297,6 +372,33 @@ impl SealTestCluster {
         service_objects[0].1
     }
 
+    /// Register a new public key in the key server object with the given Object ID, as done when
+    /// its master key is rotated.
+    pub async fn update_key_server_pk(&self, key_server_object_id: ObjectID, pk: ibe::PublicKey) {
+        let tx = self
+            .cluster
+            .sui_client()
+            .transaction_builder()
+            .move_call(
+                self.cluster.get_address_0(),
+                self.registry.0,
+                "key_server",
+                "update_pk",
+                vec![],
+                vec![
+                    SuiJsonValue::from_object_id(key_server_object_id),
+                    SuiJsonValue::new(json!(pk.to_byte_array().to_vec())).unwrap(),
+                ],
+                None,
+                50_000_000,
+                None,
+            )
+            .await
+            .unwrap();
+        let response = self.cluster.sign_and_execute_transaction(&tx).await;
+        assert!(response.status_ok().unwrap());
+    }
+
     /// Get the public keys of the key servers v1 with the given Object IDs.
     pub async fn get_public_keys(&self, object_ids: &[ObjectID]) -> Vec<ibe::PublicKey> {
         let futures = object_ids.iter().map(|id| {
@@ -1,6 +1,7 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::key_server_options::PolicyEvaluation;
 use crate::tests::externals::get_key;
 use crate::tests::SealTestCluster;
 use sui_sdk::{json::SuiJsonValue, rpc_types::ObjectChange};
@@ -63,6 +64,37 @@ async fn test_pd() {
     );
 }
 
+#[traced_test]
+#[tokio::test]
+async fn test_pd_dev_inspect() {
+    let mut tc = SealTestCluster::new(2).await;
+    tc.add_open_server().await;
+    tc.set_policy_evaluation(PolicyEvaluation::DevInspect);
+
+    let (package_id, _) = tc.publish("patterns").await;
+    let (pd, version, digest) =
+        create_private_data(tc.users[0].address, tc.test_cluster(), package_id).await;
+    let ptb = pd_create_ptb(
+        tc.test_cluster(),
+        package_id,
+        package_id,
+        pd,
+        version,
+        digest,
+    )
+    .await;
+    assert!(
+        get_key(tc.server(), &package_id, ptb.clone(), &tc.users[0].keypair)
+            .await
+            .is_ok()
+    );
+    // The policy does not check the owner, so addr2 must be denied by the ownership checks of the
+    // full node
+    assert!(get_key(tc.server(), &package_id, ptb, &tc.users[1].keypair)
+        .await
+        .is_err());
+}
+
 pub(crate) async fn create_private_data(
     user: SuiAddress,
     cluster: &TestCluster,
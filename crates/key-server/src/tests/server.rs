@@ -6,7 +6,7 @@ use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing_test::traced_test;
 
-use crate::externals::get_latest_checkpoint_timestamp;
+use crate::externals::get_latest_checkpoint;
 use crate::key_server_options::RetryConfig;
 use crate::metrics::Metrics;
 use crate::start_server_background_tasks;
@@ -15,9 +15,10 @@ use crate::tests::SealTestCluster;
 
 use crate::signed_message::{signed_message, signed_request};
 use crate::FetchKeyResponse;
-use crate::{app, time, Certificate, DefaultEncoding, FetchKeyRequest};
+use crate::{app, source_ip, time, Certificate, DefaultEncoding, FetchKeyRequest};
 use axum::body::Body;
 use axum::extract::Request;
+use axum::http::HeaderMap;
 use crypto::elgamal;
 use crypto::ibe;
 use crypto::ibe::generate_key_pair;
@@ -37,6 +38,7 @@ use serde_json::json;
 use serde_json::Value;
 use shared_crypto::intent::Intent;
 use shared_crypto::intent::IntentMessage;
+use std::net::IpAddr;
 use std::str::FromStr;
 use sui_types::base_types::{ObjectID, SuiAddress};
 use sui_types::crypto::Signature;
@@ -44,11 +46,11 @@ use sui_types::signature::GenericSignature;
 use tokio::net::TcpListener;
 
 #[tokio::test]
-async fn test_get_latest_checkpoint_timestamp() {
+async fn test_get_latest_checkpoint() {
     let tc = SealTestCluster::new(0).await;
 
     let tolerance = 20000;
-    let timestamp = get_latest_checkpoint_timestamp(SuiRpcClient::new(
+    let (_, timestamp) = get_latest_checkpoint(SuiRpcClient::new(
         tc.cluster.sui_client().clone(),
         RetryConfig::default(),
         None,
@@ -124,6 +126,7 @@ async fn test_server_background_task_monitor() {
             Arc::new(tc.server().clone()),
             metrics.clone(),
             metrics_registry.clone(),
+            None,
         )
         .await;
 
@@ -297,6 +300,8 @@ async fn test_fetch_key() {
         enc_verification_key,
         request_signature,
         certificate,
+        partial_success: false,
+        key_version: None,
     };
 
     // Run test
@@ -344,3 +349,29 @@ async fn test_fetch_key() {
     })
     .await;
 }
+
+#[test]
+fn test_source_ip() {
+    let peer: IpAddr = "10.0.0.1".parse().unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("X-Forwarded-For", "1.1.1.1, 2.2.2.2".parse().unwrap());
+
+    // The header is only used if configured, and then only the address appended by the proxy
+    assert_eq!(source_ip(&headers, None, Some(peer)), Some(peer));
+    assert_eq!(
+        source_ip(&headers, Some("X-Forwarded-For"), Some(peer)),
+        Some("2.2.2.2".parse().unwrap())
+    );
+
+    // Falls back to the peer if the header is missing or invalid
+    assert_eq!(
+        source_ip(&headers, Some("X-Real-Ip"), Some(peer)),
+        Some(peer)
+    );
+    headers.insert("X-Forwarded-For", "1.1.1.1, invalid".parse().unwrap());
+    assert_eq!(
+        source_ip(&headers, Some("X-Forwarded-For"), Some(peer)),
+        Some(peer)
+    );
+    assert_eq!(source_ip(&HeaderMap::new(), None, None), None);
+}
@@ -1,10 +1,30 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::externals::get_key;
+use super::externals::{get_key, sign};
+use crate::audit_log::AuditLog;
+use crate::errors::{ErrorResponse, InternalError};
+use crate::key_server_options::{AuditLogConfig, AuditLogSink, PolicyEvaluation};
+use crate::policy::MoveAbort;
+use crate::signed_message::signed_batch_request;
 use crate::tests::SealTestCluster;
+use crate::time::current_epoch_time;
+use crate::valid_ptb::ValidPtb;
+use crate::{
+    handle_batch_fetch_key, handle_batch_fetch_key_internal, handle_fetch_key,
+    BatchFetchKeyRequest, FetchKeyBundle, FetchKeyRequest, MAX_BATCH_SIZE,
+};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use crypto::create_full_id;
+use crypto::elgamal;
+use fastcrypto::encoding::{Base64, Encoding};
+use fastcrypto::traits::Signer;
+use rand::thread_rng;
 use serde_json::json;
 use std::path::PathBuf;
+use std::sync::Arc;
 use sui_sdk::{json::SuiJsonValue, rpc_types::ObjectChange};
 use sui_types::{
     base_types::{ObjectID, SuiAddress},
@@ -46,6 +66,102 @@ async fn test_whitelist() {
         .is_err());
 }
 
+#[traced_test]
+#[tokio::test]
+async fn test_whitelist_dev_inspect() {
+    let mut tc = SealTestCluster::new(2).await;
+    tc.add_open_server().await;
+    tc.set_policy_evaluation(PolicyEvaluation::DevInspect);
+
+    let (package_id, _) = tc.publish("patterns").await;
+    let (whitelist, cap, initial_shared_version) =
+        create_whitelist(tc.test_cluster(), package_id).await;
+    let user_address = tc.users[0].address;
+    add_user_to_whitelist(tc.test_cluster(), package_id, whitelist, cap, user_address).await;
+
+    let ptb = whitelist_create_ptb(package_id, whitelist, initial_shared_version);
+    assert!(
+        get_key(tc.server(), &package_id, ptb.clone(), &tc.users[0].keypair)
+            .await
+            .is_ok()
+    );
+    assert_eq!(
+        get_key(tc.server(), &package_id, ptb, &tc.users[1].keypair)
+            .await
+            .unwrap_err(),
+        InternalError::NoAccess
+    );
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_whitelist_local_evaluation() {
+    let mut tc = SealTestCluster::new(2).await;
+    tc.add_open_server().await;
+    tc.set_policy_evaluation(PolicyEvaluation::Local);
+
+    let (package_id, _) = tc.publish("patterns").await;
+    let (whitelist, cap, initial_shared_version) =
+        create_whitelist(tc.test_cluster(), package_id).await;
+    let user_address = tc.users[0].address;
+    add_user_to_whitelist(tc.test_cluster(), package_id, whitelist, cap, user_address).await;
+
+    // The whitelist is a table, so its entries are loaded as child objects during execution
+    let ptb = whitelist_create_ptb(package_id, whitelist, initial_shared_version);
+    assert!(
+        get_key(tc.server(), &package_id, ptb.clone(), &tc.users[0].keypair)
+            .await
+            .is_ok()
+    );
+    assert_eq!(
+        get_key(tc.server(), &package_id, ptb, &tc.users[1].keypair)
+            .await
+            .unwrap_err(),
+        InternalError::NoAccess
+    );
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_whitelist_abort_details() {
+    let mut tc = SealTestCluster::new(1).await;
+    tc.add_open_server().await;
+
+    let (package_id, _) = tc.publish("patterns").await;
+    let (whitelist, _, initial_shared_version) =
+        create_whitelist(tc.test_cluster(), package_id).await;
+    let ptb = whitelist_create_ptb(package_id, whitelist, initial_shared_version);
+
+    // Abort details are not exposed by default
+    assert_eq!(
+        get_key(tc.server(), &package_id, ptb.clone(), &tc.users[0].keypair)
+            .await
+            .unwrap_err(),
+        InternalError::NoAccess
+    );
+
+    tc.servers[0].1.options.expose_abort_details = true;
+    let error = get_key(tc.server(), &package_id, ptb, &tc.users[0].keypair)
+        .await
+        .unwrap_err();
+    let abort = MoveAbort {
+        module: "whitelist".to_string(),
+        function: Some("seal_approve".to_string()),
+        code: 1,
+    };
+    assert_eq!(error, InternalError::NoAccessWithAbort(abort.clone()));
+
+    // Clients see a NoAccess error with the details
+    assert_eq!(
+        serde_json::to_value(ErrorResponse::from(error)).unwrap(),
+        json!({
+            "error": "NoAccess",
+            "message": "Access denied: whitelist::seal_approve aborted with code 1",
+            "abort": abort,
+        })
+    );
+}
+
 #[traced_test]
 #[tokio::test]
 async fn test_whitelist_with_upgrade() {
@@ -130,6 +246,470 @@ async fn test_whitelist_with_upgrade() {
     .is_err());
 }
 
+#[traced_test]
+#[tokio::test]
+async fn test_whitelist_batch() {
+    let mut tc = SealTestCluster::new(1).await;
+    tc.add_open_server().await;
+    let state = tc.state().await;
+
+    // Two packages with a whitelist each. The user is only on the first whitelist.
+    let (package_id_1, _) = tc.publish("patterns").await;
+    let (package_id_2, _) = tc.publish("patterns").await;
+    let (whitelist_1, cap_1, isv_1) = create_whitelist(tc.test_cluster(), package_id_1).await;
+    let (whitelist_2, _, isv_2) = create_whitelist(tc.test_cluster(), package_id_2).await;
+    let user = &tc.users[0];
+    add_user_to_whitelist(
+        tc.test_cluster(),
+        package_id_1,
+        whitelist_1,
+        cap_1,
+        user.address,
+    )
+    .await;
+
+    let ptbs = vec![
+        whitelist_create_ptb(package_id_1, whitelist_1, isv_1),
+        whitelist_create_ptb(package_id_2, whitelist_2, isv_2),
+    ];
+    let (_, enc_key, enc_verification_key) = elgamal::genkey(&mut thread_rng());
+    let bundles = [package_id_1, package_id_2]
+        .iter()
+        .zip(&ptbs)
+        .map(|(package_id, ptb)| FetchKeyBundle {
+            ptb: Base64::encode(bcs::to_bytes(ptb).unwrap()),
+            certificate: sign(
+                package_id,
+                ptb,
+                &enc_key,
+                &enc_verification_key,
+                &user.keypair,
+                current_epoch_time(),
+                1,
+            )
+            .0,
+        })
+        .collect();
+    let request_signature = user.keypair.sign(&signed_batch_request(
+        &ptbs,
+        &enc_key,
+        &enc_verification_key,
+    ));
+    let mut request = BatchFetchKeyRequest {
+        bundles,
+        enc_key,
+        enc_verification_key,
+        request_signature,
+        partial_success: false,
+        key_version: None,
+    };
+
+    // The first bundle succeeds and the second is denied
+    let results = handle_batch_fetch_key_internal(&state, &request, None, "", None)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].as_ref().unwrap().0, package_id_1);
+    assert_eq!(results[1].as_ref().unwrap_err(), &InternalError::NoAccess);
+
+    // The session signature must cover the bundles in the given order
+    request.bundles.reverse();
+    assert_eq!(
+        handle_batch_fetch_key_internal(&state, &request, None, "", None)
+            .await
+            .unwrap_err(),
+        InternalError::InvalidSessionSignature
+    );
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_whitelist_audit_log() {
+    let mut tc = SealTestCluster::new(2).await;
+    tc.add_open_server().await;
+
+    let (package_id, _) = tc.publish("patterns").await;
+    let (whitelist, cap, initial_shared_version) =
+        create_whitelist(tc.test_cluster(), package_id).await;
+    let user_address = tc.users[0].address;
+    add_user_to_whitelist(tc.test_cluster(), package_id, whitelist, cap, user_address).await;
+    let ptb = whitelist_create_ptb(package_id, whitelist, initial_shared_version);
+    let encoded_ptb = Base64::encode(bcs::to_bytes(&ptb).unwrap());
+
+    let path = std::env::temp_dir().join(format!("audit-{}.log", ObjectID::random()));
+    let config = AuditLogConfig {
+        sink: AuditLogSink::File {
+            path: path.clone(),
+            max_size_bytes: 1 << 20,
+            max_files: 1,
+        },
+        hash_chain: false,
+    };
+    let mut state = tc.state().await;
+    state.audit_log = Some(Arc::new(AuditLog::new(&config, None).unwrap()));
+
+    // The first user is granted access and the second is denied
+    for user in &tc.users {
+        let (_, enc_key, enc_verification_key) = elgamal::genkey(&mut thread_rng());
+        let (certificate, request_signature) = sign(
+            &package_id,
+            &ptb,
+            &enc_key,
+            &enc_verification_key,
+            &user.keypair,
+            current_epoch_time(),
+            1,
+        );
+        let request = FetchKeyRequest {
+            ptb: encoded_ptb.clone(),
+            enc_key,
+            enc_verification_key,
+            request_signature,
+            certificate,
+            partial_success: false,
+            key_version: None,
+        };
+        let _ = handle_fetch_key(State(state.clone()), HeaderMap::new(), Json(request)).await;
+    }
+
+    // A request that passes the policy check but asks for an unknown key version releases no key
+    let (_, enc_key, enc_verification_key) = elgamal::genkey(&mut thread_rng());
+    let (certificate, request_signature) = sign(
+        &package_id,
+        &ptb,
+        &enc_key,
+        &enc_verification_key,
+        &tc.users[0].keypair,
+        current_epoch_time(),
+        1,
+    );
+    let request = FetchKeyRequest {
+        ptb: encoded_ptb.clone(),
+        enc_key,
+        enc_verification_key,
+        request_signature,
+        certificate,
+        partial_success: false,
+        key_version: Some(1),
+    };
+    assert!(matches!(
+        handle_fetch_key(State(state.clone()), HeaderMap::new(), Json(request)).await,
+        Err(InternalError::UnknownKeyVersion)
+    ));
+
+    // A batch with an invalid session signature is recorded once
+    let (_, enc_key, enc_verification_key) = elgamal::genkey(&mut thread_rng());
+    let (certificate, request_signature) = sign(
+        &package_id,
+        &ptb,
+        &enc_key,
+        &enc_verification_key,
+        &tc.users[0].keypair,
+        current_epoch_time(),
+        1,
+    );
+    let request = BatchFetchKeyRequest {
+        bundles: vec![
+            FetchKeyBundle {
+                ptb: encoded_ptb.clone(),
+                certificate: certificate.clone(),
+            },
+            FetchKeyBundle {
+                ptb: encoded_ptb,
+                certificate,
+            },
+        ],
+        enc_key,
+        enc_verification_key,
+        request_signature,
+        partial_success: false,
+        key_version: None,
+    };
+    assert!(
+        handle_batch_fetch_key(State(state.clone()), HeaderMap::new(), Json(request))
+            .await
+            .is_err()
+    );
+
+    // Dropping the audit log waits for the records to be written
+    drop(state);
+    let records = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[0]["user"], json!(tc.users[0].address));
+    assert_eq!(records[0]["package_id"], json!(package_id));
+    assert_eq!(records[0]["decision"], "granted");
+    assert_eq!(records[1]["user"], json!(tc.users[1].address));
+    assert_eq!(records[1]["decision"], "denied");
+    assert_eq!(records[1]["reason"], "NoAccess");
+    assert_eq!(records[2]["user"], json!(tc.users[0].address));
+    assert_eq!(records[2]["decision"], "denied");
+    assert_eq!(records[2]["reason"], "UnknownKeyVersion");
+    assert_eq!(records[3]["batch"], true);
+    assert_eq!(records[3]["decision"], "denied");
+    assert_eq!(records[3]["reason"], "InvalidSessionSignature");
+    assert_eq!(records[3]["key_ids"].as_array().unwrap().len(), 2);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_whitelist_partial_success() {
+    let mut tc = SealTestCluster::new(1).await;
+    tc.add_open_server().await;
+
+    // Two whitelists of the same package. The user is only on the first one.
+    let (package_id, _) = tc.publish("patterns").await;
+    let (whitelist_1, cap_1, isv_1) = create_whitelist(tc.test_cluster(), package_id).await;
+    let (whitelist_2, _, isv_2) = create_whitelist(tc.test_cluster(), package_id).await;
+    let user = &tc.users[0];
+    add_user_to_whitelist(
+        tc.test_cluster(),
+        package_id,
+        whitelist_1,
+        cap_1,
+        user.address,
+    )
+    .await;
+
+    // A single PTB requesting keys from both whitelists
+    let mut builder = ProgrammableTransactionBuilder::new();
+    for (whitelist, isv) in [(whitelist_1, isv_1), (whitelist_2, isv_2)] {
+        let id = builder.pure(whitelist.to_vec()).unwrap();
+        let list = builder
+            .obj(ObjectArg::SharedObject {
+                id: whitelist,
+                initial_shared_version: isv.into(),
+                mutable: false,
+            })
+            .unwrap();
+        builder.programmable_move_call(
+            package_id,
+            Identifier::new("whitelist").unwrap(),
+            Identifier::new("seal_approve").unwrap(),
+            vec![],
+            vec![id, list],
+        );
+    }
+    let ptb = builder.finish();
+    let valid_ptb = ValidPtb::try_from(ptb.clone()).unwrap();
+
+    let (_, pk, vk) = elgamal::genkey(&mut thread_rng());
+    let (cert, req_sig) = sign(
+        &package_id,
+        &ptb,
+        &pk,
+        &vk,
+        &user.keypair,
+        current_epoch_time(),
+        1,
+    );
+    let check = |partial_success| {
+        tc.server().check_bundle(
+            &valid_ptb,
+            &cert,
+            Some((&pk, &vk, &req_sig)),
+            1000,
+            None,
+            None,
+            None,
+            None,
+            partial_success,
+        )
+    };
+
+    // By default, the request is denied since one of the ids is denied
+    assert_eq!(check(false).await.unwrap_err(), InternalError::NoAccess);
+
+    // With partial success, the key for the first whitelist is returned
+    let (first_pkg_id, ids, errors) = check(true).await.unwrap();
+    assert_eq!(first_pkg_id, package_id);
+    assert_eq!(
+        ids,
+        vec![create_full_id(
+            &package_id.into_bytes(),
+            &whitelist_1.to_vec()
+        )]
+    );
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].id,
+        create_full_id(&package_id.into_bytes(), &whitelist_2.to_vec())
+    );
+    assert_eq!(errors[0].error, InternalError::NoAccess);
+
+    // At most MAX_BATCH_SIZE ids are evaluated individually
+    let mut builder = ProgrammableTransactionBuilder::new();
+    for i in 0..=MAX_BATCH_SIZE {
+        let id = builder
+            .pure([whitelist_1.to_vec(), vec![i as u8]].concat())
+            .unwrap();
+        let list = builder
+            .obj(ObjectArg::SharedObject {
+                id: whitelist_1,
+                initial_shared_version: isv_1.into(),
+                mutable: false,
+            })
+            .unwrap();
+        builder.programmable_move_call(
+            package_id,
+            Identifier::new("whitelist").unwrap(),
+            Identifier::new("seal_approve").unwrap(),
+            vec![],
+            vec![id, list],
+        );
+    }
+    let ptb = builder.finish();
+    let valid_ptb = ValidPtb::try_from(ptb.clone()).unwrap();
+    let (cert, req_sig) = sign(
+        &package_id,
+        &ptb,
+        &pk,
+        &vk,
+        &user.keypair,
+        current_epoch_time(),
+        1,
+    );
+    assert!(matches!(
+        tc.server()
+            .check_bundle(
+                &valid_ptb,
+                &cert,
+                Some((&pk, &vk, &req_sig)),
+                1000,
+                None,
+                None,
+                None,
+                None,
+                true,
+            )
+            .await,
+        Err(InternalError::InvalidPTB(_))
+    ));
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_whitelist_partial_success_denied_ptb() {
+    let mut tc = SealTestCluster::new(1).await;
+    tc.add_open_server().await;
+
+    let (package_id, _) = tc.publish("patterns").await;
+    let (whitelist_1, cap_1, isv_1) = create_whitelist(tc.test_cluster(), package_id).await;
+    let (whitelist_2, _, isv_2) = create_whitelist(tc.test_cluster(), package_id).await;
+    let user = &tc.users[0];
+    add_user_to_whitelist(
+        tc.test_cluster(),
+        package_id,
+        whitelist_1,
+        cap_1,
+        user.address,
+    )
+    .await;
+    let isv = |whitelist| {
+        if whitelist == whitelist_1 {
+            isv_1
+        } else {
+            isv_2
+        }
+    };
+    let full_id =
+        |whitelist: ObjectID| create_full_id(&package_id.into_bytes(), &whitelist.to_vec());
+
+    // PTBs that are denied as a whole, given as (id, whitelist, mutable) per command, together
+    // with the ids granted with partial success or None if the PTB is evaluated as a whole.
+    let cases = [
+        // The second command for the first id is denied, so the first id is denied as well
+        (
+            vec![
+                (whitelist_1, whitelist_1, false),
+                (whitelist_1, whitelist_2, false),
+                (whitelist_2, whitelist_2, false),
+            ],
+            Some(vec![]),
+        ),
+        // Only the first id is approved on its own
+        (
+            vec![
+                (whitelist_1, whitelist_1, false),
+                (whitelist_2, whitelist_2, false),
+            ],
+            Some(vec![full_id(whitelist_1)]),
+        ),
+        // A mutable shared object may have side effects, so the PTB is evaluated as a whole
+        (
+            vec![
+                (whitelist_1, whitelist_1, true),
+                (whitelist_2, whitelist_2, false),
+            ],
+            None,
+        ),
+    ];
+
+    let (_, pk, vk) = elgamal::genkey(&mut thread_rng());
+    for (commands, expected) in cases {
+        let mut builder = ProgrammableTransactionBuilder::new();
+        for (id, whitelist, mutable) in commands {
+            let id = builder.pure(id.to_vec()).unwrap();
+            let list = builder
+                .obj(ObjectArg::SharedObject {
+                    id: whitelist,
+                    initial_shared_version: isv(whitelist).into(),
+                    mutable,
+                })
+                .unwrap();
+            builder.programmable_move_call(
+                package_id,
+                Identifier::new("whitelist").unwrap(),
+                Identifier::new("seal_approve").unwrap(),
+                vec![],
+                vec![id, list],
+            );
+        }
+        let ptb = builder.finish();
+        let valid_ptb = ValidPtb::try_from(ptb.clone()).unwrap();
+        let (cert, req_sig) = sign(
+            &package_id,
+            &ptb,
+            &pk,
+            &vk,
+            &user.keypair,
+            current_epoch_time(),
+            1,
+        );
+        let check = |partial_success| {
+            tc.server().check_bundle(
+                &valid_ptb,
+                &cert,
+                Some((&pk, &vk, &req_sig)),
+                1000,
+                None,
+                None,
+                None,
+                None,
+                partial_success,
+            )
+        };
+
+        assert_eq!(check(false).await.unwrap_err(), InternalError::NoAccess);
+
+        // Partial success never grants an id for which any command is denied
+        match expected {
+            Some(expected) => {
+                let (_, ids, errors) = check(true).await.unwrap();
+                assert_eq!(ids, expected);
+                assert!(errors.iter().all(|error| !ids.contains(&error.id)));
+                assert!(!ids.contains(&full_id(whitelist_2)));
+            }
+            None => assert_eq!(check(true).await.unwrap_err(), InternalError::NoAccess),
+        }
+    }
+}
+
 // TODO: fix next test (as the router was modified)
 // #[traced_test]
 // #[tokio::test]
@@ -7,8 +7,8 @@ use serde::{Deserialize, Serialize};
 
 /// The Identity-based encryption types.
 pub type IbeMasterKey = ibe::MasterKey;
-type IbeDerivedKey = ibe::UserSecretKey;
-type IbePublicKey = ibe::PublicKey;
+pub type IbeDerivedKey = ibe::UserSecretKey;
+pub type IbePublicKey = ibe::PublicKey;
 
 /// ElGamal related types.
 pub type ElGamalPublicKey = elgamal::PublicKey<IbeDerivedKey>;
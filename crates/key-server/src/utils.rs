@@ -62,3 +62,12 @@ pub fn decode_object_id(env_name: &str) -> anyhow::Result<ObjectID> {
     ObjectID::from_hex_literal(&hex_string)
         .map_err(|_| anyhow!("Invalid ObjectID for environment variable {env_name}"))
 }
+
+/// Compare two strings in time independent of the position of the first difference.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
@@ -5,7 +5,7 @@ use crate::return_err;
 use crate::KeyId;
 use crypto::create_full_id;
 use fastcrypto::encoding::{Base64, Encoding};
-use sui_sdk::types::transaction::{Argument, CallArg, Command, ProgrammableTransaction};
+use sui_sdk::types::transaction::{Argument, CallArg, Command, ObjectArg, ProgrammableTransaction};
 use sui_types::base_types::ObjectID;
 use sui_types::transaction::ProgrammableMoveCall;
 use tracing::debug;
@@ -114,15 +114,19 @@ fn get_key_id(
     })
 }
 
+/// Decode a base64 encoded BCS serialized PTB without checking its validity.
+pub fn ptb_from_base64(s: &str) -> Result<ProgrammableTransaction, InternalError> {
+    Base64::decode(s)
+        .map_err(|_| InternalError::InvalidPTB("Invalid Base64".to_string()))
+        .and_then(|b| {
+            bcs::from_bytes::<ProgrammableTransaction>(&b)
+                .map_err(|_| InternalError::InvalidPTB("Invalid BCS".to_string()))
+        })
+}
+
 impl ValidPtb {
     pub fn try_from_base64(s: &str) -> Result<Self, InternalError> {
-        Base64::decode(s)
-            .map_err(|_| InternalError::InvalidPTB("Invalid Base64".to_string()))
-            .and_then(|b| {
-                bcs::from_bytes::<ProgrammableTransaction>(&b)
-                    .map_err(|_| InternalError::InvalidPTB("Invalid BCS".to_string()))
-            })
-            .and_then(ValidPtb::try_from)
+        ptb_from_base64(s).and_then(ValidPtb::try_from)
     }
 
     // The ids without the pkgId prefix
@@ -156,6 +160,53 @@ impl ValidPtb {
     pub fn ptb(&self) -> &ProgrammableTransaction {
         &self.0
     }
+
+    /// Split the PTB into one PTB per distinct id with the commands for that id, such that the ids
+    /// can be evaluated individually. All PTBs keep the inputs of the original PTB.
+    ///
+    /// Note that the commands of an id no longer see the side effects of the commands of other
+    /// ids, so a policy may evaluate differently than in the original PTB. To limit this, None is
+    /// returned if a command uses the result of another command or if the PTB takes a mutable
+    /// shared object, and the PTB should then be evaluated as a whole.
+    pub fn split_by_id(&self) -> Option<Vec<(KeyId, ValidPtb)>> {
+        if self.0.inputs.iter().any(|input| {
+            matches!(
+                input,
+                CallArg::Object(ObjectArg::SharedObject { mutable: true, .. })
+            )
+        }) {
+            return None;
+        }
+        let mut ptbs: Vec<(KeyId, ProgrammableTransaction)> = vec![];
+        for cmd in &self.0.commands {
+            let Command::MoveCall(call) = cmd else {
+                unreachable!()
+            };
+            if call
+                .arguments
+                .iter()
+                .any(|arg| matches!(arg, Argument::Result(_) | Argument::NestedResult(..)))
+            {
+                return None;
+            }
+            let id = get_key_id(&self.0, call).expect("checked above");
+            match ptbs.iter_mut().find(|(other, _)| *other == id) {
+                Some((_, ptb)) => ptb.commands.push(cmd.clone()),
+                None => ptbs.push((
+                    id,
+                    ProgrammableTransaction {
+                        inputs: self.0.inputs.clone(),
+                        commands: vec![cmd.clone()],
+                    },
+                )),
+            }
+        }
+        Some(
+            ptbs.into_iter()
+                .map(|(id, ptb)| (id, ValidPtb(ptb)))
+                .collect(),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -193,6 +244,81 @@ mod tests {
         assert_eq!(valid_ptb.pkg_id(), pkgid);
     }
 
+    #[test]
+    fn test_split_by_id() {
+        let mut builder = ProgrammableTransactionBuilder::new();
+        let id_1 = builder.pure(vec![1u8, 2, 3]).unwrap();
+        let id_2 = builder.pure(vec![4u8, 5, 6]).unwrap();
+        let pkgid = ObjectID::random();
+        for (function, id) in [("seal_approve_x", id_1), ("seal_approve_y", id_2)] {
+            builder.programmable_move_call(
+                pkgid,
+                Identifier::new("bla").unwrap(),
+                Identifier::new(function).unwrap(),
+                vec![],
+                vec![id],
+            );
+        }
+        builder.programmable_move_call(
+            pkgid,
+            Identifier::new("bla").unwrap(),
+            Identifier::new("seal_approve_z").unwrap(),
+            vec![],
+            vec![id_1],
+        );
+        let valid_ptb = ValidPtb::try_from(builder.finish()).unwrap();
+
+        let split = valid_ptb.split_by_id().unwrap();
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].0, vec![1u8, 2, 3]);
+        assert_eq!(split[0].1.inner_ids(), vec![vec![1u8, 2, 3]; 2]);
+        assert_eq!(split[1].0, vec![4u8, 5, 6]);
+        assert_eq!(split[1].1.inner_ids(), vec![vec![4u8, 5, 6]]);
+        assert!(split
+            .iter()
+            .all(|(_, ptb)| ptb.ptb().inputs == valid_ptb.ptb().inputs));
+
+        // Commands using results of other commands cannot be split
+        let mut builder = ProgrammableTransactionBuilder::new();
+        let id = builder.pure(vec![1u8, 2, 3]).unwrap();
+        let result = builder.programmable_move_call(
+            pkgid,
+            Identifier::new("bla").unwrap(),
+            Identifier::new("seal_approve_x").unwrap(),
+            vec![],
+            vec![id],
+        );
+        builder.programmable_move_call(
+            pkgid,
+            Identifier::new("bla").unwrap(),
+            Identifier::new("seal_approve_y").unwrap(),
+            vec![],
+            vec![id, result],
+        );
+        let valid_ptb = ValidPtb::try_from(builder.finish()).unwrap();
+        assert!(valid_ptb.split_by_id().is_none());
+
+        // Commands taking a mutable shared object may have side effects on other commands
+        let mut builder = ProgrammableTransactionBuilder::new();
+        let id = builder.pure(vec![1u8, 2, 3]).unwrap();
+        let object = builder
+            .obj(ObjectArg::SharedObject {
+                id: ObjectID::random(),
+                initial_shared_version: 1.into(),
+                mutable: true,
+            })
+            .unwrap();
+        builder.programmable_move_call(
+            pkgid,
+            Identifier::new("bla").unwrap(),
+            Identifier::new("seal_approve_x").unwrap(),
+            vec![],
+            vec![id, object],
+        );
+        let valid_ptb = ValidPtb::try_from(builder.finish()).unwrap();
+        assert!(valid_ptb.split_by_id().is_none());
+    }
+
     #[test]
     fn test_invalid_empty_ptb() {
         let builder = ProgrammableTransactionBuilder::new();
@@ -0,0 +1,318 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Key ceremony commands: splitting a master key or seed into Shamir shares, reconstructing it from
+//! a quorum of shares and verifying it against a published public key, and splitting a master key
+//! between the members of a key server committee.
+
+use crate::{serializable_to_string, DefaultEncoding, GenSeedOutput, GenkeyOutput, Output};
+use crypto::ibe;
+use crypto::ibe::{MasterKey, SEED_LENGTH};
+use crypto::threshold;
+use crypto::tss;
+use fastcrypto::encoding::Encoding;
+use fastcrypto::error::{FastCryptoError, FastCryptoResult};
+use fastcrypto::serde_helpers::ToFromByteArray;
+use rand::thread_rng;
+use serde_json::{json, Value};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The length of a secret that can be split. Both master keys and seeds are 32 bytes.
+const SECRET_LENGTH: usize = 32;
+
+/// A Shamir share of a master key or a seed, encoded as the index of the share followed by the share.
+#[derive(Debug, Clone)]
+pub struct KeyShare {
+    index: u8,
+    share: [u8; SECRET_LENGTH],
+}
+
+impl FromStr for KeyShare {
+    type Err = FastCryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = DefaultEncoding::decode(s)?;
+        if bytes.len() != SECRET_LENGTH + 1 {
+            return Err(FastCryptoError::InputLengthWrong(SECRET_LENGTH + 1));
+        }
+        Ok(KeyShare {
+            index: bytes[0],
+            share: bytes[1..].try_into().expect("fixed length"),
+        })
+    }
+}
+
+impl Display for KeyShare {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            DefaultEncoding::encode([&[self.index], self.share.as_slice()].concat())
+        )
+    }
+}
+
+pub struct SplitOutput {
+    threshold: u8,
+    shares: Vec<KeyShare>,
+    /// The public key of the split master key. Not set when splitting a seed.
+    public_key: Option<ibe::PublicKey>,
+}
+
+/// Split a master key or a seed into `number_of_shares` shares such that `threshold` of them are
+/// needed to reconstruct it.
+pub fn split(
+    master_key: Option<MasterKey>,
+    seed: Option<[u8; SEED_LENGTH]>,
+    threshold: u8,
+    number_of_shares: u8,
+) -> FastCryptoResult<SplitOutput> {
+    let (secret, public_key) = match (master_key, seed) {
+        (Some(master_key), None) => (
+            master_key.to_byte_array(),
+            Some(ibe::public_key_from_master_key(&master_key)),
+        ),
+        (None, Some(seed)) => (seed, None),
+        _ => return Err(FastCryptoError::InvalidInput),
+    };
+    let sharing = tss::split(&mut thread_rng(), secret, threshold, number_of_shares)?;
+    Ok(SplitOutput {
+        threshold,
+        shares: sharing
+            .indices()
+            .iter()
+            .zip(sharing.shares())
+            .map(|(index, share)| KeyShare {
+                index: *index,
+                share: *share,
+            })
+            .collect(),
+        public_key,
+    })
+}
+
+/// Reconstruct a secret from a quorum of shares.
+fn combine(shares: &[KeyShare]) -> FastCryptoResult<[u8; SECRET_LENGTH]> {
+    tss::combine(
+        &shares
+            .iter()
+            .map(|share| (share.index, share.share))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Reconstruct a master key from a quorum of shares. If a public key is given, the reconstructed
+/// master key must match it.
+pub fn combine_master_key(
+    shares: &[KeyShare],
+    public_key: Option<&ibe::PublicKey>,
+) -> FastCryptoResult<GenkeyOutput> {
+    // If too few or invalid shares are given, the result is most likely not a valid scalar.
+    let master_key = MasterKey::from_byte_array(&combine(shares)?)?;
+    verify_master_key(&master_key, public_key)?;
+    Ok(GenkeyOutput(ibe::into_key_pair(master_key)))
+}
+
+/// Reconstruct a seed from a quorum of shares. If a public key and a derivation index are given,
+/// the master key derived from the reconstructed seed must match the public key.
+pub fn combine_seed(
+    shares: &[KeyShare],
+    public_key: Option<&ibe::PublicKey>,
+    derivation_index: Option<u64>,
+) -> FastCryptoResult<GenSeedOutput> {
+    let seed = combine(shares)?;
+    if let Some(public_key) = public_key {
+        let derivation_index = derivation_index.ok_or(FastCryptoError::InvalidInput)?;
+        verify_master_key(
+            &ibe::derive_master_key(&seed, derivation_index),
+            Some(public_key),
+        )?;
+    }
+    Ok(GenSeedOutput(seed))
+}
+
+/// Check that the public key of the given master key is the expected one.
+pub fn verify_master_key(
+    master_key: &MasterKey,
+    public_key: Option<&ibe::PublicKey>,
+) -> FastCryptoResult<()> {
+    match public_key {
+        Some(public_key) if ibe::public_key_from_master_key(master_key) != *public_key => Err(
+            FastCryptoError::GeneralError("The master key does not match the public key".into()),
+        ),
+        _ => Ok(()),
+    }
+}
+
+impl Display for SplitOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Threshold: {}", self.threshold)?;
+        for share in &self.shares {
+            writeln!(f, "Share {}: {}", share.index, share)?;
+        }
+        if let Some(public_key) = &self.public_key {
+            write!(f, "Public key: {}", serializable_to_string(public_key))?;
+        }
+        Ok(())
+    }
+}
+
+impl Output for SplitOutput {
+    fn to_json(&self) -> Value {
+        json!({
+            "threshold": self.threshold,
+            "shares": self.shares.iter().map(|share| share.to_string()).collect::<Vec<_>>(),
+            "public_key": self.public_key.as_ref().map(serializable_to_string),
+        })
+    }
+}
+
+pub struct SplitCommitteeOutput {
+    threshold: u16,
+    shares: Vec<threshold::MasterKeyShare>,
+    public_key: ibe::PublicKey,
+}
+
+/// Split a master key between `members` members of a key server committee such that `threshold`
+/// of them are needed to compute user secret keys. Unlike [split], the shares are used directly by
+/// the members and the master key is never reconstructed.
+pub fn split_committee(
+    master_key: MasterKey,
+    threshold: u16,
+    members: u16,
+) -> FastCryptoResult<SplitCommitteeOutput> {
+    Ok(SplitCommitteeOutput {
+        threshold,
+        shares: threshold::split(&mut thread_rng(), &master_key, threshold, members)?,
+        public_key: ibe::public_key_from_master_key(&master_key),
+    })
+}
+
+impl Display for SplitCommitteeOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Threshold: {}", self.threshold)?;
+        for share in &self.shares {
+            writeln!(f, "Member {}:", share.index)?;
+            writeln!(f, "  Share: {}", serializable_to_string(&share.share))?;
+            writeln!(
+                f,
+                "  Public key share: {}",
+                serializable_to_string(&threshold::public_key_share(share).value)
+            )?;
+        }
+        write!(
+            f,
+            "Public key: {}",
+            serializable_to_string(&self.public_key)
+        )
+    }
+}
+
+impl Output for SplitCommitteeOutput {
+    fn to_json(&self) -> Value {
+        json!({
+            "threshold": self.threshold,
+            "members": self.shares.iter().map(|share| json!({
+                "index": share.index,
+                "share": serializable_to_string(&share.share),
+                "public_key_share": serializable_to_string(&threshold::public_key_share(share).value),
+            })).collect::<Vec<_>>(),
+            "public_key": serializable_to_string(&self.public_key),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::ibe::generate_seed;
+
+    #[test]
+    fn test_split_and_combine_master_key() {
+        let (master_key, public_key) = ibe::generate_key_pair(&mut thread_rng());
+        let output = split(Some(master_key), None, 3, 5).unwrap();
+        assert_eq!(output.shares.len(), 5);
+        assert_eq!(output.public_key, Some(public_key));
+
+        // Shares survive encoding
+        let shares = output
+            .shares
+            .iter()
+            .map(|share| KeyShare::from_str(&share.to_string()).unwrap())
+            .collect::<Vec<_>>();
+
+        // Any quorum reconstructs the master key
+        for quorum in [&shares[..3], &shares[2..], &shares[1..4]] {
+            let reconstructed = combine_master_key(quorum, Some(&public_key)).unwrap();
+            assert_eq!(reconstructed.0, (master_key, public_key));
+        }
+
+        // Too few shares do not reconstruct the master key
+        assert!(combine_master_key(&shares[..2], Some(&public_key)).is_err());
+
+        // A different public key is rejected
+        let (_, other_public_key) = ibe::generate_key_pair(&mut thread_rng());
+        assert!(combine_master_key(&shares[..3], Some(&other_public_key)).is_err());
+    }
+
+    #[test]
+    fn test_split_and_combine_seed() {
+        let seed = generate_seed(&mut thread_rng());
+        let public_key = ibe::public_key_from_master_key(&ibe::derive_master_key(&seed, 7));
+        let output = split(None, Some(seed), 2, 3).unwrap();
+        assert!(output.public_key.is_none());
+
+        let reconstructed = combine_seed(&output.shares[1..], Some(&public_key), Some(7)).unwrap();
+        assert_eq!(reconstructed.0, seed);
+
+        // The public key is checked for the given derivation index
+        assert!(combine_seed(&output.shares[1..], Some(&public_key), Some(8)).is_err());
+        assert!(combine_seed(&output.shares[1..], Some(&public_key), None).is_err());
+    }
+
+    #[test]
+    fn test_invalid_split() {
+        let seed = generate_seed(&mut thread_rng());
+        assert!(split(None, Some(seed), 4, 3).is_err());
+        assert!(split(None, Some(seed), 0, 3).is_err());
+        assert!(split(None, None, 2, 3).is_err());
+    }
+
+    #[test]
+    fn test_key_share_encoding() {
+        assert!(KeyShare::from_str("0x0102").is_err());
+        let share = KeyShare {
+            index: 3,
+            share: [7; SECRET_LENGTH],
+        };
+        assert_eq!(
+            share.to_string(),
+            format!("0x03{}", "07".repeat(SECRET_LENGTH))
+        );
+    }
+
+    #[test]
+    fn test_split_committee() {
+        let (master_key, public_key) = ibe::generate_key_pair(&mut thread_rng());
+        let output = split_committee(master_key, 2, 3).unwrap();
+        assert_eq!(output.public_key, public_key);
+        assert_eq!(
+            output.shares.iter().map(|s| s.index).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        // The public key shares combine to the public key
+        let public_key_shares = output
+            .shares
+            .iter()
+            .map(threshold::public_key_share)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            threshold::combine(&public_key_shares[1..], 2).unwrap(),
+            public_key
+        );
+
+        assert!(split_committee(master_key, 4, 3).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Editing of key server configuration files, see `key-server-config.yaml`.
+//!
+//! Config files are edited as text to preserve comments and formatting. The result is parsed again
+//! to check that only the intended change was made.
+
+use fastcrypto::error::{FastCryptoError, FastCryptoResult};
+use serde::Deserialize;
+
+/// The parts of the key server options that are needed here. See `KeyServerOptions` in the key server.
+#[derive(Deserialize)]
+struct KeyServerOptions {
+    server_mode: ServerMode,
+}
+
+#[derive(Deserialize)]
+enum ServerMode {
+    Open {},
+    Permissioned { client_configs: Vec<ClientConfig> },
+    Committee {},
+    CommitteeMember {},
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ClientConfig {
+    name: String,
+    client_master_key: ClientKeyType,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+enum ClientKeyType {
+    Derived { derivation_index: u64 },
+    Imported {},
+    Exported { deprecated_derivation_index: u64 },
+}
+
+fn config_error(message: impl Into<String>) -> FastCryptoError {
+    FastCryptoError::GeneralError(message.into())
+}
+
+fn client_configs(config: &str) -> FastCryptoResult<Vec<ClientConfig>> {
+    let options: KeyServerOptions = serde_yaml::from_str(config)
+        .map_err(|e| config_error(format!("Invalid key server config: {}", e)))?;
+    match options.server_mode {
+        ServerMode::Permissioned { client_configs } => Ok(client_configs),
+        _ => Err(config_error("The key server is not in permissioned mode")),
+    }
+}
+
+/// Returns the value of a `key: value` line, ignoring indentation and trailing comments.
+fn yaml_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let value = line.trim_start().strip_prefix(key)?.strip_prefix(':')?;
+    Some(value.split('#').next().unwrap_or_default().trim())
+}
+
+/// Change the client using the derived key with the given derivation index to `Exported`, such that
+/// the key server stops serving the key.
+///
+/// @param config The content of a key server config file in permissioned mode.
+/// @param derivation_index The derivation index of the exported key.
+/// @return The updated config and the name of the client.
+pub fn mark_client_key_exported(
+    config: &str,
+    derivation_index: u64,
+) -> FastCryptoResult<(String, String)> {
+    let before = client_configs(config)?;
+    let client = before
+        .iter()
+        .find(|c| match c.client_master_key {
+            ClientKeyType::Derived {
+                derivation_index: i,
+            }
+            | ClientKeyType::Exported {
+                deprecated_derivation_index: i,
+            } => i == derivation_index,
+            ClientKeyType::Imported { .. } => false,
+        })
+        .ok_or_else(|| {
+            config_error(format!(
+                "No client with derivation index {}",
+                derivation_index
+            ))
+        })?;
+    if matches!(client.client_master_key, ClientKeyType::Exported { .. }) {
+        return Err(config_error(format!(
+            "The key of client {} is already exported",
+            client.name
+        )));
+    }
+
+    // Find the `client_master_key: !Derived` line followed by `derivation_index: <index>`.
+    let mut lines = config.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut position = None;
+    for (i, line) in lines.iter().enumerate() {
+        if yaml_value(line, "client_master_key") != Some("!Derived") {
+            continue;
+        }
+        let index_line = lines[i + 1..]
+            .iter()
+            .position(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+            .map(|j| i + 1 + j)
+            .filter(|j| {
+                yaml_value(&lines[*j], "derivation_index")
+                    == Some(derivation_index.to_string().as_str())
+            });
+        if let Some(j) = index_line {
+            position = Some((i, j));
+            break;
+        }
+    }
+    let (key_type_line, index_line) = position.ok_or(config_error(
+        "The client master key must be written as `client_master_key: !Derived` followed by `derivation_index: <index>` on the next line",
+    ))?;
+    lines[key_type_line] = lines[key_type_line].replacen("!Derived", "!Exported", 1);
+    lines[index_line] =
+        lines[index_line].replacen("derivation_index", "deprecated_derivation_index", 1);
+    let mut updated = lines.join("\n");
+    if config.ends_with('\n') {
+        updated.push('\n');
+    }
+
+    // Check that the only change is the key type of the client.
+    let expected = before
+        .iter()
+        .cloned()
+        .map(|mut c| {
+            if c.name == client.name {
+                c.client_master_key = ClientKeyType::Exported {
+                    deprecated_derivation_index: derivation_index,
+                };
+            }
+            c
+        })
+        .collect::<Vec<_>>();
+    if client_configs(&updated)? != expected {
+        return Err(config_error("Failed to update the key server config"));
+    }
+    Ok((updated, client.name.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"network: Testnet
+server_mode: !Permissioned
+  client_configs:
+    - name: "alice"
+      client_master_key: !Derived
+        derivation_index: 0
+      key_server_object_id: "0xaaaa000000000000000000000000000000000000000000000000000000000001"
+      package_ids:
+        - "0x1111111111111111111111111111111111111111111111111111111111111111"
+    - name: "bob"
+      # Bob's key
+      client_master_key: !Derived
+
+        derivation_index: 1 # Never change this
+      key_server_object_id: "0xbbbb000000000000000000000000000000000000000000000000000000000002"
+      package_ids:
+        - "0x2222222222222222222222222222222222222222222222222222222222222222"
+    - name: "carol"
+      client_master_key: !Exported
+        deprecated_derivation_index: 2
+      key_server_object_id: "0xcccc000000000000000000000000000000000000000000000000000000000003"
+      package_ids:
+        - "0x3333333333333333333333333333333333333333333333333333333333333333"
+"#;
+
+    #[test]
+    fn test_mark_client_key_exported() {
+        let (updated, name) = mark_client_key_exported(CONFIG, 1).unwrap();
+        assert_eq!(name, "bob");
+        assert_eq!(
+            updated,
+            CONFIG
+                .replace(
+                    "# Bob's key\n      client_master_key: !Derived",
+                    "# Bob's key\n      client_master_key: !Exported"
+                )
+                .replace(
+                    "derivation_index: 1 # Never change this",
+                    "deprecated_derivation_index: 1 # Never change this"
+                )
+        );
+
+        let (updated, name) = mark_client_key_exported(&updated, 0).unwrap();
+        assert_eq!(name, "alice");
+        assert!(client_configs(&updated)
+            .unwrap()
+            .iter()
+            .all(|c| matches!(c.client_master_key, ClientKeyType::Exported { .. })));
+    }
+
+    #[test]
+    fn test_mark_client_key_exported_errors() {
+        // Unknown derivation index
+        assert!(mark_client_key_exported(CONFIG, 3).is_err());
+
+        // Already exported
+        assert!(mark_client_key_exported(CONFIG, 2).is_err());
+
+        // Flow style is not supported
+        let flow_style = CONFIG.replace(
+            "client_master_key: !Derived\n        derivation_index: 0",
+            "client_master_key: !Derived { derivation_index: 0 }",
+        );
+        assert!(client_configs(&flow_style).is_ok());
+        assert!(mark_client_key_exported(&flow_style, 0).is_err());
+
+        // Open mode
+        let open = "server_mode: !Open\n  key_server_object_id: \"0x1\"\n";
+        assert!(mark_client_key_exported(open, 0).is_err());
+    }
+}
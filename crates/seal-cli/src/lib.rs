@@ -0,0 +1,6 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The parts of `seal-cli` that are also used by tests of the key server.
+
+pub mod remote;
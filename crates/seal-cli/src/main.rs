@@ -1,26 +1,45 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use clap::{Parser, Subcommand};
+use ceremony::KeyShare;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use crypto::dem::{Aes256Gcm, Hmac256Ctr};
-use crypto::ibe::{generate_seed, SEED_LENGTH};
+use crypto::ibe::{generate_seed, ProofOfPossession, SEED_LENGTH};
+use crypto::keystore::Keystore;
 use crypto::prefixed_hex::PrefixedHex;
 use crypto::EncryptionInput::Plain;
 use crypto::{
     create_full_id, ibe, seal_decrypt, seal_encrypt, Ciphertext, EncryptedObject, EncryptionInput,
     IBEEncryptions, IBEPublicKeys, IBEUserSecretKeys, ObjectID,
 };
-use fastcrypto::encoding::Encoding;
+use fastcrypto::encoding::{Base64, Encoding};
 use fastcrypto::error::{FastCryptoError, FastCryptoResult};
 use fastcrypto::groups::bls12381::{G1Element, G2Element, Scalar};
+use itertools::Itertools;
 use rand::thread_rng;
+use seal_cli::remote::RemoteArgs;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::str::FromStr;
+use validate::KnownKeyServer;
+
+mod ceremony;
+mod config;
+mod validate;
 
 const KEY_LENGTH: usize = 32;
 
+/// The maximum number of keys derived by `derive-range`.
+const MAX_DERIVE_RANGE: u64 = 1000;
+
 /// Default encoding for serializing and deserializing values.
 type DefaultEncoding = PrefixedHex;
 
@@ -29,6 +48,17 @@ type DefaultEncoding = PrefixedHex;
 struct Arguments {
     #[command(subcommand)]
     command: Command,
+    /// The format of the output.
+    #[arg(long = "output", global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// Human readable text.
+    Text,
+    /// Structured JSON. Binary values are encoded using `--out-encoding` where supported and hex otherwise.
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
@@ -47,6 +77,118 @@ enum Command {
         #[arg(long)]
         index: u64,
     },
+    /// Derive the public keys for a range of indices from a seed. This is used to find the public key
+    /// of an unassigned index when onboarding a client in permissioned mode.
+    DeriveRange {
+        /// Seed for the key pairs. Must be 32 bytes.
+        #[arg(long)]
+        seed: EncodedByteArray<SEED_LENGTH>,
+        /// The first index of the range.
+        #[arg(long)]
+        from: u64,
+        /// The last index of the range (inclusive). At most 1000 keys are derived at once.
+        #[arg(long)]
+        to: u64,
+        /// If given, a proof-of-possession for this key server object is also created for each key.
+        #[arg(long)]
+        key_server_id: Option<ObjectID>,
+    },
+    /// Export the derived master key of a permissioned client, e.g., to move the client to another key
+    /// server. The key is printed in the format expected for an `Imported` client key. If a config
+    /// file is given, the client is marked as `Exported` in it such that the key is no longer served.
+    ExportClientKey {
+        /// Seed of the key server. Must be 32 bytes.
+        #[arg(long)]
+        seed: EncodedByteArray<SEED_LENGTH>,
+        /// Derivation index of the client key.
+        #[arg(long)]
+        index: u64,
+        /// The environment variable holding the key on the key server importing it.
+        #[arg(long, default_value = "CLIENT_MASTER_KEY")]
+        env_var: String,
+        /// A key server config file to update.
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+    /// Encrypt a master key into a keystore file unlocked by a passphrase, which key servers can load
+    /// using a `!Keystore` key source. The passphrase is read from an environment variable.
+    CreateKeystore {
+        /// A file holding the hex encoded master key, a BLS12-381 scalar. Use `-` to read it from
+        /// stdin. The key is not accepted as an argument since arguments are visible to other users.
+        #[arg(long, value_name = "FILE")]
+        master_key_file: PathBuf,
+        /// The environment variable holding the passphrase, both here and on the key server.
+        #[arg(long, default_value = "KEYSTORE_PASSPHRASE")]
+        passphrase_env_var: String,
+        /// The keystore file to create. Must not exist.
+        #[arg(long)]
+        path: PathBuf,
+    },
+    /// Split a master key or a seed into Shamir shares such that a threshold of them is needed to
+    /// reconstruct it. Shares should be distributed to different parties and stored offline.
+    SplitKey {
+        /// Master key to split. Hex encoding of a BLS12-381 scalar.
+        #[arg(long, value_parser = parse_serializable::<Scalar, DefaultEncoding>, required_unless_present = "seed", conflicts_with = "seed")]
+        master_key: Option<Scalar>,
+        /// Seed to split. Must be 32 bytes.
+        #[arg(long)]
+        seed: Option<EncodedByteArray<SEED_LENGTH>>,
+        /// The number of shares needed to reconstruct the master key or seed.
+        #[arg(long)]
+        threshold: u8,
+        /// The total number of shares.
+        #[arg(long)]
+        shares: u8,
+    },
+    /// Reconstruct a master key or a seed from a quorum of shares created with `split-key`.
+    CombineKey {
+        /// Hex encoded shares.
+        #[arg(num_args = 1.., required = true)]
+        shares: Vec<KeyShare>,
+        /// The shares are of a seed rather than a master key.
+        #[arg(long)]
+        seed: bool,
+        /// Public key the reconstructed key must match. Hex encoding of a compressed BLS12-381 G2Element.
+        #[arg(long, value_parser = parse_serializable::<G2Element, DefaultEncoding>)]
+        public_key: Option<G2Element>,
+        /// Index of the key pair derived from the reconstructed seed that must match the public key.
+        #[arg(long, requires_all = ["seed", "public_key"])]
+        index: Option<u64>,
+    },
+    /// Split a master key between the members of a key server committee such that a threshold of
+    /// them is needed to compute keys. Each member is configured with its share, and the server
+    /// coordinating the committee with the public key shares of all members.
+    SplitCommitteeKey {
+        /// Master key to split. Hex encoding of a BLS12-381 scalar.
+        #[arg(long, value_parser = parse_serializable::<Scalar, DefaultEncoding>)]
+        master_key: Scalar,
+        /// The number of members needed to compute a key.
+        #[arg(long)]
+        threshold: u16,
+        /// The number of members of the committee.
+        #[arg(long)]
+        members: u16,
+    },
+    /// Verify that a master key, or a key pair derived from a seed, matches a published public key.
+    VerifyMasterKey {
+        /// Master key. Hex encoding of a BLS12-381 scalar.
+        #[arg(long, value_parser = parse_serializable::<Scalar, DefaultEncoding>, required_unless_present = "seed", conflicts_with = "seed")]
+        master_key: Option<Scalar>,
+        /// Seed of the key pair. Must be 32 bytes.
+        #[arg(long, requires = "index")]
+        seed: Option<EncodedByteArray<SEED_LENGTH>>,
+        /// Index of the key pair derived from the seed.
+        #[arg(long, requires = "seed")]
+        index: Option<u64>,
+        /// Public key. Hex encoding of a compressed BLS12-381 G2Element.
+        #[arg(long, value_parser = parse_serializable::<G2Element, DefaultEncoding>)]
+        public_key: G2Element,
+    },
+    /// Create or verify a proof-of-possession of a master key, as served by key servers.
+    Pop {
+        #[command(subcommand)]
+        command: PopCommand,
+    },
     /// Extract a user secret key from an id and a master key.
     Extract {
         /// The Sui address of the Move package that handles the KMS for this key
@@ -98,9 +240,9 @@ enum Command {
     /// The key is derived from the ID using an IBKEM, Boneh-Franklin over BLS12381, and the message is encrypted using AES-256-GCM.
     /// This outputs both the encrypted object as a hex-encoded BCS serialization, which can be shared publicly, and the derived symmetric key which should be kept privately.
     EncryptAes {
-        /// The message to encrypt as hex-encoded bytes
-        #[arg(long)]
-        message: EncodedBytes,
+        /// The message to encrypt as hex-encoded bytes. Use `--in` to read the message from a file or stdin instead.
+        #[arg(long, required_unless_present = "input", conflicts_with = "input")]
+        message: Option<EncodedBytes>,
         /// Optional additional authenticated data as hex-encoded bytes
         #[arg(long)]
         aad: Option<EncodedBytes>,
@@ -119,14 +261,18 @@ enum Command {
         /// The address for the Move objects representing the key servers
         #[arg(num_args = 1.., last = true)]
         object_ids: Vec<ObjectID>,
+        #[command(flatten)]
+        input: InputArgs,
+        #[command(flatten)]
+        output: OutputArgs,
     },
     /// Encrypt a message using Seal.
     /// The key is derived from the ID using an IBKEM, Boneh-Franklin over BLS12381, and the message is encrypted using counter-mode with hmac-sha3-256 as a PRF.
     /// This outputs both the encrypted object as a hex-encoded BCS serialization, which can be shared publicly, and the derived symmetric key which should be kept privately.
     EncryptHmac {
-        /// The message to encrypt as hex-encoded bytes
-        #[arg(long)]
-        message: EncodedBytes,
+        /// The message to encrypt as hex-encoded bytes. Use `--in` to read the message from a file or stdin instead.
+        #[arg(long, required_unless_present = "input", conflicts_with = "input")]
+        message: Option<EncodedBytes>,
         /// Optional additional authenticated data as hex-encoded bytes
         #[arg(long)]
         aad: Option<EncodedBytes>,
@@ -145,53 +291,195 @@ enum Command {
         /// The address for the Move objects representing the key servers
         #[arg(num_args = 1.., last = true)]
         object_ids: Vec<ObjectID>,
+        #[command(flatten)]
+        input: InputArgs,
+        #[command(flatten)]
+        output: OutputArgs,
     },
     /// Decrypt a Seal encrypted object.
     /// In case the encrypted object holds a message, this is returned.
     /// If Plain was used, the derived encryption key is returned.
     Decrypt {
-        /// An encrypted object as hex-encoded bytes
-        #[arg(value_parser = parse_serializable::<EncryptedObject, DefaultEncoding>)]
-        encrypted_object: EncryptedObject,
-        /// The secret keys for the key servers. The order of the keys must match the order of the key servers in the object_ids field.
-        #[arg(value_parser = parse_serializable::<G1Element, DefaultEncoding>, num_args = 1..)]
-        secret_keys: Vec<G1Element>,
+        /// An encrypted object as hex-encoded bytes followed by the secret keys for the key servers.
+        /// The encrypted object must be omitted if it is read using `--in`, and the secret keys must be omitted if `--remote` is used.
+        /// The order of the keys must match the order of the key servers in the object_ids field.
+        #[arg(num_args = 1.., required_unless_present = "input", value_name = "[ENCRYPTED_OBJECT] SECRET_KEYS")]
+        encrypted_object_and_secret_keys: Vec<String>,
         /// The address for the Move objects representing the key servers used for this decryption.
-        #[arg(num_args = 1.., last = true)]
+        #[arg(num_args = 1.., last = true, conflicts_with = "remote")]
         object_ids: Vec<ObjectID>,
+        /// Fetch the keys from the key servers of the encrypted object instead of using secret keys.
+        /// The PTB is either given with `--ptb` or built to call `--module::--function` with the id of the encrypted object.
+        #[arg(long)]
+        remote: bool,
+        #[command(flatten)]
+        remote_args: RemoteArgs,
+        #[command(flatten)]
+        input: InputArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Fetch user secret keys from key servers.
+    /// The keys are requested with a session key signed by a key from the local Sui keystore and verified against the public keys of the key servers.
+    FetchKeys {
+        /// The first version of the package that handles the KMS for this key. Defaults to the package called by the PTB.
+        #[arg(long)]
+        package_id: Option<ObjectID>,
+        /// The ID of the key, without the package id prefix. Required if no PTB is given.
+        #[arg(long)]
+        id: Option<EncodedBytes>,
+        /// The object ids of the key servers. Their URLs and public keys are read from chain.
+        #[arg(long = "key-server", value_name = "OBJECT_ID", num_args = 1.., required = true)]
+        key_servers: Vec<ObjectID>,
+        /// The number of key servers that must return keys.
+        #[arg(long)]
+        threshold: u8,
+        #[command(flatten)]
+        remote_args: RemoteArgs,
     },
     /// Parse a Seal encrypted object.
     /// This outputs the parts of the parsed encrypted object as a hex-encoded BCS serialization.
     Parse {
-        /// The encrypted object as hex-encoded bytes
-        #[arg(value_parser = parse_serializable::<EncryptedObject, DefaultEncoding>)]
-        encrypted_object: EncryptedObject,
+        /// The encrypted object as hex-encoded bytes. Use `--in` to read it from a file or stdin instead.
+        #[arg(value_parser = parse_serializable::<EncryptedObject, DefaultEncoding>, required_unless_present = "input", conflicts_with = "input")]
+        encrypted_object: Option<EncryptedObject>,
+        #[command(flatten)]
+        input: InputArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Check an encrypted object for structural problems, e.g., when it cannot be decrypted.
+    /// This reports all findings instead of failing on the first problem like `parse`.
+    Validate {
+        /// The encrypted object as hex-encoded bytes. Use `--in` to read it from a file or stdin instead.
+        #[arg(required_unless_present = "input", conflicts_with = "input")]
+        encrypted_object: Option<EncodedBytes>,
+        /// Known key servers as `<OBJECT_ID>=<PUBLIC_KEY>`. If given, the key servers of the object
+        /// are checked against them.
+        #[arg(long = "key-server", value_name = "OBJECT_ID=PUBLIC_KEY")]
+        key_servers: Vec<KnownKeyServer>,
+        #[command(flatten)]
+        input: InputArgs,
     },
     SymmetricDecrypt {
-        /// An encrypted object as hex-encoded bytes.
-        #[arg(value_parser = parse_serializable::<EncryptedObject, DefaultEncoding>)]
-        encrypted_object: EncryptedObject,
+        /// An encrypted object as hex-encoded bytes. Use `--in` to read it from a file or stdin instead.
+        #[arg(value_parser = parse_serializable::<EncryptedObject, DefaultEncoding>, required_unless_present = "input", conflicts_with = "input")]
+        encrypted_object: Option<EncryptedObject>,
         /// The derived symmetric key from the encryption.
         #[arg(long)]
         key: EncodedByteArray<KEY_LENGTH>,
+        #[command(flatten)]
+        input: InputArgs,
+        #[command(flatten)]
+        output: OutputArgs,
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum PopCommand {
+    /// Create a proof-of-possession of a master key for a key server object.
+    Create {
+        /// Master key. Hex encoding of a BLS12-381 scalar.
+        #[arg(long, value_parser = parse_serializable::<Scalar, DefaultEncoding>)]
+        master_key: Scalar,
+        /// The object id of the key server that the proof-of-possession is bound to.
+        #[arg(long)]
+        key_server_id: ObjectID,
+    },
+    /// Verify a proof-of-possession against a public key and a key server object.
+    Verify {
+        /// Public key. Hex encoding of a compressed BLS12-381 G2Element.
+        #[arg(long, value_parser = parse_serializable::<G2Element, DefaultEncoding>)]
+        public_key: G2Element,
+        /// The object id of the key server that the proof-of-possession is bound to.
+        #[arg(long)]
+        key_server_id: ObjectID,
+        /// Proof-of-possession. Hex encoding of a compressed BLS12-381 G1Element.
+        #[arg(long, value_parser = parse_serializable::<G1Element, DefaultEncoding>)]
+        pop: G1Element,
+    },
+}
+
+/// Encoding of binary inputs and outputs.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum ByteEncoding {
+    /// Hex encoding with a `0x` prefix.
+    Hex,
+    Base64,
+    /// Raw bytes. Values in text outputs are hex encoded.
+    Binary,
+}
+
+/// Arguments for reading the input of a command from a file or stdin.
+#[derive(Args, Debug)]
+struct InputArgs {
+    /// Read the input from a file. Use `-` to read from stdin.
+    #[arg(long = "in", value_name = "FILE")]
+    input: Option<PathBuf>,
+    /// The encoding of the input read using `--in`.
+    #[arg(long, value_enum, default_value_t = ByteEncoding::Binary)]
+    in_encoding: ByteEncoding,
+}
+
+/// Arguments for writing the output of a command to a file or stdout.
+#[derive(Args, Debug)]
+struct OutputArgs {
+    /// Write the output to a file. Use `-` to write to stdout.
+    /// Additional outputs, e.g., the symmetric key when encrypting, are written to stderr.
+    #[arg(long = "out", value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// The encoding of the output.
+    #[arg(long, value_enum, default_value_t = ByteEncoding::Hex)]
+    out_encoding: ByteEncoding,
+}
+
 struct GenkeyOutput((Scalar, G2Element));
 struct GenSeedOutput([u8; SEED_LENGTH]);
+struct DeriveRangeOutput(Vec<(u64, G2Element, Option<ProofOfPossession>)>);
+struct ExportClientKeyOutput {
+    key_pair: (Scalar, G2Element),
+    env_var: String,
+    /// The name of the client marked as exported in the config file, if any.
+    exported_client: Option<String>,
+}
+struct CreateKeystoreOutput {
+    public_key: G2Element,
+    path: PathBuf,
+    passphrase_env_var: String,
+}
+struct PopOutput(ProofOfPossession);
 struct ExtractOutput(G1Element);
 struct VerifyOutput(FastCryptoResult<()>);
-struct EncryptionOutput((EncryptedObject, [u8; KEY_LENGTH]));
-struct DecryptionOutput(Vec<u8>);
-struct ParseOutput(EncryptedObject);
-struct SymmetricDecryptOutput(Vec<u8>);
+struct EncryptionOutput((EncryptedObject, [u8; KEY_LENGTH]), ByteEncoding);
+struct DecryptionOutput(Vec<u8>, ByteEncoding);
+struct ParseOutput(EncryptedObject, ByteEncoding);
+struct SymmetricDecryptOutput(Vec<u8>, ByteEncoding);
+struct FetchKeysOutput(HashMap<ObjectID, HashMap<Vec<u8>, G1Element>>);
+struct ErrorOutput(FastCryptoError);
 
-fn main() -> FastCryptoResult<()> {
+fn main() -> ExitCode {
     let args = Arguments::parse();
+    let format = args.output_format;
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // JSON errors are printed to stdout so they can be parsed like any other output.
+            let output = ErrorOutput(e);
+            match format {
+                OutputFormat::Text => eprintln!("{}", output),
+                OutputFormat::Json => println!("{}", output.render(format)),
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Arguments) -> FastCryptoResult<()> {
+    let format = args.output_format;
 
     let output = match args.command {
-        Command::Genkey => GenkeyOutput(ibe::generate_key_pair(&mut thread_rng())).to_string(),
-        Command::GenSeed => GenSeedOutput(generate_seed(&mut thread_rng())).to_string(),
+        Command::Genkey => GenkeyOutput(ibe::generate_key_pair(&mut thread_rng())).render(format),
+        Command::GenSeed => GenSeedOutput(generate_seed(&mut thread_rng())).render(format),
         Command::DeriveKey {
             seed,
             index: derivation_index,
@@ -203,8 +491,162 @@ fn main() -> FastCryptoResult<()> {
                 &seed.0,
                 derivation_index,
             )))
-            .to_string()
+            .render(format)
+        }
+        Command::DeriveRange {
+            seed,
+            from,
+            to,
+            key_server_id,
+        } => {
+            if from > to {
+                return Err(FastCryptoError::GeneralError(
+                    "--from must not be larger than --to".to_string(),
+                ));
+            }
+            if to - from >= MAX_DERIVE_RANGE {
+                return Err(FastCryptoError::GeneralError(format!(
+                    "At most {} keys can be derived at once",
+                    MAX_DERIVE_RANGE
+                )));
+            }
+            DeriveRangeOutput(
+                (from..=to)
+                    .map(|derivation_index| {
+                        let master_key = ibe::derive_master_key(&seed.0, derivation_index);
+                        (
+                            derivation_index,
+                            ibe::public_key_from_master_key(&master_key),
+                            key_server_id.map(|key_server_id| {
+                                ibe::create_proof_of_possession(
+                                    &master_key,
+                                    &key_server_id.into_bytes(),
+                                )
+                            }),
+                        )
+                    })
+                    .collect(),
+            )
+            .render(format)
+        }
+        Command::ExportClientKey {
+            seed,
+            index: derivation_index,
+            env_var,
+            config: config_path,
+        } => {
+            let exported_client = match config_path {
+                Some(path) => {
+                    let content = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+                    let (updated, name) =
+                        config::mark_client_key_exported(&content, derivation_index)?;
+                    fs::write(&path, updated).map_err(|e| io_error(&path, e))?;
+                    Some(name)
+                }
+                None => None,
+            };
+            ExportClientKeyOutput {
+                key_pair: ibe::into_key_pair(ibe::derive_master_key(&seed.0, derivation_index)),
+                env_var,
+                exported_client,
+            }
+            .render(format)
+        }
+        Command::CreateKeystore {
+            master_key_file,
+            passphrase_env_var,
+            path,
+        } => {
+            let master_key = read_master_key(&master_key_file)?;
+            let passphrase = std::env::var(&passphrase_env_var).map_err(|_| {
+                FastCryptoError::GeneralError(format!(
+                    "Environment variable {} must be set",
+                    passphrase_env_var
+                ))
+            })?;
+            let master_key_bytes = bcs::to_bytes(&master_key).expect("serialization failed");
+            let keystore = Keystore::encrypt(&master_key_bytes, &passphrase, &mut thread_rng())?;
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+                .map_err(|e| io_error(&path, e))?;
+            file.write_all(
+                serde_json::to_string_pretty(&keystore)
+                    .expect("serialization failed")
+                    .as_bytes(),
+            )
+            .map_err(|e| io_error(&path, e))?;
+            CreateKeystoreOutput {
+                public_key: ibe::public_key_from_master_key(&master_key),
+                path,
+                passphrase_env_var,
+            }
+            .render(format)
         }
+        Command::SplitKey {
+            master_key,
+            seed,
+            threshold,
+            shares,
+        } => {
+            ceremony::split(master_key, seed.map(|seed| seed.0), threshold, shares)?.render(format)
+        }
+        Command::CombineKey {
+            shares,
+            seed: false,
+            public_key,
+            ..
+        } => ceremony::combine_master_key(&shares, public_key.as_ref())?.render(format),
+        Command::CombineKey {
+            shares,
+            seed: true,
+            public_key,
+            index,
+        } => ceremony::combine_seed(&shares, public_key.as_ref(), index)?.render(format),
+        Command::SplitCommitteeKey {
+            master_key,
+            threshold,
+            members,
+        } => ceremony::split_committee(master_key, threshold, members)?.render(format),
+        Command::VerifyMasterKey {
+            master_key,
+            seed,
+            index,
+            public_key,
+        } => {
+            let master_key = match (master_key, seed, index) {
+                (Some(master_key), _, _) => master_key,
+                (None, Some(seed), Some(index)) => ibe::derive_master_key(&seed.0, index),
+                _ => return Err(FastCryptoError::InvalidInput),
+            };
+            VerifyOutput(ceremony::verify_master_key(&master_key, Some(&public_key))).render(format)
+        }
+        Command::Pop {
+            command:
+                PopCommand::Create {
+                    master_key,
+                    key_server_id,
+                },
+        } => PopOutput(ibe::create_proof_of_possession(
+            &master_key,
+            &key_server_id.into_bytes(),
+        ))
+        .render(format),
+        Command::Pop {
+            command:
+                PopCommand::Verify {
+                    public_key,
+                    key_server_id,
+                    pop,
+                },
+        } => VerifyOutput(ibe::verify_proof_of_possession(
+            &pop,
+            &public_key,
+            &key_server_id.into_bytes(),
+        ))
+        .render(format),
         Command::Extract {
             package_id,
             id,
@@ -213,7 +655,7 @@ fn main() -> FastCryptoResult<()> {
             &master_key,
             &create_full_id(&package_id, &id.0),
         ))
-        .to_string(),
+        .render(format),
         Command::Verify {
             package_id,
             id,
@@ -224,22 +666,25 @@ fn main() -> FastCryptoResult<()> {
             &create_full_id(&package_id, &id.0),
             &public_key,
         ))
-        .to_string(),
+        .render(format),
         Command::Plain {
             package_id,
             id,
             threshold,
             public_keys,
             object_ids,
-        } => EncryptionOutput(seal_encrypt(
-            package_id,
-            id.0,
-            object_ids,
-            &IBEPublicKeys::BonehFranklinBLS12381(public_keys),
-            threshold,
-            Plain,
-        )?)
-        .to_string(),
+        } => EncryptionOutput(
+            seal_encrypt(
+                package_id,
+                id.0,
+                object_ids,
+                &IBEPublicKeys::BonehFranklinBLS12381(public_keys),
+                threshold,
+                Plain,
+            )?,
+            ByteEncoding::Hex,
+        )
+        .render(format),
         Command::EncryptAes {
             message,
             aad,
@@ -248,18 +693,31 @@ fn main() -> FastCryptoResult<()> {
             threshold,
             public_keys,
             object_ids,
-        } => EncryptionOutput(seal_encrypt(
-            package_id,
-            id.0,
-            object_ids,
-            &IBEPublicKeys::BonehFranklinBLS12381(public_keys),
-            threshold,
-            EncryptionInput::Aes256Gcm {
-                data: message.0,
-                aad: aad.map(|a| a.0),
-            },
-        )?)
-        .to_string(),
+            input,
+            output,
+        } => {
+            let data = match message {
+                Some(message) => message.0,
+                None => input.read()?,
+            };
+            return output.write(
+                &EncryptionOutput(
+                    seal_encrypt(
+                        package_id,
+                        id.0,
+                        object_ids,
+                        &IBEPublicKeys::BonehFranklinBLS12381(public_keys),
+                        threshold,
+                        EncryptionInput::Aes256Gcm {
+                            data,
+                            aad: aad.map(|a| a.0),
+                        },
+                    )?,
+                    output.out_encoding,
+                ),
+                format,
+            );
+        }
         Command::EncryptHmac {
             message,
             aad,
@@ -268,45 +726,136 @@ fn main() -> FastCryptoResult<()> {
             threshold,
             public_keys,
             object_ids,
-        } => EncryptionOutput(seal_encrypt(
-            package_id,
-            id.0,
+            input,
+            output,
+        } => {
+            let data = match message {
+                Some(message) => message.0,
+                None => input.read()?,
+            };
+            return output.write(
+                &EncryptionOutput(
+                    seal_encrypt(
+                        package_id,
+                        id.0,
+                        object_ids,
+                        &IBEPublicKeys::BonehFranklinBLS12381(public_keys),
+                        threshold,
+                        EncryptionInput::Hmac256Ctr {
+                            data,
+                            aad: aad.map(|a| a.0),
+                        },
+                    )?,
+                    output.out_encoding,
+                ),
+                format,
+            );
+        }
+        Command::Decrypt {
+            encrypted_object_and_secret_keys,
             object_ids,
-            &IBEPublicKeys::BonehFranklinBLS12381(public_keys),
+            remote,
+            remote_args,
+            input,
+            output,
+        } => {
+            // If the encrypted object is not read from a file, it is the first positional argument.
+            let mut args = encrypted_object_and_secret_keys.iter();
+            let encrypted_object = if input.input.is_some() {
+                input.read_serializable()?
+            } else {
+                parse_arg::<EncryptedObject>(args.next().ok_or(FastCryptoError::GeneralError(
+                    "Missing encrypted object".to_string(),
+                ))?)?
+            };
+            let secret_keys = args
+                .map(|key| parse_arg::<G1Element>(key))
+                .collect::<FastCryptoResult<Vec<_>>>()?;
+            if remote {
+                if !secret_keys.is_empty() {
+                    return Err(FastCryptoError::GeneralError(
+                        "Secret keys cannot be used with --remote".to_string(),
+                    ));
+                }
+                let message =
+                    tokio_runtime().block_on(decrypt_remote(&encrypted_object, &remote_args))?;
+                return output.write(&DecryptionOutput(message, output.out_encoding), format);
+            }
+            return output.write(
+                &DecryptionOutput(
+                    seal_decrypt(
+                        &encrypted_object, // TODO
+                        &IBEUserSecretKeys::BonehFranklinBLS12381(
+                            object_ids.into_iter().zip(secret_keys).collect(),
+                        ),
+                        None,
+                    )?,
+                    output.out_encoding,
+                ),
+                format,
+            );
+        }
+        Command::FetchKeys {
+            package_id,
+            id,
+            key_servers,
             threshold,
-            EncryptionInput::Hmac256Ctr {
-                data: message.0,
-                aad: aad.map(|a| a.0),
-            },
-        )?)
-        .to_string(),
-        Command::Decrypt {
+            remote_args,
+        } => tokio_runtime()
+            .block_on(remote_args.fetch_keys(
+                package_id,
+                id.map(|id| id.0),
+                &key_servers,
+                threshold,
+            ))
+            .map(FetchKeysOutput)?
+            .render(format),
+        Command::Parse {
             encrypted_object,
-            secret_keys,
-            object_ids,
-        } => DecryptionOutput(seal_decrypt(
-            &encrypted_object, // TODO
-            &IBEUserSecretKeys::BonehFranklinBLS12381(
-                object_ids.into_iter().zip(secret_keys).collect(),
-            ),
-            None,
-        )?)
-        .to_string(),
-        Command::Parse { encrypted_object } => ParseOutput(encrypted_object).to_string(),
+            input,
+            output,
+        } => {
+            let encrypted_object = match encrypted_object {
+                Some(encrypted_object) => encrypted_object,
+                None => input.read_serializable()?,
+            };
+            return output.write(&ParseOutput(encrypted_object, output.out_encoding), format);
+        }
+        Command::Validate {
+            encrypted_object,
+            key_servers,
+            input,
+        } => {
+            let bytes = match encrypted_object {
+                Some(encrypted_object) => encrypted_object.0,
+                None => input.read()?,
+            };
+            validate::validate(&bytes, &key_servers).render(format)
+        }
         Command::SymmetricDecrypt {
             encrypted_object,
             key,
-        } => match encrypted_object.ciphertext {
-            Ciphertext::Aes256Gcm { blob, aad } => {
-                Aes256Gcm::decrypt(&blob, &aad.unwrap_or(vec![]), &key.0)
-            }
-            Ciphertext::Hmac256Ctr { blob, aad, mac } => {
-                Hmac256Ctr::decrypt(&blob, &mac, &aad.unwrap_or(vec![]), &key.0)
-            }
-            _ => Err(FastCryptoError::InvalidInput),
+            input,
+            output,
+        } => {
+            let encrypted_object = match encrypted_object {
+                Some(encrypted_object) => encrypted_object,
+                None => input.read_serializable()?,
+            };
+            let message = match encrypted_object.ciphertext {
+                Ciphertext::Aes256Gcm { blob, aad } => {
+                    Aes256Gcm::decrypt(&blob, &aad.unwrap_or(vec![]), &key.0)
+                }
+                Ciphertext::Hmac256Ctr { blob, aad, mac } => {
+                    Hmac256Ctr::decrypt(&blob, &mac, &aad.unwrap_or(vec![]), &key.0)
+                }
+                _ => Err(FastCryptoError::InvalidInput),
+            }?;
+            return output.write(
+                &SymmetricDecryptOutput(message, output.out_encoding),
+                format,
+            );
         }
-        .map(SymmetricDecryptOutput)?
-        .to_string(),
     };
     println!("{}", output);
     Ok(())
@@ -342,6 +891,170 @@ impl<const N: usize> FromStr for EncodedByteArray<N> {
     }
 }
 
+fn tokio_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Runtime::new().expect("failed to create runtime")
+}
+
+/// Fetch keys from the key servers of the encrypted object and decrypt it.
+async fn decrypt_remote(
+    encrypted_object: &EncryptedObject,
+    remote_args: &RemoteArgs,
+) -> FastCryptoResult<Vec<u8>> {
+    let sui_client = remote_args.sui_client().await?;
+    let key_servers = encrypted_object
+        .services
+        .iter()
+        .map(|(object_id, _)| *object_id)
+        .collect::<Vec<_>>();
+    let ptb = remote_args
+        .ptb(
+            &sui_client,
+            Some(encrypted_object.package_id),
+            Some(encrypted_object.id.clone()),
+        )
+        .await?;
+    let session_key = remote_args.session_key(encrypted_object.package_id)?;
+    let client = remote_args.seal_client(&sui_client, &key_servers).await?;
+    client
+        .decrypt(encrypted_object, &ptb, &session_key)
+        .await
+        .map_err(|e| FastCryptoError::GeneralError(e.to_string()))
+}
+
+fn parse_arg<T: for<'a> Deserialize<'a>>(s: &str) -> FastCryptoResult<T> {
+    parse_serializable::<T, DefaultEncoding>(s).map_err(FastCryptoError::GeneralError)
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> FastCryptoError {
+    FastCryptoError::GeneralError(format!("{}: {}", path.display(), e))
+}
+
+fn is_std_stream(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Read a hex encoded master key from a file or, if `path` is `-`, from stdin.
+fn read_master_key(path: &Path) -> FastCryptoResult<Scalar> {
+    let mut content = String::new();
+    if is_std_stream(path) {
+        std::io::stdin().read_to_string(&mut content)
+    } else {
+        fs::File::open(path).and_then(|mut file| file.read_to_string(&mut content))
+    }
+    .map_err(|e| io_error(path, e))?;
+    parse_serializable::<Scalar, DefaultEncoding>(content.trim()).map_err(|e| {
+        FastCryptoError::GeneralError(format!("Invalid master key in {}: {}", path.display(), e))
+    })
+}
+
+impl ByteEncoding {
+    /// Encode bytes for writing to a file or stdout. Text encodings end with a newline.
+    fn encode(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            ByteEncoding::Binary => bytes.to_vec(),
+            _ => format!("{}\n", self.encode_text(bytes)).into_bytes(),
+        }
+    }
+
+    /// Encode bytes for use in a text output. Binary falls back to hex.
+    fn encode_text(&self, bytes: &[u8]) -> String {
+        match self {
+            ByteEncoding::Base64 => Base64::encode(bytes),
+            ByteEncoding::Hex | ByteEncoding::Binary => DefaultEncoding::encode(bytes),
+        }
+    }
+
+    fn decode(&self, bytes: Vec<u8>) -> FastCryptoResult<Vec<u8>> {
+        if *self == ByteEncoding::Binary {
+            return Ok(bytes);
+        }
+        let text = String::from_utf8(bytes).map_err(|_| FastCryptoError::InvalidInput)?;
+        match self {
+            ByteEncoding::Base64 => Base64::decode(text.trim()),
+            _ => DefaultEncoding::decode(text.trim()),
+        }
+    }
+}
+
+impl InputArgs {
+    /// Read and decode the input from the file or stdin given by `--in`.
+    fn read(&self) -> FastCryptoResult<Vec<u8>> {
+        let path = self.input.as_deref().expect("required by clap");
+        let mut bytes = vec![];
+        if is_std_stream(path) {
+            std::io::stdin().read_to_end(&mut bytes)
+        } else {
+            fs::File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+        }
+        .map_err(|e| io_error(path, e))?;
+        self.in_encoding.decode(bytes)
+    }
+
+    /// Read the input and deserialize it from BCS.
+    fn read_serializable<T: for<'a> Deserialize<'a>>(&self) -> FastCryptoResult<T> {
+        bcs::from_bytes(&self.read()?).map_err(|e| FastCryptoError::GeneralError(format!("{}", e)))
+    }
+}
+
+/// Outputs of commands which can be printed as text or JSON.
+trait Output: Display {
+    /// The JSON representation of the output. Field names are part of the stable output format.
+    fn to_json(&self) -> Value;
+
+    fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.to_string(),
+            OutputFormat::Json => {
+                serde_json::to_string_pretty(&self.to_json()).expect("serialization failed")
+            }
+        }
+    }
+}
+
+/// Outputs which can be written to a file or stdout using [OutputArgs].
+trait DataOutput: Output {
+    /// The main output, e.g., an encrypted object or a decrypted message, in the chosen encoding.
+    fn encoded_data(&self) -> Vec<u8>;
+
+    /// Additional outputs that are written to stderr if the main output is written to a file or
+    /// stdout, e.g., the symmetric key when encrypting.
+    fn info(&self) -> Option<String> {
+        None
+    }
+}
+
+impl OutputArgs {
+    /// Write the output. Without `--out`, text encoded outputs are printed as before with labels.
+    /// JSON outputs are written as a single document, including any additional outputs.
+    fn write(&self, output: &dyn DataOutput, format: OutputFormat) -> FastCryptoResult<()> {
+        match &self.output {
+            None if format == OutputFormat::Json || self.out_encoding != ByteEncoding::Binary => {
+                println!("{}", output.render(format))
+            }
+            path => {
+                let data = match format {
+                    OutputFormat::Json => format!("{}\n", output.render(format)).into_bytes(),
+                    OutputFormat::Text => {
+                        if let Some(info) = output.info() {
+                            eprintln!("{}", info);
+                        }
+                        output.encoded_data()
+                    }
+                };
+                match path.as_deref() {
+                    Some(path) if !is_std_stream(path) => {
+                        fs::write(path, data).map_err(|e| io_error(path, e))?
+                    }
+                    _ => std::io::stdout()
+                        .write_all(&data)
+                        .map_err(|e| io_error(Path::new("stdout"), e))?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 //
 // Output formatting
 //
@@ -365,18 +1078,146 @@ impl Display for GenkeyOutput {
     }
 }
 
+impl Output for GenkeyOutput {
+    fn to_json(&self) -> Value {
+        json!({
+            "master_key": serializable_to_string(&self.0 .0),
+            "public_key": serializable_to_string(&self.0 .1),
+        })
+    }
+}
+
 impl Display for GenSeedOutput {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "Seed: {}", DefaultEncoding::encode(self.0))
     }
 }
 
+impl Output for GenSeedOutput {
+    fn to_json(&self) -> Value {
+        json!({ "seed": DefaultEncoding::encode(self.0) })
+    }
+}
+
+impl Display for DeriveRangeOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (derivation_index, public_key, pop) in &self.0 {
+            writeln!(
+                f,
+                "Public key with index {}: {}",
+                derivation_index,
+                serializable_to_string(public_key)
+            )?;
+            if let Some(pop) = pop {
+                writeln!(
+                    f,
+                    "Proof of possession with index {}: {}",
+                    derivation_index,
+                    serializable_to_string(pop)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Output for DeriveRangeOutput {
+    fn to_json(&self) -> Value {
+        json!({
+            "keys": self
+                .0
+                .iter()
+                .map(|(derivation_index, public_key, pop)| {
+                    let mut key = json!({
+                        "index": derivation_index,
+                        "public_key": serializable_to_string(public_key),
+                    });
+                    if let Some(pop) = pop {
+                        key["pop"] = json!(serializable_to_string(pop));
+                    }
+                    key
+                })
+                .collect::<Vec<_>>()
+        })
+    }
+}
+
+impl Display for ExportClientKeyOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", GenkeyOutput(self.key_pair))?;
+        writeln!(f, "Client key config for the importing key server:")?;
+        writeln!(f, "  client_master_key: !Imported")?;
+        write!(f, "    env_var: \"{}\"", self.env_var)?;
+        if let Some(name) = &self.exported_client {
+            write!(f, "\nMarked the key of client \"{}\" as exported", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl Output for ExportClientKeyOutput {
+    fn to_json(&self) -> Value {
+        json!({
+            "master_key": serializable_to_string(&self.key_pair.0),
+            "public_key": serializable_to_string(&self.key_pair.1),
+            "env_var": self.env_var,
+            "exported_client": self.exported_client,
+        })
+    }
+}
+
+impl Display for CreateKeystoreOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Public key: {}",
+            serializable_to_string(&self.public_key)
+        )?;
+        writeln!(f, "Key source config for the key server:")?;
+        writeln!(f, "  key_source: !Keystore")?;
+        writeln!(f, "    path: \"{}\"", self.path.display())?;
+        write!(f, "    passphrase_env_var: \"{}\"", self.passphrase_env_var)
+    }
+}
+
+impl Output for CreateKeystoreOutput {
+    fn to_json(&self) -> Value {
+        json!({
+            "public_key": serializable_to_string(&self.public_key),
+            "path": self.path,
+            "passphrase_env_var": self.passphrase_env_var,
+        })
+    }
+}
+
+impl Display for PopOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Proof of possession: {}",
+            serializable_to_string(&self.0)
+        )
+    }
+}
+
+impl Output for PopOutput {
+    fn to_json(&self) -> Value {
+        json!({ "pop": serializable_to_string(&self.0) })
+    }
+}
+
 impl Display for ExtractOutput {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "User secret key: {}", serializable_to_string(&self.0))
     }
 }
 
+impl Output for ExtractOutput {
+    fn to_json(&self) -> Value {
+        json!({ "user_secret_key": serializable_to_string(&self.0) })
+    }
+}
+
 impl Display for VerifyOutput {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -391,20 +1232,57 @@ impl Display for VerifyOutput {
     }
 }
 
+impl Output for VerifyOutput {
+    fn to_json(&self) -> Value {
+        json!({ "valid": self.0.is_ok() })
+    }
+}
+
 impl Display for EncryptionOutput {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "Encrypted object (bcs): {}\nSymmetric key: {}",
-            DefaultEncoding::encode(bcs::to_bytes(&self.0 .0).unwrap()),
-            DefaultEncoding::encode(self.0 .1)
+            self.1.encode_text(&bcs::to_bytes(&self.0 .0).unwrap()),
+            self.1.encode_text(&self.0 .1)
         )
     }
 }
 
+impl Output for EncryptionOutput {
+    fn to_json(&self) -> Value {
+        json!({
+            "encrypted_object": self.1.encode_text(&bcs::to_bytes(&self.0 .0).unwrap()),
+            "symmetric_key": self.1.encode_text(&self.0 .1),
+        })
+    }
+}
+
+impl DataOutput for EncryptionOutput {
+    fn encoded_data(&self) -> Vec<u8> {
+        self.1.encode(&bcs::to_bytes(&self.0 .0).unwrap())
+    }
+
+    fn info(&self) -> Option<String> {
+        Some(format!("Symmetric key: {}", self.1.encode_text(&self.0 .1)))
+    }
+}
+
 impl Display for DecryptionOutput {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Decrypted message: {}", DefaultEncoding::encode(&self.0))
+        write!(f, "Decrypted message: {}", self.1.encode_text(&self.0))
+    }
+}
+
+impl Output for DecryptionOutput {
+    fn to_json(&self) -> Value {
+        json!({ "message": self.1.encode_text(&self.0) })
+    }
+}
+
+impl DataOutput for DecryptionOutput {
+    fn encoded_data(&self) -> Vec<u8> {
+        self.1.encode(&self.0)
     }
 }
 
@@ -412,7 +1290,7 @@ impl Display for ParseOutput {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Version: {}", self.0.version)?;
         writeln!(f, "Package ID: {}", self.0.package_id)?;
-        writeln!(f, "ID: {}", DefaultEncoding::encode(&self.0.id))?;
+        writeln!(f, "ID: {}", self.1.encode_text(&self.0.id))?;
         writeln!(f, "Services: share index:")?;
         for (id, index) in &self.0.services {
             writeln!(f, "  {}: {}", id, index)?;
@@ -422,24 +1300,24 @@ impl Display for ParseOutput {
         match &self.0.ciphertext {
             Ciphertext::Aes256Gcm { blob, aad } => {
                 writeln!(f, "  Type: AES-256-GCM")?;
-                writeln!(f, "  Blob: {}", DefaultEncoding::encode(blob))?;
+                writeln!(f, "  Blob: {}", self.1.encode_text(blob))?;
                 writeln!(
                     f,
                     "  AAD: {}\n",
                     aad.as_ref()
-                        .map_or("None".to_string(), DefaultEncoding::encode)
+                        .map_or("None".to_string(), |aad| self.1.encode_text(aad))
                 )?;
             }
             Ciphertext::Hmac256Ctr { blob, aad, mac } => {
                 writeln!(f, "  Type: HMAC-256-CTR")?;
-                writeln!(f, "  Blob: {}", DefaultEncoding::encode(blob))?;
+                writeln!(f, "  Blob: {}", self.1.encode_text(blob))?;
                 writeln!(
                     f,
                     "  AAD: {}",
                     aad.as_ref()
-                        .map_or("None".to_string(), DefaultEncoding::encode)
+                        .map_or("None".to_string(), |aad| self.1.encode_text(aad))
                 )?;
-                writeln!(f, "  MAC: {}", DefaultEncoding::encode(mac))?;
+                writeln!(f, "  MAC: {}", self.1.encode_text(mac))?;
             }
             Ciphertext::Plain => {
                 writeln!(f, "  Type: Plain")?;
@@ -455,12 +1333,12 @@ impl Display for ParseOutput {
                 writeln!(f, "  Type: Boneh-Franklin BLS12-381")?;
                 writeln!(f, "  Shares:")?;
                 for share in shares.iter() {
-                    writeln!(f, "    {}", DefaultEncoding::encode(share))?;
+                    writeln!(f, "    {}", self.1.encode_text(share))?;
                 }
                 write!(
                     f,
                     "  Encapsulation: {}",
-                    serializable_to_string(&encapsulation)
+                    self.1.encode_text(&bcs::to_bytes(encapsulation).unwrap())
                 )?;
             }
         };
@@ -468,8 +1346,371 @@ impl Display for ParseOutput {
     }
 }
 
+impl Output for ParseOutput {
+    fn to_json(&self) -> Value {
+        let encode = |bytes: &[u8]| self.1.encode_text(bytes);
+        let ciphertext = match &self.0.ciphertext {
+            Ciphertext::Aes256Gcm { blob, aad } => json!({
+                "type": "Aes256Gcm",
+                "blob": encode(blob),
+                "aad": aad.as_ref().map(|aad| encode(aad)),
+            }),
+            Ciphertext::Hmac256Ctr { blob, aad, mac } => json!({
+                "type": "Hmac256Ctr",
+                "blob": encode(blob),
+                "aad": aad.as_ref().map(|aad| encode(aad)),
+                "mac": encode(mac),
+            }),
+            Ciphertext::Plain => json!({ "type": "Plain" }),
+        };
+        let encrypted_shares = match &self.0.encrypted_shares {
+            IBEEncryptions::BonehFranklinBLS12381 {
+                nonce,
+                encrypted_shares,
+                encrypted_randomness,
+            } => {
+                let shares = encrypted_shares
+                    .iter()
+                    .map(|share| encode(share))
+                    .collect::<Vec<_>>();
+                json!({
+                    "type": "BonehFranklinBLS12381",
+                    "nonce": encode(&bcs::to_bytes(nonce).unwrap()),
+                    "encrypted_shares": shares,
+                    "encrypted_randomness": encode(encrypted_randomness),
+                })
+            }
+        };
+        let services = self
+            .0
+            .services
+            .iter()
+            .map(|(object_id, index)| {
+                json!({
+                    "object_id": object_id.to_string(),
+                    "share_index": index,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "version": self.0.version,
+            "package_id": self.0.package_id.to_string(),
+            "id": encode(&self.0.id),
+            "services": services,
+            "threshold": self.0.threshold,
+            "encrypted_shares": encrypted_shares,
+            "ciphertext": ciphertext,
+        })
+    }
+}
+
+impl DataOutput for ParseOutput {
+    /// The parsed object is always written as text.
+    fn encoded_data(&self) -> Vec<u8> {
+        format!("{}\n", self).into_bytes()
+    }
+}
+
 impl Display for SymmetricDecryptOutput {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Decrypted message: {}", DefaultEncoding::encode(&self.0))
+        writeln!(f, "Decrypted message: {}", self.1.encode_text(&self.0))
+    }
+}
+
+impl Output for SymmetricDecryptOutput {
+    fn to_json(&self) -> Value {
+        json!({ "message": self.1.encode_text(&self.0) })
+    }
+}
+
+impl DataOutput for SymmetricDecryptOutput {
+    fn encoded_data(&self) -> Vec<u8> {
+        self.1.encode(&self.0)
+    }
+}
+
+impl Display for FetchKeysOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (object_id, keys) in &self.0 {
+            writeln!(f, "Key server {}:", object_id)?;
+            for (full_id, key) in keys {
+                writeln!(
+                    f,
+                    "  {}: {}",
+                    DefaultEncoding::encode(full_id),
+                    serializable_to_string(key)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Output for FetchKeysOutput {
+    fn to_json(&self) -> Value {
+        let keys = self
+            .0
+            .iter()
+            .flat_map(|(object_id, keys)| {
+                keys.iter()
+                    .map(move |(full_id, key)| (object_id, full_id, key))
+            })
+            .sorted_by_key(|(object_id, full_id, _)| (**object_id, (*full_id).clone()))
+            .map(|(object_id, full_id, key)| {
+                json!({
+                    "key_server": object_id.to_string(),
+                    "full_id": DefaultEncoding::encode(full_id),
+                    "user_secret_key": serializable_to_string(key),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "keys": keys })
+    }
+}
+
+impl Display for ErrorOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Error: {:?}", self.0)
+    }
+}
+
+impl Output for ErrorOutput {
+    fn to_json(&self) -> Value {
+        let message = match &self.0 {
+            FastCryptoError::GeneralError(message) => message.clone(),
+            e => e.to_string(),
+        };
+        json!({ "error": message })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+    use fastcrypto::groups::GroupElement;
+
+    // The expected JSON outputs below define the output format. Changes to them are breaking
+    // changes for scripts consuming the output.
+
+    /// The encrypted object from the examples in UsingSeal.md.
+    const ENCRYPTED_OBJECT: &str = "0000000000000000000000000000000000000000000000000000000000000000000d53e66d756e6472206672f3f069030000000000000000000000000000000000000000000000000000000000000001010000000000000000000000000000000000000000000000000000000000000002020000000000000000000000000000000000000000000000000000000000000003030200841b3a59241e099e8b8d9cec1d531b1e8fe4b4170433e30d9aaa9fc764201f69e589a0b2a0e65bfb279d4b25ee1ce8141812bfb785abdb05134c3958f53c2e81e7bc06e5c1f1ebd7e489b5cf652216b13e6b7c2b13da70a4a7c05c3544a1ddf703b627cb3268d74c74ead83fb827c60fa23c1d192fb8a7db50ea8721bf7c95bd1748b5ed7da6873f4a5b539cb16085e5cd174206db776c04902c7d8c02d6fa47aada89c2fa0692973a83a7a900f2b0dd7f7475e55095d0df7b0483ae1192761d368985e51d72597df02764c654536130c905a8de4a6c9169643e9dd01efab17a9200723b7d7b2ede8924cfb3687a0c41599b87bebc9d913d8eb81a2027ba8286a7b2cd9f5303b6b551fa545189e2f13cb65642b66595ca4256f42cdda2ac78af39abde06184da29131437e1417ebb35c7136d2c74b8ab9fa4147077bbcdbfafc2b05458792eefe0424fedef10247b8b3c787e7772800";
+
+    fn encrypted_object() -> EncryptedObject {
+        parse_serializable::<EncryptedObject, DefaultEncoding>(ENCRYPTED_OBJECT).unwrap()
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let output = ParseOutput(encrypted_object(), ByteEncoding::Hex);
+        assert_eq!(
+            output.to_json(),
+            json!({
+                "version": 0,
+                "package_id": "0x0000000000000000000000000000000000000000000000000000000000000000",
+                "id": "0x53e66d756e6472206672f3f069",
+                "services": [
+                    { "object_id": "0x0000000000000000000000000000000000000000000000000000000000000001", "share_index": 1 },
+                    { "object_id": "0x0000000000000000000000000000000000000000000000000000000000000002", "share_index": 2 },
+                    { "object_id": "0x0000000000000000000000000000000000000000000000000000000000000003", "share_index": 3 },
+                ],
+                "threshold": 2,
+                "encrypted_shares": {
+                    "type": "BonehFranklinBLS12381",
+                    "nonce": "0x841b3a59241e099e8b8d9cec1d531b1e8fe4b4170433e30d9aaa9fc764201f69e589a0b2a0e65bfb279d4b25ee1ce8141812bfb785abdb05134c3958f53c2e81e7bc06e5c1f1ebd7e489b5cf652216b13e6b7c2b13da70a4a7c05c3544a1ddf7",
+                    "encrypted_shares": [
+                        "0xb627cb3268d74c74ead83fb827c60fa23c1d192fb8a7db50ea8721bf7c95bd17",
+                        "0x48b5ed7da6873f4a5b539cb16085e5cd174206db776c04902c7d8c02d6fa47aa",
+                        "0xda89c2fa0692973a83a7a900f2b0dd7f7475e55095d0df7b0483ae1192761d36",
+                    ],
+                    "encrypted_randomness": "0x8985e51d72597df02764c654536130c905a8de4a6c9169643e9dd01efab17a92",
+                },
+                "ciphertext": {
+                    "type": "Aes256Gcm",
+                    "blob": "0x3b7d7b2ede8924cfb3687a0c41599b87bebc9d913d8eb81a2027ba8286a7b2cd9f5303b6b551fa545189e2f13cb65642b66595ca4256f42cdda2ac78af39abde06184da29131437e1417ebb35c7136d2c74b8ab9fa4147077bbcdbfafc2b05458792eefe0424fedef10247b8b3c787e77728",
+                    "aad": null,
+                },
+            })
+        );
+
+        // The JSON output is pretty printed
+        assert_eq!(
+            serde_json::from_str::<Value>(&output.render(OutputFormat::Json)).unwrap(),
+            output.to_json()
+        );
+    }
+
+    #[test]
+    fn test_encryption_json() {
+        assert_eq!(
+            EncryptionOutput((encrypted_object(), [0; KEY_LENGTH]), ByteEncoding::Base64).to_json(),
+            json!({
+                "encrypted_object": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAADVPmbXVuZHIgZnLz8GkDAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAEBAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAICAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAMDAgCEGzpZJB4JnouNnOwdUxsej+S0FwQz4w2aqp/HZCAfaeWJoLKg5lv7J51LJe4c6BQYEr+3havbBRNMOVj1PC6B57wG5cHx69fkibXPZSIWsT5rfCsT2nCkp8BcNUSh3fcDtifLMmjXTHTq2D+4J8YPojwdGS+4p9tQ6ochv3yVvRdIte19poc/SltTnLFgheXNF0IG23dsBJAsfYwC1vpHqtqJwvoGkpc6g6epAPKw3X90deVQldDfewSDrhGSdh02iYXlHXJZffAnZMZUU2EwyQWo3kpskWlkPp3QHvqxepIAcjt9ey7eiSTPs2h6DEFZm4e+vJ2RPY64GiAnuoKGp7LNn1MDtrVR+lRRieLxPLZWQrZllcpCVvQs3aKseK85q94GGE2ikTFDfhQX67NccTbSx0uKufpBRwd7vNv6/CsFRYeS7v4EJP7e8QJHuLPHh+d3KAA=",
+                "symmetric_key": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+            })
+        );
+        assert_eq!(
+            DecryptionOutput(vec![1, 2, 3], ByteEncoding::Hex).to_json(),
+            json!({ "message": "0x010203" })
+        );
+        assert_eq!(
+            SymmetricDecryptOutput(vec![1, 2, 3], ByteEncoding::Base64).to_json(),
+            json!({ "message": "AQID" })
+        );
+    }
+
+    #[test]
+    fn test_keys_json() {
+        assert_eq!(
+            GenkeyOutput((Scalar::from(1u128), G2Element::generator())).to_json(),
+            json!({
+                "master_key": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "public_key": "0x93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8",
+            })
+        );
+        assert_eq!(
+            GenSeedOutput([1; SEED_LENGTH]).to_json(),
+            json!({ "seed": "0x0101010101010101010101010101010101010101010101010101010101010101" })
+        );
+        assert_eq!(
+            ExtractOutput(G1Element::generator()).to_json(),
+            json!({ "user_secret_key": "0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb" })
+        );
+        assert_eq!(
+            DeriveRangeOutput(vec![
+                (0, G2Element::generator(), None),
+                (1, G2Element::generator(), Some(G1Element::generator())),
+            ])
+            .to_json(),
+            json!({
+                "keys": [
+                    {
+                        "index": 0,
+                        "public_key": "0x93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8",
+                    },
+                    {
+                        "index": 1,
+                        "public_key": "0x93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8",
+                        "pop": "0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb",
+                    },
+                ]
+            })
+        );
+        assert_eq!(
+            ExportClientKeyOutput {
+                key_pair: (Scalar::from(1u128), G2Element::generator()),
+                env_var: "BOB_BLS_KEY".to_string(),
+                exported_client: Some("bob".to_string()),
+            }
+            .to_json(),
+            json!({
+                "master_key": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "public_key": "0x93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8",
+                "env_var": "BOB_BLS_KEY",
+                "exported_client": "bob",
+            })
+        );
+        assert_eq!(
+            CreateKeystoreOutput {
+                public_key: G2Element::generator(),
+                path: PathBuf::from("/etc/seal/master-key.json"),
+                passphrase_env_var: "KEYSTORE_PASSPHRASE".to_string(),
+            }
+            .to_json(),
+            json!({
+                "public_key": "0x93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8",
+                "path": "/etc/seal/master-key.json",
+                "passphrase_env_var": "KEYSTORE_PASSPHRASE",
+            })
+        );
+        assert_eq!(
+            PopOutput(G1Element::generator()).to_json(),
+            json!({ "pop": "0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb" })
+        );
+        assert_eq!(VerifyOutput(Ok(())).to_json(), json!({ "valid": true }));
+        assert_eq!(
+            VerifyOutput(Err(FastCryptoError::InvalidInput)).to_json(),
+            json!({ "valid": false })
+        );
+    }
+
+    #[test]
+    fn test_fetch_keys_json() {
+        let key_server_1 = ObjectID::from_hex_literal("0x1").unwrap();
+        let key_server_2 = ObjectID::from_hex_literal("0x2").unwrap();
+        let output = FetchKeysOutput(HashMap::from([
+            (
+                key_server_2,
+                HashMap::from([(vec![2], G1Element::generator())]),
+            ),
+            (
+                key_server_1,
+                HashMap::from([
+                    (vec![2], G1Element::generator()),
+                    (vec![1], G1Element::generator()),
+                ]),
+            ),
+        ]));
+        assert_eq!(
+            output.to_json(),
+            json!({
+                "keys": [
+                    { "key_server": "0x0000000000000000000000000000000000000000000000000000000000000001", "full_id": "0x01", "user_secret_key": "0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb" },
+                    { "key_server": "0x0000000000000000000000000000000000000000000000000000000000000001", "full_id": "0x02", "user_secret_key": "0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb" },
+                    { "key_server": "0x0000000000000000000000000000000000000000000000000000000000000002", "full_id": "0x02", "user_secret_key": "0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb" },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_error_json() {
+        let output = ErrorOutput(FastCryptoError::GeneralError("Range too large".to_string()));
+        assert_eq!(output.to_json(), json!({ "error": "Range too large" }));
+
+        let output = ErrorOutput(FastCryptoError::InvalidInput);
+        assert_eq!(
+            output.to_json(),
+            json!({ "error": FastCryptoError::InvalidInput.to_string() })
+        );
+    }
+
+    #[test]
+    fn test_parse_decrypt_args() {
+        let object_id = "0x0000000000000000000000000000000000000000000000000000000000000001";
+
+        // The encrypted object is required unless it is read with --in
+        let error =
+            Arguments::try_parse_from(["seal-cli", "decrypt", "--", object_id]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+
+        let args =
+            Arguments::try_parse_from(["seal-cli", "decrypt", "0x01", "0x02", "--", object_id])
+                .unwrap();
+        let Command::Decrypt {
+            encrypted_object_and_secret_keys,
+            input,
+            ..
+        } = args.command
+        else {
+            panic!("expected decrypt command");
+        };
+        assert_eq!(encrypted_object_and_secret_keys, vec!["0x01", "0x02"]);
+        assert!(input.input.is_none());
+
+        let args = Arguments::try_parse_from([
+            "seal-cli", "decrypt", "--in", "-", "0x02", "--", object_id,
+        ])
+        .unwrap();
+        let Command::Decrypt {
+            encrypted_object_and_secret_keys,
+            input,
+            ..
+        } = args.command
+        else {
+            panic!("expected decrypt command");
+        };
+        assert_eq!(encrypted_object_and_secret_keys, vec!["0x02"]);
+        assert_eq!(input.input, Some(PathBuf::from("-")));
     }
 }
@@ -0,0 +1,167 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fetching keys from key servers for the `fetch-keys` and `decrypt --remote` commands.
+
+use clap::Args;
+use crypto::ibe::UserSecretKey;
+use crypto::ObjectID;
+use fastcrypto::encoding::{Base64, Encoding};
+use fastcrypto::error::{FastCryptoError, FastCryptoResult};
+use seal_client::onchain::{key_server_configs, seal_approve_ptb};
+use seal_client::{KeyId, SealClient, SessionKey};
+use shared_crypto::intent::{Intent, PersonalMessage};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use sui_keys::keystore::{AccountKeystore, FileBasedKeystore};
+use sui_sdk::{SuiClient, SuiClientBuilder};
+use sui_types::base_types::SuiAddress;
+use sui_types::signature::GenericSignature;
+use sui_types::transaction::{Command, ProgrammableTransaction};
+
+const DEFAULT_RPC_URL: &str = "https://fullnode.testnet.sui.io:443";
+
+/// Arguments for fetching keys from key servers.
+#[derive(Args, Debug)]
+pub struct RemoteArgs {
+    /// The URL of the Sui full node used to look up key servers and objects.
+    #[arg(long, default_value = DEFAULT_RPC_URL)]
+    rpc_url: String,
+    /// The Sui keystore holding the key of the user. Defaults to `~/.sui/sui_config/sui.keystore`.
+    #[arg(long)]
+    keystore: Option<PathBuf>,
+    /// The address of the user that signs the session certificate. Must be in the keystore.
+    #[arg(long)]
+    address: Option<SuiAddress>,
+    /// The time-to-live of the session key in minutes.
+    #[arg(long, default_value_t = 10)]
+    ttl_min: u16,
+    /// A Base64 encoded BCS serialized PTB calling `seal_approve*` functions. If not given, a PTB with
+    /// a single call to `--module::--function` is built.
+    #[arg(long, conflicts_with_all = ["module", "objects"])]
+    ptb: Option<String>,
+    /// The module of the `seal_approve*` function.
+    #[arg(long)]
+    module: Option<String>,
+    /// The name of the `seal_approve*` function.
+    #[arg(long, default_value = "seal_approve")]
+    function: String,
+    /// Objects passed as arguments to the `seal_approve*` function after the id.
+    #[arg(long = "object", value_name = "OBJECT_ID")]
+    objects: Vec<ObjectID>,
+}
+
+fn sui_error(e: impl ToString) -> FastCryptoError {
+    FastCryptoError::GeneralError(e.to_string())
+}
+
+impl RemoteArgs {
+    /// Create a client for the given key servers with their configurations read from chain.
+    pub async fn seal_client(
+        &self,
+        sui_client: &SuiClient,
+        key_server_ids: &[ObjectID],
+    ) -> FastCryptoResult<SealClient> {
+        let key_servers = key_server_configs(sui_client, key_server_ids)
+            .await
+            .map_err(sui_error)?;
+        let client = SealClient::new(key_servers);
+        client.verify_key_servers().await.map_err(sui_error)?;
+        Ok(client)
+    }
+
+    pub async fn sui_client(&self) -> FastCryptoResult<SuiClient> {
+        SuiClientBuilder::default()
+            .build(&self.rpc_url)
+            .await
+            .map_err(sui_error)
+    }
+
+    /// Returns the PTB given by `--ptb` or builds one calling the `seal_approve*` function for the
+    /// given package and id.
+    pub async fn ptb(
+        &self,
+        sui_client: &SuiClient,
+        package_id: Option<ObjectID>,
+        id: Option<Vec<u8>>,
+    ) -> FastCryptoResult<ProgrammableTransaction> {
+        if let Some(ptb) = &self.ptb {
+            return bcs::from_bytes(&Base64::decode(ptb)?).map_err(sui_error);
+        }
+        let (Some(package_id), Some(id), Some(module)) = (package_id, id, &self.module) else {
+            return Err(FastCryptoError::GeneralError(
+                "Either --ptb or --package-id, --id and --module must be given".to_string(),
+            ));
+        };
+        seal_approve_ptb(
+            sui_client,
+            package_id,
+            module,
+            &self.function,
+            id,
+            &self.objects,
+        )
+        .await
+        .map_err(sui_error)
+    }
+
+    /// Fetch the keys approved by the PTB from the given key servers, see the `fetch-keys` command.
+    /// The package defaults to the package called by the PTB.
+    pub async fn fetch_keys(
+        &self,
+        package_id: Option<ObjectID>,
+        id: Option<Vec<u8>>,
+        key_server_ids: &[ObjectID],
+        threshold: u8,
+    ) -> FastCryptoResult<HashMap<ObjectID, HashMap<KeyId, UserSecretKey>>> {
+        let sui_client = self.sui_client().await?;
+        let ptb = self.ptb(&sui_client, package_id, id).await?;
+        let package_id = match package_id {
+            Some(package_id) => package_id,
+            None => ptb_package_id(&ptb)?,
+        };
+        let session_key = self.session_key(package_id)?;
+        let client = self.seal_client(&sui_client, key_server_ids).await?;
+        client
+            .fetch_keys(key_server_ids, threshold, &ptb, &session_key)
+            .await
+            .map_err(sui_error)
+    }
+
+    /// Create a session key for the given package and sign it with the user's key from the keystore.
+    pub fn session_key(&self, package_id: ObjectID) -> FastCryptoResult<SessionKey> {
+        let address = self.address.ok_or(FastCryptoError::GeneralError(
+            "--address must be given".to_string(),
+        ))?;
+        let keystore_path = match &self.keystore {
+            Some(path) => path.clone(),
+            None => std::env::var("HOME")
+                .map(|home| PathBuf::from(home).join(".sui/sui_config/sui.keystore"))
+                .map_err(sui_error)?,
+        };
+        let keystore = FileBasedKeystore::new(&keystore_path).map_err(sui_error)?;
+
+        let mut session_key = SessionKey::new(address, package_id, None, self.ttl_min);
+        let signature = keystore
+            .sign_secure(
+                &address,
+                &PersonalMessage {
+                    message: session_key.personal_message().into_bytes(),
+                },
+                Intent::personal_message(),
+            )
+            .map_err(sui_error)?;
+        session_key.set_personal_message_signature(GenericSignature::Signature(signature));
+        Ok(session_key)
+    }
+}
+
+/// The package called by the first command of a PTB.
+pub fn ptb_package_id(ptb: &ProgrammableTransaction) -> FastCryptoResult<ObjectID> {
+    match ptb.commands.first() {
+        Some(Command::MoveCall(call)) => Ok(call.package),
+        _ => Err(FastCryptoError::GeneralError(
+            "The PTB must start with a Move call".to_string(),
+        )),
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structural validation of encrypted objects for the `validate` command.
+
+use crate::{DefaultEncoding, Output};
+use crypto::{ibe, Ciphertext, ObjectID, KEY_SIZE};
+use fastcrypto::encoding::Encoding;
+use fastcrypto::error::FastCryptoError;
+use fastcrypto::groups::bls12381::{G2Element, G2_ELEMENT_BYTE_LENGTH};
+use fastcrypto::groups::GroupElement;
+use fastcrypto::serde_helpers::ToFromByteArray;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use serde_with::serde_as;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The only supported version of encrypted objects.
+const SUPPORTED_VERSION: u8 = 0;
+
+/// Same as [crypto::EncryptedObject] but without checking that the nonce is a valid group element
+/// when deserializing, such that an invalid nonce can be reported as a finding.
+#[derive(Serialize, Deserialize)]
+struct UncheckedEncryptedObject {
+    version: u8,
+    package_id: ObjectID,
+    id: Vec<u8>,
+    services: Vec<(ObjectID, u8)>,
+    threshold: u8,
+    encrypted_shares: UncheckedIBEEncryptions,
+    ciphertext: Ciphertext,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+enum UncheckedIBEEncryptions {
+    BonehFranklinBLS12381 {
+        #[serde_as(as = "[_; G2_ELEMENT_BYTE_LENGTH]")]
+        nonce: [u8; G2_ELEMENT_BYTE_LENGTH],
+        encrypted_shares: Vec<ibe::Ciphertext>,
+        encrypted_randomness: [u8; KEY_SIZE],
+    },
+}
+
+/// A key server known to the caller, given as `<OBJECT_ID>=<PUBLIC_KEY>`.
+#[derive(Debug, Clone)]
+pub struct KnownKeyServer {
+    object_id: ObjectID,
+    public_key: ibe::PublicKey,
+}
+
+impl FromStr for KnownKeyServer {
+    type Err = FastCryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (object_id, public_key) = s.split_once('=').ok_or(FastCryptoError::GeneralError(
+            "Expected <OBJECT_ID>=<PUBLIC_KEY>".to_string(),
+        ))?;
+        Ok(KnownKeyServer {
+            object_id: ObjectID::from_hex_literal(object_id)
+                .map_err(|e| FastCryptoError::GeneralError(e.to_string()))?,
+            public_key: crate::parse_serializable::<G2Element, DefaultEncoding>(public_key)
+                .map_err(FastCryptoError::GeneralError)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The object cannot be decrypted.
+    Error,
+    /// The object can be decrypted, but decryption may fail or be less secure than expected.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    severity: Severity,
+    message: String,
+}
+
+pub struct ValidateOutput(Vec<Finding>);
+
+impl ValidateOutput {
+    fn is_valid(&self) -> bool {
+        self.0.iter().all(|f| f.severity != Severity::Error)
+    }
+}
+
+/// Check an encrypted object for structural consistency.
+///
+/// @param bytes The BCS serialized encrypted object.
+/// @param key_servers Known key servers. If empty, the key servers of the object are not checked.
+/// @return All problems found.
+pub fn validate(bytes: &[u8], key_servers: &[KnownKeyServer]) -> ValidateOutput {
+    let mut findings = vec![];
+    let mut error = |message: String| {
+        findings.push(Finding {
+            severity: Severity::Error,
+            message,
+        })
+    };
+
+    let object: UncheckedEncryptedObject = match bcs::from_bytes(bytes) {
+        Ok(object) => object,
+        Err(e) => {
+            error(format!("Not a BCS serialized encrypted object: {}", e));
+            return ValidateOutput(findings);
+        }
+    };
+    let UncheckedIBEEncryptions::BonehFranklinBLS12381 {
+        nonce,
+        encrypted_shares,
+        ..
+    } = &object.encrypted_shares;
+
+    if object.version != SUPPORTED_VERSION {
+        error(format!("Unsupported version {}", object.version));
+    }
+    if object.services.is_empty() {
+        error("No key servers".to_string());
+    }
+    if encrypted_shares.len() != object.services.len() {
+        error(format!(
+            "The number of encrypted shares ({}) does not match the number of key servers ({})",
+            encrypted_shares.len(),
+            object.services.len()
+        ));
+    }
+    if object.threshold == 0 || object.threshold as usize > object.services.len() {
+        error(format!(
+            "The threshold {} must be between 1 and the number of key servers ({})",
+            object.threshold,
+            object.services.len()
+        ));
+    }
+    if object.services.iter().any(|(_, index)| *index == 0) {
+        error("Share index 0 is not allowed".to_string());
+    }
+    for (index, count) in object.services.iter().counts_by(|(_, index)| *index) {
+        if count > 1 {
+            error(format!("Share index {} is used {} times", index, count));
+        }
+    }
+    match G2Element::from_byte_array(nonce) {
+        Err(_) => error("The nonce is not a valid BLS12-381 G2 element".to_string()),
+        Ok(nonce) if nonce == G2Element::zero() => {
+            error("The nonce is the identity element".to_string())
+        }
+        Ok(_) => (),
+    }
+
+    if !key_servers.is_empty() {
+        check_key_servers(&object, key_servers, &mut findings);
+    }
+    ValidateOutput(findings)
+}
+
+/// Check that enough shares are held by known key servers and that they use distinct public keys.
+fn check_key_servers(
+    object: &UncheckedEncryptedObject,
+    key_servers: &[KnownKeyServer],
+    findings: &mut Vec<Finding>,
+) {
+    let public_keys = key_servers
+        .iter()
+        .map(|ks| (ks.object_id, ks.public_key))
+        .collect::<HashMap<_, _>>();
+    let mut known_shares = 0;
+    for (object_id, index) in &object.services {
+        if public_keys.contains_key(object_id) {
+            known_shares += 1;
+        } else {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: format!(
+                    "Share {} is held by unknown key server {}",
+                    index, object_id
+                ),
+            });
+        }
+    }
+    if known_shares < object.threshold as usize {
+        findings.push(Finding {
+            severity: Severity::Error,
+            message: format!(
+                "Only {} shares are held by known key servers but the threshold is {}",
+                known_shares, object.threshold
+            ),
+        });
+    }
+
+    // Distinct key server objects sharing a public key hold their shares under the same master key.
+    let object_ids = object.services.iter().map(|(id, _)| *id).unique();
+    for (public_key, ids) in &object_ids
+        .filter_map(|id| public_keys.get(&id).map(|pk| (pk.to_byte_array(), id)))
+        .into_group_map()
+    {
+        if ids.len() > 1 {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                message: format!(
+                    "Key servers {} use the same public key {}",
+                    ids.iter().join(", "),
+                    DefaultEncoding::encode(public_key)
+                ),
+            });
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl Display for ValidateOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for finding in &self.0 {
+            writeln!(f, "{}: {}", finding.severity, finding.message)?;
+        }
+        write!(
+            f,
+            "{}",
+            if self.is_valid() {
+                "The encrypted object is valid"
+            } else {
+                "The encrypted object is invalid"
+            }
+        )
+    }
+}
+
+impl Output for ValidateOutput {
+    fn to_json(&self) -> Value {
+        json!({
+            "valid": self.is_valid(),
+            "findings": self.0.iter().map(|finding| json!({
+                "severity": finding.severity.to_string(),
+                "message": finding.message,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::{seal_encrypt, EncryptionInput, IBEPublicKeys};
+    use rand::thread_rng;
+
+    fn key_servers(n: usize) -> Vec<KnownKeyServer> {
+        (0..n)
+            .map(|_| KnownKeyServer {
+                object_id: ObjectID::random(),
+                public_key: ibe::generate_key_pair(&mut thread_rng()).1,
+            })
+            .collect()
+    }
+
+    fn encrypt(key_servers: &[KnownKeyServer], threshold: u8) -> UncheckedEncryptedObject {
+        let (encrypted_object, _) = seal_encrypt(
+            ObjectID::random(),
+            vec![1, 2, 3],
+            key_servers.iter().map(|ks| ks.object_id).collect(),
+            &IBEPublicKeys::BonehFranklinBLS12381(
+                key_servers.iter().map(|ks| ks.public_key).collect(),
+            ),
+            threshold,
+            EncryptionInput::Aes256Gcm {
+                data: b"Hello".to_vec(),
+                aad: None,
+            },
+        )
+        .unwrap();
+        bcs::from_bytes(&bcs::to_bytes(&encrypted_object).unwrap()).unwrap()
+    }
+
+    fn errors(object: &UncheckedEncryptedObject, key_servers: &[KnownKeyServer]) -> Vec<String> {
+        validate(&bcs::to_bytes(object).unwrap(), key_servers)
+            .0
+            .into_iter()
+            .filter(|f| f.severity == Severity::Error)
+            .map(|f| f.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        let key_servers = key_servers(3);
+        let object = encrypt(&key_servers, 2);
+        let output = validate(&bcs::to_bytes(&object).unwrap(), &key_servers);
+        assert!(output.0.is_empty());
+        assert_eq!(output.to_json(), json!({ "valid": true, "findings": [] }));
+
+        // The unchecked object serializes like the original
+        let encrypted_object: crypto::EncryptedObject =
+            bcs::from_bytes(&bcs::to_bytes(&object).unwrap()).unwrap();
+        assert_eq!(encrypted_object.services, object.services);
+    }
+
+    #[test]
+    fn test_structural_errors() {
+        let key_servers = key_servers(3);
+
+        let mut object = encrypt(&key_servers, 2);
+        object.services.pop();
+        assert_eq!(
+            errors(&object, &[]),
+            vec!["The number of encrypted shares (3) does not match the number of key servers (2)"]
+        );
+
+        let mut object = encrypt(&key_servers, 2);
+        object.threshold = 4;
+        assert_eq!(
+            errors(&object, &[]),
+            vec!["The threshold 4 must be between 1 and the number of key servers (3)"]
+        );
+
+        let mut object = encrypt(&key_servers, 2);
+        object.services[2].1 = 1;
+        assert_eq!(errors(&object, &[]), vec!["Share index 1 is used 2 times"]);
+
+        let mut object = encrypt(&key_servers, 2);
+        let UncheckedIBEEncryptions::BonehFranklinBLS12381 { nonce, .. } =
+            &mut object.encrypted_shares;
+        nonce[1] ^= 1;
+        assert_eq!(
+            errors(&object, &[]),
+            vec!["The nonce is not a valid BLS12-381 G2 element"]
+        );
+
+        let output = validate(&[1, 2, 3], &[]);
+        assert!(!output.is_valid());
+        assert_eq!(output.0.len(), 1);
+    }
+
+    #[test]
+    fn test_key_servers() {
+        let mut key_servers = key_servers(3);
+        let object = encrypt(&key_servers, 2);
+
+        // One unknown key server is fine, two are not
+        let output = validate(&bcs::to_bytes(&object).unwrap(), &key_servers[1..]);
+        assert!(output.is_valid());
+        assert_eq!(output.0[0].severity, Severity::Warning);
+        assert_eq!(
+            errors(&object, &key_servers[2..]),
+            vec!["Only 1 shares are held by known key servers but the threshold is 2"]
+        );
+
+        // Key servers with the same public key
+        key_servers[1].public_key = key_servers[0].public_key;
+        let output = validate(&bcs::to_bytes(&object).unwrap(), &key_servers);
+        assert!(output.is_valid());
+        assert_eq!(output.0.len(), 1);
+        assert!(output.0[0].message.contains("use the same public key"));
+
+        // Parsing of key servers
+        let key_server = KnownKeyServer::from_str(&format!(
+            "{}={}",
+            key_servers[2].object_id,
+            DefaultEncoding::encode(key_servers[2].public_key.to_byte_array())
+        ))
+        .unwrap();
+        assert_eq!(key_server.object_id, key_servers[2].object_id);
+        assert_eq!(key_server.public_key, key_servers[2].public_key);
+        assert!(KnownKeyServer::from_str(&key_servers[2].object_id.to_string()).is_err());
+    }
+}
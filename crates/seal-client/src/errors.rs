@@ -0,0 +1,129 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use fastcrypto::error::FastCryptoError;
+use std::fmt::{Display, Formatter};
+use sui_types::base_types::ObjectID;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SealClientError {
+    /// The key server is not among the key servers configured for the client.
+    UnknownKeyServer(ObjectID),
+    /// A request to a key server failed, e.g., because the server could not be reached.
+    Http {
+        object_id: ObjectID,
+        message: String,
+    },
+    /// A key server responded with an error.
+    KeyServer {
+        object_id: ObjectID,
+        status: u16,
+        error: String,
+        message: String,
+    },
+    /// A key server returned a response that could not be parsed.
+    InvalidResponse {
+        object_id: ObjectID,
+        message: String,
+    },
+    /// The proof-of-possession returned by a key server does not match its public key.
+    InvalidProofOfPossession(ObjectID),
+    /// The keys returned by a key server are not valid for its public key.
+    InvalidKeys(ObjectID),
+    /// Fewer key servers than the threshold returned valid keys.
+    NotEnoughKeys {
+        threshold: u8,
+        errors: Vec<SealClientError>,
+    },
+    /// The certificate of the session key has not been signed by the user.
+    UnsignedSessionKey,
+    /// The session key has expired or its creation time is in the future.
+    ExpiredSessionKey,
+    /// The session key is invalid, e.g., its time-to-live exceeds the allowed maximum or it could
+    /// not be imported.
+    InvalidSessionKey(String),
+    /// A request to the Sui full node failed.
+    Sui(String),
+    /// The given object is not a valid key server object.
+    InvalidKeyServerObject(ObjectID),
+    /// A PTB could not be built.
+    InvalidPtb(String),
+    /// The decryption of the encrypted object failed.
+    Decryption(FastCryptoError),
+}
+
+pub type SealClientResult<T> = Result<T, SealClientError>;
+
+impl SealClientError {
+    /// Returns true if the error is transient and the request should be retried.
+    pub(crate) fn is_retriable(&self) -> bool {
+        match self {
+            SealClientError::Http { .. } => true,
+            // The key server returns SERVICE_UNAVAILABLE for internal errors, e.g., if its full node is stale.
+            SealClientError::KeyServer { status, .. } => *status == 503,
+            _ => false,
+        }
+    }
+}
+
+impl Display for SealClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SealClientError::UnknownKeyServer(object_id) => {
+                write!(f, "Unknown key server {}", object_id)
+            }
+            SealClientError::Http { object_id, message } => {
+                write!(f, "Request to key server {} failed: {}", object_id, message)
+            }
+            SealClientError::KeyServer {
+                object_id,
+                status,
+                error,
+                message,
+            } => write!(
+                f,
+                "Key server {} returned {} ({}): {}",
+                object_id, error, status, message
+            ),
+            SealClientError::InvalidResponse { object_id, message } => write!(
+                f,
+                "Invalid response from key server {}: {}",
+                object_id, message
+            ),
+            SealClientError::InvalidProofOfPossession(object_id) => write!(
+                f,
+                "Invalid proof-of-possession from key server {}",
+                object_id
+            ),
+            SealClientError::InvalidKeys(object_id) => {
+                write!(f, "Invalid keys returned by key server {}", object_id)
+            }
+            SealClientError::NotEnoughKeys { threshold, errors } => {
+                write!(
+                    f,
+                    "Could not get keys from {} key servers. Errors: ",
+                    threshold
+                )?;
+                for error in errors {
+                    write!(f, "[{}] ", error)?;
+                }
+                Ok(())
+            }
+            SealClientError::UnsignedSessionKey => {
+                write!(f, "The session key has not been signed by the user")
+            }
+            SealClientError::ExpiredSessionKey => write!(f, "The session key has expired"),
+            SealClientError::InvalidSessionKey(message) => {
+                write!(f, "Invalid session key: {}", message)
+            }
+            SealClientError::Sui(message) => write!(f, "Sui RPC request failed: {}", message),
+            SealClientError::InvalidKeyServerObject(object_id) => {
+                write!(f, "Invalid key server object {}", object_id)
+            }
+            SealClientError::InvalidPtb(message) => write!(f, "Invalid PTB: {}", message),
+            SealClientError::Decryption(e) => write!(f, "Decryption failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SealClientError {}
@@ -0,0 +1,377 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A client for Seal key servers.
+//!
+//! The client fetches user secret keys from a set of key servers over HTTP using a [SessionKey],
+//! verifies them against the public keys of the key servers and uses them to decrypt
+//! [EncryptedObject]s.
+
+use crate::errors::{SealClientError, SealClientResult};
+use crypto::client::{decrypt_and_verify_response, user_secret_keys_for_id, FetchKeyResponse};
+use crypto::elgamal;
+use crypto::ibe::{verify_proof_of_possession, ProofOfPossession, UserSecretKey};
+use crypto::{create_full_id, ibe, seal_decrypt, EncryptedObject, IBEPublicKeys, ObjectID};
+use fastcrypto::ed25519::Ed25519Signature;
+use fastcrypto::encoding::{Base64, Encoding};
+use fastcrypto::groups::bls12381::G2Element;
+use futures::future::join_all;
+use itertools::Itertools;
+use rand::thread_rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use sui_types::transaction::ProgrammableTransaction;
+use tracing::{debug, warn};
+
+pub use crypto::client::KeyId;
+pub use session::{Certificate, ExportedSessionKey, SessionKey};
+
+pub mod errors;
+pub mod onchain;
+pub mod session;
+pub mod signed_message;
+#[cfg(test)]
+mod tests;
+
+/// ElGamal related types.
+pub type ElGamalPublicKey = elgamal::PublicKey<ibe::UserSecretKey>;
+pub type ElgamalVerificationKey = elgamal::VerificationKey<ibe::PublicKey>;
+
+/// The version of the key server API implemented by this client, sent in the `Client-Sdk-Version`
+/// header. Key servers reject requests from SDK versions below their configured minimum.
+pub const SDK_VERSION: &str = "0.4.11";
+
+/// The SDK type sent in the `Client-Sdk-Type` header.
+const SDK_TYPE: &str = "rust";
+
+/// A key server the client can fetch keys from.
+#[derive(Debug, Clone)]
+pub struct KeyServerConfig {
+    /// The object id of the key server object on Sui.
+    pub object_id: ObjectID,
+    /// The URL of the key server.
+    pub url: String,
+    /// The public key of the key server.
+    pub public_key: ibe::PublicKey,
+    /// The version of the master key matching `public_key`. Only needed if the key server is
+    /// rotating its master key and `public_key` is not the one registered on-chain. Otherwise, the
+    /// key server uses the key registered on-chain.
+    pub key_version: Option<u32>,
+}
+
+/// Configuration for the retry logic.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// The maximum number of attempts per request.
+    pub max_retries: u32,
+
+    /// The minimum delay between retries.
+    pub min_delay: Duration,
+
+    /// The maximum delay between retries.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            min_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct FetchKeyRequest {
+    pub ptb: String,
+    pub enc_key: ElGamalPublicKey,
+    pub enc_verification_key: ElgamalVerificationKey,
+    pub request_signature: Ed25519Signature,
+    pub certificate: Certificate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_version: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct GetServiceResponse {
+    pub service_id: ObjectID,
+    pub pop: ProofOfPossession,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: serde_json::Value,
+    message: String,
+}
+
+/// Client for fetching keys from a set of key servers.
+#[derive(Clone)]
+pub struct SealClient {
+    key_servers: HashMap<ObjectID, KeyServerConfig>,
+    http_client: reqwest::Client,
+    retry_config: RetryConfig,
+}
+
+impl SealClient {
+    pub fn new(key_servers: impl IntoIterator<Item = KeyServerConfig>) -> Self {
+        Self::new_with_retry_config(key_servers, RetryConfig::default())
+    }
+
+    pub fn new_with_retry_config(
+        key_servers: impl IntoIterator<Item = KeyServerConfig>,
+        retry_config: RetryConfig,
+    ) -> Self {
+        Self {
+            key_servers: key_servers
+                .into_iter()
+                .map(|ks| (ks.object_id, ks))
+                .collect(),
+            http_client: reqwest::Client::new(),
+            retry_config,
+        }
+    }
+
+    /// Returns the configuration of the key server with the given object id.
+    pub fn key_server(&self, object_id: &ObjectID) -> SealClientResult<&KeyServerConfig> {
+        self.key_servers
+            .get(object_id)
+            .ok_or(SealClientError::UnknownKeyServer(*object_id))
+    }
+
+    /// Fetch the proof-of-possession from all key servers and verify them against the configured
+    /// public keys. This ensures that each key server holds the master key for its public key.
+    pub async fn verify_key_servers(&self) -> SealClientResult<()> {
+        join_all(
+            self.key_servers
+                .values()
+                .map(|key_server| self.verify_key_server(key_server)),
+        )
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    async fn verify_key_server(&self, key_server: &KeyServerConfig) -> SealClientResult<()> {
+        let mut url = format!(
+            "{}/v1/service?service_id={}",
+            key_server.url.trim_end_matches('/'),
+            key_server.object_id
+        );
+        if let Some(key_version) = key_server.key_version {
+            url.push_str(&format!("&key_version={key_version}"));
+        }
+        let response: GetServiceResponse = self
+            .send_with_retries(key_server.object_id, || self.http_client.get(&url))
+            .await?;
+        if response.service_id != key_server.object_id
+            || verify_proof_of_possession(
+                &response.pop,
+                &key_server.public_key,
+                &key_server.object_id.into_bytes(),
+            )
+            .is_err()
+        {
+            return Err(SealClientError::InvalidProofOfPossession(
+                key_server.object_id,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fetch the keys for all ids in the given PTB from the given key servers concurrently.
+    /// The keys are verified against the public keys of the key servers.
+    ///
+    /// @param key_server_ids The key servers to request keys from.
+    /// @param threshold The minimum number of key servers that must return valid keys.
+    /// @param ptb The PTB calling the `seal_approve*` functions for the requested ids.
+    /// @param session_key A session key signed by the user.
+    /// @return The verified keys per key server, indexed by full id.
+    pub async fn fetch_keys(
+        &self,
+        key_server_ids: &[ObjectID],
+        threshold: u8,
+        ptb: &ProgrammableTransaction,
+        session_key: &SessionKey,
+    ) -> SealClientResult<HashMap<ObjectID, HashMap<KeyId, UserSecretKey>>> {
+        if session_key.is_expired() {
+            return Err(SealClientError::ExpiredSessionKey);
+        }
+        let certificate = session_key.certificate()?;
+
+        // A fresh ElGamal key pair is used per request
+        let (enc_secret_key, enc_key, enc_verification_key) =
+            elgamal::genkey::<_, G2Element, _>(&mut thread_rng());
+        let request_signature = session_key.sign_request(ptb, &enc_key, &enc_verification_key);
+        let request = FetchKeyRequest {
+            ptb: Base64::encode(bcs::to_bytes(ptb).expect("should serialize")),
+            enc_key,
+            enc_verification_key,
+            request_signature,
+            certificate,
+            key_version: None,
+        };
+
+        let request = &request;
+        let enc_secret_key = &enc_secret_key;
+        let results = join_all(key_server_ids.iter().map(|object_id| async move {
+            let key_server = self.key_server(object_id)?;
+            let response = self.fetch_keys_from_server(key_server, request).await?;
+            decrypt_and_verify_response(&response, enc_secret_key, &key_server.public_key)
+                .map_err(|_| SealClientError::InvalidKeys(*object_id))
+                .map(|keys| (*object_id, keys))
+        }))
+        .await;
+
+        let (keys, errors): (HashMap<_, _>, Vec<_>) = results.into_iter().partition_result();
+        for error in &errors {
+            warn!("Failed fetching keys: {}", error);
+        }
+        if keys.len() < threshold as usize {
+            return Err(SealClientError::NotEnoughKeys { threshold, errors });
+        }
+        Ok(keys)
+    }
+
+    /// Fetch keys from the key servers of the given encrypted object and decrypt it.
+    /// If the public keys of all key servers of the object are known, the consistency of all
+    /// shares is checked.
+    ///
+    /// @param encrypted_object The object to decrypt.
+    /// @param ptb The PTB calling the `seal_approve*` functions for the id of the object.
+    /// @param session_key A session key signed by the user.
+    /// @return The decrypted plaintext or, if `Plain` mode was used, the derived key.
+    pub async fn decrypt(
+        &self,
+        encrypted_object: &EncryptedObject,
+        ptb: &ProgrammableTransaction,
+        session_key: &SessionKey,
+    ) -> SealClientResult<Vec<u8>> {
+        let key_server_ids = encrypted_object
+            .services
+            .iter()
+            .map(|(object_id, _)| *object_id)
+            .unique()
+            .collect_vec();
+        let keys = self
+            .fetch_keys(
+                &key_server_ids,
+                encrypted_object.threshold,
+                ptb,
+                session_key,
+            )
+            .await?;
+
+        let full_id = create_full_id(&encrypted_object.package_id, &encrypted_object.id);
+        let public_keys = encrypted_object
+            .services
+            .iter()
+            .map(|(object_id, _)| self.key_servers.get(object_id).map(|ks| ks.public_key))
+            .collect::<Option<Vec<_>>>()
+            .map(IBEPublicKeys::BonehFranklinBLS12381);
+        seal_decrypt(
+            encrypted_object,
+            &user_secret_keys_for_id(&keys, &full_id),
+            public_keys.as_ref(),
+        )
+        .map_err(SealClientError::Decryption)
+    }
+
+    async fn fetch_keys_from_server(
+        &self,
+        key_server: &KeyServerConfig,
+        request: &FetchKeyRequest,
+    ) -> SealClientResult<FetchKeyResponse> {
+        let url = format!("{}/v1/fetch_key", key_server.url.trim_end_matches('/'));
+        // The key version is not covered by the request signature and is set per key server
+        let request = FetchKeyRequest {
+            key_version: key_server.key_version,
+            ..request.clone()
+        };
+        self.send_with_retries(key_server.object_id, || {
+            self.http_client.post(&url).json(&request)
+        })
+        .await
+    }
+
+    /// Send the request created by `request` with the SDK headers and parse the response.
+    /// Retriable errors are retried with exponential backoff as configured in [RetryConfig].
+    async fn send_with_retries<T: DeserializeOwned>(
+        &self,
+        object_id: ObjectID,
+        request: impl Fn() -> reqwest::RequestBuilder,
+    ) -> SealClientResult<T> {
+        with_retries(&self.retry_config, || async {
+            let response = request()
+                .header("Client-Sdk-Version", SDK_VERSION)
+                .header("Client-Sdk-Type", SDK_TYPE)
+                .send()
+                .await
+                .map_err(|e| SealClientError::Http {
+                    object_id,
+                    message: e.to_string(),
+                })?;
+            parse_response(object_id, response).await
+        })
+        .await
+    }
+}
+
+/// Parse a response from a key server. Error responses are parsed into [SealClientError::KeyServer].
+async fn parse_response<T: DeserializeOwned>(
+    object_id: ObjectID,
+    response: reqwest::Response,
+) -> SealClientResult<T> {
+    let status = response.status();
+    if status.is_success() {
+        return response
+            .json()
+            .await
+            .map_err(|e| SealClientError::InvalidResponse {
+                object_id,
+                message: e.to_string(),
+            });
+    }
+    let body = response.text().await.unwrap_or_default();
+    let (error, message) = match serde_json::from_str::<ErrorResponse>(&body) {
+        // Errors with a parameter, e.g., InvalidPTB, are serialized as objects.
+        Ok(ErrorResponse { error, message }) => match error {
+            serde_json::Value::String(error) => (error, message),
+            error => (error.to_string(), message),
+        },
+        Err(_) => ("Unknown".to_string(), body),
+    };
+    Err(SealClientError::KeyServer {
+        object_id,
+        status: status.as_u16(),
+        error,
+        message,
+    })
+}
+
+/// Executes an async function with retries for retriable errors and exponential backoff.
+async fn with_retries<T, F, Fut>(retry_config: &RetryConfig, mut func: F) -> SealClientResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = SealClientResult<T>>,
+{
+    let mut attempts_remaining = retry_config.max_retries;
+    let mut current_delay = retry_config.min_delay;
+    loop {
+        match func().await {
+            Err(error) if error.is_retriable() && attempts_remaining > 1 => {
+                debug!(
+                    "Retrying request due to retriable error: {}. Remaining attempts: {}",
+                    error, attempts_remaining
+                );
+                tokio::time::sleep(current_delay).await;
+                current_delay = std::cmp::min(current_delay * 2, retry_config.max_delay);
+                attempts_remaining -= 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers for reading key server objects and building `seal_approve` PTBs using a Sui full node.
+
+use crate::errors::{SealClientError, SealClientResult};
+use crate::KeyServerConfig;
+use crypto::key_server::{parse_key_server_v1, KEY_SERVER_V1};
+use futures::future::join_all;
+use serde_json::json;
+use sui_sdk::rpc_types::{SuiObjectDataOptions, SuiObjectResponse};
+use sui_sdk::SuiClient;
+use sui_types::base_types::ObjectID;
+use sui_types::dynamic_field::DynamicFieldName;
+use sui_types::object::Owner;
+use sui_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+use sui_types::transaction::{ObjectArg, ProgrammableTransaction};
+use sui_types::{Identifier, TypeTag};
+
+/// Look up the URLs and public keys of the given key servers on-chain.
+pub async fn key_server_configs(
+    sui_client: &SuiClient,
+    object_ids: &[ObjectID],
+) -> SealClientResult<Vec<KeyServerConfig>> {
+    join_all(
+        object_ids
+            .iter()
+            .map(|object_id| key_server_config(sui_client, *object_id)),
+    )
+    .await
+    .into_iter()
+    .collect()
+}
+
+async fn key_server_config(
+    sui_client: &SuiClient,
+    object_id: ObjectID,
+) -> SealClientResult<KeyServerConfig> {
+    let field_id = sui_client
+        .read_api()
+        .get_dynamic_field_object(
+            object_id,
+            DynamicFieldName {
+                type_: TypeTag::U64,
+                value: json!(KEY_SERVER_V1.to_string()),
+            },
+        )
+        .await
+        .map_err(|e| SealClientError::Sui(e.to_string()))?
+        .object_id()
+        .map_err(|_| SealClientError::InvalidKeyServerObject(object_id))?;
+    let response = get_object(sui_client, field_id, SuiObjectDataOptions::new().with_bcs()).await?;
+    let bytes = response
+        .move_object_bcs()
+        .ok_or(SealClientError::InvalidKeyServerObject(object_id))?;
+    let key_server = parse_key_server_v1(bytes)
+        .map_err(|_| SealClientError::InvalidKeyServerObject(object_id))?;
+    Ok(KeyServerConfig {
+        object_id,
+        url: key_server.url,
+        public_key: key_server.public_key,
+        key_version: None,
+    })
+}
+
+/// Build a PTB with a single call to a `seal_approve*` function. The first argument of the function
+/// is the id and the remaining arguments are the given objects, which are looked up on-chain.
+///
+/// @param package_id The package defining the `seal_approve*` function.
+/// @param module The module of the function.
+/// @param function The name of the function.
+/// @param id The inner id of the key, e.g., without the package id prefix.
+/// @param objects Objects to pass as the remaining arguments. Shared objects are passed immutably.
+pub async fn seal_approve_ptb(
+    sui_client: &SuiClient,
+    package_id: ObjectID,
+    module: &str,
+    function: &str,
+    id: Vec<u8>,
+    objects: &[ObjectID],
+) -> SealClientResult<ProgrammableTransaction> {
+    let mut builder = ProgrammableTransactionBuilder::new();
+    let mut arguments = vec![builder
+        .pure(id)
+        .map_err(|e| SealClientError::InvalidPtb(e.to_string()))?];
+    for object_id in objects {
+        let object = get_object(
+            sui_client,
+            *object_id,
+            SuiObjectDataOptions::new().with_owner(),
+        )
+        .await?
+        .data
+        .ok_or(SealClientError::Sui(format!(
+            "Object {} not found",
+            object_id
+        )))?;
+        let object_arg = match object.owner {
+            Some(Owner::Shared {
+                initial_shared_version,
+            }) => ObjectArg::SharedObject {
+                id: *object_id,
+                initial_shared_version,
+                mutable: false,
+            },
+            _ => ObjectArg::ImmOrOwnedObject(object.object_ref()),
+        };
+        arguments.push(
+            builder
+                .obj(object_arg)
+                .map_err(|e| SealClientError::InvalidPtb(e.to_string()))?,
+        );
+    }
+    builder.programmable_move_call(
+        package_id,
+        Identifier::new(module).map_err(|e| SealClientError::InvalidPtb(e.to_string()))?,
+        Identifier::new(function).map_err(|e| SealClientError::InvalidPtb(e.to_string()))?,
+        vec![],
+        arguments,
+    );
+    Ok(builder.finish())
+}
+
+async fn get_object(
+    sui_client: &SuiClient,
+    object_id: ObjectID,
+    options: SuiObjectDataOptions,
+) -> SealClientResult<SuiObjectResponse> {
+    sui_client
+        .read_api()
+        .get_object_with_options(object_id, options)
+        .await
+        .map_err(|e| SealClientError::Sui(e.to_string()))
+}
@@ -0,0 +1,410 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::errors::{SealClientError, SealClientResult};
+use crate::signed_message::{signed_message, signed_request};
+use crate::{ElGamalPublicKey, ElgamalVerificationKey};
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Base64, Encoding};
+use fastcrypto::traits::{KeyPair, Signer, ToFromBytes};
+use rand::thread_rng;
+use serde::{Deserialize, Serialize};
+use shared_crypto::intent::{Intent, IntentMessage, PersonalMessage};
+use std::time::Duration;
+use sui_types::base_types::{ObjectID, SuiAddress};
+use sui_types::crypto::Signature;
+use sui_types::signature::GenericSignature;
+use sui_types::transaction::ProgrammableTransaction;
+
+/// The "session" certificate, signed by the user.
+/// Must be aligned with the key server.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Certificate {
+    pub user: SuiAddress,
+    pub session_vk: Ed25519PublicKey,
+    pub creation_time: u64,
+    pub ttl_min: u16,
+    pub signature: GenericSignature,
+    pub mvr_name: Option<String>,
+}
+
+/// The default maximum time-to-live of session keys accepted by key servers.
+pub const DEFAULT_SESSION_KEY_TTL_MAX: Duration = Duration::from_secs(30 * 60);
+
+/// A session key allows a user to fetch keys for a package for a limited time after signing a
+/// single personal message, see [SessionKey::personal_message].
+///
+/// A session key can be exported with [SessionKey::export] and imported in another process with
+/// [SessionKey::import]. Note that the exported session key contains the private session key and
+/// should be handled with the same care as the keys it gives access to, at least until it expires.
+pub struct SessionKey {
+    user: SuiAddress,
+    package_id: ObjectID,
+    mvr_name: Option<String>,
+    creation_time: u64,
+    ttl_min: u16,
+    session_key: Ed25519KeyPair,
+    signature: Option<GenericSignature>,
+}
+
+impl SessionKey {
+    /// Create a new session key for the given user and package.
+    /// Before it can be used, the personal message must be signed by the user, see
+    /// [SessionKey::sign_personal_message] and [SessionKey::set_personal_message_signature].
+    ///
+    /// @param user The address of the user.
+    /// @param package_id The first version of the package whose keys are accessed.
+    /// @param mvr_name Optional MVR name of the package. If set, it is shown to the user instead of the package id.
+    /// @param ttl_min The time-to-live of the session key in minutes.
+    pub fn new(
+        user: SuiAddress,
+        package_id: ObjectID,
+        mvr_name: Option<String>,
+        ttl_min: u16,
+    ) -> Self {
+        Self {
+            user,
+            package_id,
+            mvr_name,
+            creation_time: current_epoch_time(),
+            ttl_min,
+            session_key: Ed25519KeyPair::generate(&mut thread_rng()),
+            signature: None,
+        }
+    }
+
+    /// Export the session key, including the private session key and the user's signature, as a
+    /// JSON string.
+    pub fn export(&self) -> String {
+        serde_json::to_string(&ExportedSessionKey::from(self)).expect("should serialize")
+    }
+
+    /// Import a session key exported with [SessionKey::export].
+    /// Returns an error if the session key cannot be parsed or if it has expired.
+    pub fn import(exported: &str) -> SealClientResult<Self> {
+        let exported: ExportedSessionKey = serde_json::from_str(exported)
+            .map_err(|e| SealClientError::InvalidSessionKey(e.to_string()))?;
+        let session_key = SessionKey::try_from(exported)?;
+        if session_key.is_expired() {
+            return Err(SealClientError::ExpiredSessionKey);
+        }
+        Ok(session_key)
+    }
+
+    pub fn user(&self) -> SuiAddress {
+        self.user
+    }
+
+    pub fn package_id(&self) -> ObjectID {
+        self.package_id
+    }
+
+    pub fn creation_time(&self) -> u64 {
+        self.creation_time
+    }
+
+    pub fn ttl_min(&self) -> u16 {
+        self.ttl_min
+    }
+
+    /// The time in milliseconds since the UNIX epoch after which key servers reject this session key.
+    pub fn expiration_time(&self) -> u64 {
+        self.creation_time + ttl_millis(self.ttl_min)
+    }
+
+    /// Returns true if key servers will reject this session key because it has expired or because
+    /// its creation time is in the future.
+    pub fn is_expired(&self) -> bool {
+        is_expired(self.creation_time, self.ttl_min, current_epoch_time())
+    }
+
+    /// Check that the session key will be accepted by a key server with the given maximum
+    /// time-to-live, following the same rules as the key server:
+    /// - The time-to-live must not exceed `ttl_max`.
+    /// - The creation time must not be in the future.
+    /// - The time since creation must not exceed the time-to-live.
+    pub fn check_validity(&self, ttl_max: Duration) -> SealClientResult<()> {
+        if Duration::from_millis(ttl_millis(self.ttl_min)) > ttl_max {
+            return Err(SealClientError::InvalidSessionKey(format!(
+                "Time-to-live of {} mins exceeds the maximum",
+                self.ttl_min
+            )));
+        }
+        if self.is_expired() {
+            return Err(SealClientError::ExpiredSessionKey);
+        }
+        Ok(())
+    }
+
+    /// The personal message that should be signed by the user.
+    pub fn personal_message(&self) -> String {
+        signed_message(
+            self.package_name(),
+            self.session_key.public(),
+            self.creation_time,
+            self.ttl_min,
+        )
+    }
+
+    /// Set a signature on the personal message created by the user, e.g., using a wallet.
+    pub fn set_personal_message_signature(&mut self, signature: GenericSignature) {
+        self.signature = Some(signature);
+    }
+
+    /// Sign the personal message with the given key of the user.
+    pub fn sign_personal_message(&mut self, signer: &dyn Signer<Signature>) {
+        let personal_msg = PersonalMessage {
+            message: self.personal_message().into_bytes(),
+        };
+        let msg_with_intent = IntentMessage::new(Intent::personal_message(), personal_msg);
+        self.set_personal_message_signature(GenericSignature::Signature(Signature::new_secure(
+            &msg_with_intent,
+            signer,
+        )));
+    }
+
+    /// Returns the certificate for this session key or an error if the personal message has not
+    /// been signed yet.
+    pub fn certificate(&self) -> SealClientResult<Certificate> {
+        let signature = self
+            .signature
+            .clone()
+            .ok_or(SealClientError::UnsignedSessionKey)?;
+        Ok(Certificate {
+            user: self.user,
+            session_vk: self.session_key.public().clone(),
+            creation_time: self.creation_time,
+            ttl_min: self.ttl_min,
+            signature,
+            mvr_name: self.mvr_name.clone(),
+        })
+    }
+
+    /// Sign a fetch key request using the session key.
+    pub(crate) fn sign_request(
+        &self,
+        ptb: &ProgrammableTransaction,
+        enc_key: &ElGamalPublicKey,
+        enc_verification_key: &ElgamalVerificationKey,
+    ) -> Ed25519Signature {
+        self.session_key
+            .sign(&signed_request(ptb, enc_key, enc_verification_key))
+    }
+
+    /// The key server expects the MVR name, if given, and otherwise the package id.
+    fn package_name(&self) -> String {
+        self.mvr_name
+            .clone()
+            .unwrap_or(self.package_id.to_hex_uncompressed())
+    }
+}
+
+/// The serializable representation of a [SessionKey].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportedSessionKey {
+    pub user: SuiAddress,
+    pub package_id: ObjectID,
+    pub mvr_name: Option<String>,
+    pub creation_time: u64,
+    pub ttl_min: u16,
+    /// The Base64 encoded Ed25519 private session key.
+    pub session_key: String,
+    pub signature: Option<GenericSignature>,
+}
+
+impl From<&SessionKey> for ExportedSessionKey {
+    fn from(session_key: &SessionKey) -> Self {
+        Self {
+            user: session_key.user,
+            package_id: session_key.package_id,
+            mvr_name: session_key.mvr_name.clone(),
+            creation_time: session_key.creation_time,
+            ttl_min: session_key.ttl_min,
+            session_key: Base64::encode(session_key.session_key.copy().private().as_bytes()),
+            signature: session_key.signature.clone(),
+        }
+    }
+}
+
+impl TryFrom<ExportedSessionKey> for SessionKey {
+    type Error = SealClientError;
+
+    fn try_from(exported: ExportedSessionKey) -> Result<Self, Self::Error> {
+        let private_key = Base64::decode(&exported.session_key)
+            .ok()
+            .and_then(|bytes| Ed25519PrivateKey::from_bytes(&bytes).ok())
+            .ok_or(SealClientError::InvalidSessionKey(
+                "Invalid private session key".to_string(),
+            ))?;
+        Ok(Self {
+            user: exported.user,
+            package_id: exported.package_id,
+            mvr_name: exported.mvr_name,
+            creation_time: exported.creation_time,
+            ttl_min: exported.ttl_min,
+            session_key: Ed25519KeyPair::from(private_key),
+            signature: exported.signature,
+        })
+    }
+}
+
+fn ttl_millis(ttl_min: u16) -> u64 {
+    ttl_min as u64 * 60 * 1000
+}
+
+/// A session key is expired if it was created in the future or more than `ttl_min` minutes ago.
+fn is_expired(creation_time: u64, ttl_min: u16, now: u64) -> bool {
+    match now.checked_sub(creation_time) {
+        None => true,
+        Some(elapsed) => elapsed > ttl_millis(ttl_min),
+    }
+}
+
+/// Returns the current epoch time in milliseconds since the UNIX epoch.
+pub(crate) fn current_epoch_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("fixed start time")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sui_types::crypto::{get_key_pair, SignatureScheme, SuiSignature};
+
+    #[test]
+    fn test_session_key_certificate() {
+        let (user, keypair): (_, Ed25519KeyPair) = get_key_pair();
+        let package_id = ObjectID::random();
+        let mut session_key = SessionKey::new(user, package_id, None, 10);
+        assert!(session_key.personal_message().starts_with(&format!(
+            "Accessing keys of package {} for 10 mins from",
+            package_id.to_hex_uncompressed()
+        )));
+
+        // Cannot create a certificate before signing
+        assert_eq!(
+            session_key.certificate().err(),
+            Some(SealClientError::UnsignedSessionKey)
+        );
+
+        session_key.sign_personal_message(&keypair);
+        let certificate = session_key.certificate().unwrap();
+        assert_eq!(certificate.user, user);
+        assert_eq!(certificate.ttl_min, 10);
+
+        let GenericSignature::Signature(signature) = certificate.signature else {
+            panic!("Expected a simple signature");
+        };
+        let personal_msg = PersonalMessage {
+            message: session_key.personal_message().into_bytes(),
+        };
+        assert!(signature
+            .verify_secure(
+                &IntentMessage::new(Intent::personal_message(), personal_msg),
+                user,
+                SignatureScheme::ED25519,
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_export_import() {
+        let (user, keypair): (_, Ed25519KeyPair) = get_key_pair();
+        let mut session_key = SessionKey::new(
+            user,
+            ObjectID::random(),
+            Some("@my/package".to_string()),
+            10,
+        );
+        session_key.sign_personal_message(&keypair);
+
+        let imported = SessionKey::import(&session_key.export()).unwrap();
+        assert_eq!(
+            ExportedSessionKey::from(&imported),
+            ExportedSessionKey::from(&session_key)
+        );
+        assert_eq!(imported.personal_message(), session_key.personal_message());
+        assert_eq!(
+            bcs::to_bytes(&imported.certificate().unwrap()).unwrap(),
+            bcs::to_bytes(&session_key.certificate().unwrap()).unwrap()
+        );
+
+        // Unsigned session keys can also be exported
+        let unsigned = SessionKey::new(user, ObjectID::random(), None, 10);
+        assert_eq!(
+            SessionKey::import(&unsigned.export())
+                .unwrap()
+                .certificate()
+                .err(),
+            Some(SealClientError::UnsignedSessionKey)
+        );
+
+        // Invalid input
+        assert!(matches!(
+            SessionKey::import("invalid"),
+            Err(SealClientError::InvalidSessionKey(_))
+        ));
+        let mut exported = ExportedSessionKey::from(&session_key);
+        exported.session_key = Base64::encode([1, 2, 3]);
+        assert!(matches!(
+            SessionKey::import(&serde_json::to_string(&exported).unwrap()),
+            Err(SealClientError::InvalidSessionKey(_))
+        ));
+
+        // Expired session keys cannot be imported
+        let mut exported = ExportedSessionKey::from(&session_key);
+        exported.creation_time -= 11 * 60 * 1000;
+        assert_eq!(
+            SessionKey::import(&serde_json::to_string(&exported).unwrap()).err(),
+            Some(SealClientError::ExpiredSessionKey)
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        let now = current_epoch_time();
+        assert!(!is_expired(now, 1, now));
+        assert!(!is_expired(now - 60_000, 1, now));
+        assert!(is_expired(now - 60_001, 1, now));
+        assert!(is_expired(now + 1, 1, now));
+
+        let (user, _): (_, Ed25519KeyPair) = get_key_pair();
+        let session_key = SessionKey::new(user, ObjectID::random(), None, 10);
+        assert_eq!(
+            session_key.expiration_time(),
+            session_key.creation_time() + 10 * 60 * 1000
+        );
+        assert!(session_key
+            .check_validity(DEFAULT_SESSION_KEY_TTL_MAX)
+            .is_ok());
+        assert!(matches!(
+            session_key.check_validity(Duration::from_secs(5 * 60)),
+            Err(SealClientError::InvalidSessionKey(_))
+        ));
+
+        let mut exported = ExportedSessionKey::from(&session_key);
+        exported.creation_time = current_epoch_time() + 60_000;
+        assert_eq!(
+            SessionKey::try_from(exported)
+                .unwrap()
+                .check_validity(DEFAULT_SESSION_KEY_TTL_MAX),
+            Err(SealClientError::ExpiredSessionKey)
+        );
+    }
+
+    #[test]
+    fn test_session_key_mvr_name() {
+        let (user, _): (_, Ed25519KeyPair) = get_key_pair();
+        let session_key = SessionKey::new(
+            user,
+            ObjectID::random(),
+            Some("@my/package".to_string()),
+            10,
+        );
+        assert!(session_key
+            .personal_message()
+            .starts_with("Accessing keys of package @my/package for 10 mins from"));
+    }
+}
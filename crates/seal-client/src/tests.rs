@@ -0,0 +1,420 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tests against locally spawned mock key servers. The mock key servers implement the same API as
+//! the key server and check the signatures of requests, but use a given closure instead of a dry
+//! run to evaluate the policy.
+
+use crate::errors::SealClientError;
+use crate::signed_message::{signed_message, signed_request};
+use crate::{
+    FetchKeyRequest, GetServiceResponse, KeyServerConfig, RetryConfig, SealClient, SessionKey,
+};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use crypto::client::{DecryptionKey, FetchKeyResponse};
+use crypto::ibe::{create_proof_of_possession, generate_key_pair, MasterKey};
+use crypto::{
+    create_full_id, elgamal, ibe, seal_encrypt, EncryptionInput, IBEPublicKeys, ObjectID,
+};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Base64, Encoding};
+use fastcrypto::traits::VerifyingKey;
+use itertools::Itertools;
+use rand::thread_rng;
+use serde_json::{json, Value};
+use shared_crypto::intent::{Intent, IntentMessage, PersonalMessage};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use sui_types::base_types::SuiAddress;
+use sui_types::crypto::{get_key_pair, SignatureScheme, SuiSignature};
+use sui_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+use sui_types::signature::GenericSignature;
+use sui_types::transaction::{Argument, CallArg, Command, ProgrammableTransaction};
+use sui_types::Identifier;
+use tokio::net::TcpListener;
+
+type Policy = Arc<dyn Fn(SuiAddress, &ProgrammableTransaction) -> bool + Send + Sync>;
+
+struct MockKeyServer {
+    object_id: ObjectID,
+    master_key: MasterKey,
+    policy: Policy,
+    /// The number of requests that should fail with a retriable error before succeeding.
+    failures_remaining: AtomicU32,
+}
+
+type MockError = (StatusCode, Json<Value>);
+
+fn mock_error(status: StatusCode, error: &str) -> MockError {
+    (
+        status,
+        Json(json!({ "error": error, "message": "mock error" })),
+    )
+}
+
+async fn handle_get_service(
+    State(server): State<Arc<MockKeyServer>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<GetServiceResponse>, MockError> {
+    let service_id = params
+        .get("service_id")
+        .and_then(|id| ObjectID::from_hex_literal(id).ok())
+        .filter(|id| *id == server.object_id)
+        .ok_or(mock_error(StatusCode::BAD_REQUEST, "InvalidServiceId"))?;
+    Ok(Json(GetServiceResponse {
+        service_id,
+        pop: create_proof_of_possession(&server.master_key, &service_id.into_bytes()),
+    }))
+}
+
+async fn handle_fetch_key(
+    State(server): State<Arc<MockKeyServer>>,
+    Json(request): Json<FetchKeyRequest>,
+) -> Result<Json<FetchKeyResponse>, MockError> {
+    if server
+        .failures_remaining
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |f| f.checked_sub(1))
+        .is_ok()
+    {
+        return Err(mock_error(StatusCode::SERVICE_UNAVAILABLE, "Failure"));
+    }
+
+    let ptb: ProgrammableTransaction = Base64::decode(&request.ptb)
+        .ok()
+        .and_then(|bytes| bcs::from_bytes(&bytes).ok())
+        .ok_or(mock_error(StatusCode::FORBIDDEN, "InvalidPTB"))?;
+    let ids = full_ids(&ptb);
+
+    // Check the user's signature on the certificate
+    let certificate = &request.certificate;
+    let Some(Command::MoveCall(first_cmd)) = ptb.commands.first() else {
+        return Err(mock_error(StatusCode::FORBIDDEN, "InvalidPTB"));
+    };
+    let message = signed_message(
+        first_cmd.package.to_hex_uncompressed(),
+        &certificate.session_vk,
+        certificate.creation_time,
+        certificate.ttl_min,
+    );
+    let GenericSignature::Signature(signature) = &certificate.signature else {
+        return Err(mock_error(StatusCode::FORBIDDEN, "InvalidSignature"));
+    };
+    signature
+        .verify_secure(
+            &IntentMessage::new(
+                Intent::personal_message(),
+                PersonalMessage {
+                    message: message.into_bytes(),
+                },
+            ),
+            certificate.user,
+            SignatureScheme::ED25519,
+        )
+        .map_err(|_| mock_error(StatusCode::FORBIDDEN, "InvalidSignature"))?;
+
+    // Check the session signature on the request
+    certificate
+        .session_vk
+        .verify(
+            &signed_request(&ptb, &request.enc_key, &request.enc_verification_key),
+            &request.request_signature,
+        )
+        .map_err(|_| mock_error(StatusCode::FORBIDDEN, "InvalidSessionSignature"))?;
+
+    if !(server.policy)(certificate.user, &ptb) {
+        return Err(mock_error(StatusCode::FORBIDDEN, "NoAccess"));
+    }
+
+    Ok(Json(FetchKeyResponse {
+        decryption_keys: ids
+            .into_iter()
+            .map(|id| DecryptionKey {
+                encrypted_key: elgamal::encrypt(
+                    &mut thread_rng(),
+                    &ibe::extract(&server.master_key, &id),
+                    &request.enc_key,
+                ),
+                id,
+            })
+            .collect(),
+    }))
+}
+
+/// The full ids of all seal_approve calls in the PTB. The mock assumes that all packages are first versions.
+fn full_ids(ptb: &ProgrammableTransaction) -> Vec<Vec<u8>> {
+    ptb.commands
+        .iter()
+        .filter_map(|cmd| match cmd {
+            Command::MoveCall(cmd) => match (cmd.arguments.first(), &ptb.inputs) {
+                (Some(Argument::Input(i)), inputs) => match &inputs[*i as usize] {
+                    CallArg::Pure(bytes) => bcs::from_bytes::<Vec<u8>>(bytes)
+                        .ok()
+                        .map(|id| create_full_id(&cmd.package, &id)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Spawn a mock key server with the given master key and policy. Returns its URL.
+async fn spawn_mock_key_server(
+    object_id: ObjectID,
+    master_key: MasterKey,
+    policy: Policy,
+    failures: u32,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let state = Arc::new(MockKeyServer {
+        object_id,
+        master_key,
+        policy,
+        failures_remaining: AtomicU32::new(failures),
+    });
+    let app = Router::new()
+        .route("/v1/fetch_key", post(handle_fetch_key))
+        .route("/v1/service", get(handle_get_service))
+        .with_state(state);
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{addr}")
+}
+
+fn allow_all() -> Policy {
+    Arc::new(|_, _| true)
+}
+
+fn deny_all() -> Policy {
+    Arc::new(|_, _| false)
+}
+
+fn create_ptb(package_id: ObjectID, ids: &[Vec<u8>]) -> ProgrammableTransaction {
+    let mut builder = ProgrammableTransactionBuilder::new();
+    for id in ids {
+        let id = builder.pure(id.clone()).unwrap();
+        builder.programmable_move_call(
+            package_id,
+            Identifier::new("mock").unwrap(),
+            Identifier::new("seal_approve").unwrap(),
+            vec![],
+            vec![id],
+        );
+    }
+    builder.finish()
+}
+
+fn fast_retry_config() -> RetryConfig {
+    RetryConfig {
+        max_retries: 3,
+        min_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(100),
+    }
+}
+
+/// Spawn mock key servers with the given policies and return their configurations.
+async fn setup_key_servers(policies: Vec<(Policy, u32)>) -> Vec<KeyServerConfig> {
+    let mut key_servers = vec![];
+    for (policy, failures) in policies {
+        let (master_key, public_key) = generate_key_pair(&mut thread_rng());
+        let object_id = ObjectID::random();
+        let url = spawn_mock_key_server(object_id, master_key, policy, failures).await;
+        key_servers.push(KeyServerConfig {
+            object_id,
+            url,
+            public_key,
+            key_version: None,
+        });
+    }
+    key_servers
+}
+
+fn encrypt(
+    key_servers: &[KeyServerConfig],
+    package_id: ObjectID,
+    id: &[u8],
+    threshold: u8,
+    message: &[u8],
+) -> crypto::EncryptedObject {
+    seal_encrypt(
+        package_id,
+        id.to_vec(),
+        key_servers.iter().map(|ks| ks.object_id).collect(),
+        &IBEPublicKeys::BonehFranklinBLS12381(key_servers.iter().map(|ks| ks.public_key).collect()),
+        threshold,
+        EncryptionInput::Aes256Gcm {
+            data: message.to_vec(),
+            aad: None,
+        },
+    )
+    .unwrap()
+    .0
+}
+
+fn signed_session_key(package_id: ObjectID) -> SessionKey {
+    let (user, keypair): (_, Ed25519KeyPair) = get_key_pair();
+    let mut session_key = SessionKey::new(user, package_id, None, 10);
+    session_key.sign_personal_message(&keypair);
+    session_key
+}
+
+#[tokio::test]
+async fn test_decrypt() {
+    let key_servers =
+        setup_key_servers(vec![(allow_all(), 0), (allow_all(), 0), (deny_all(), 0)]).await;
+    let client = SealClient::new_with_retry_config(key_servers.clone(), fast_retry_config());
+    client.verify_key_servers().await.unwrap();
+
+    let package_id = ObjectID::random();
+    let id = vec![1, 2, 3, 4];
+    let message = b"Hello, World!";
+    let encrypted_object = encrypt(&key_servers, package_id, &id, 2, message);
+
+    let session_key = signed_session_key(package_id);
+    let ptb = create_ptb(package_id, &[id]);
+
+    // Two out of three key servers approve
+    assert_eq!(
+        client
+            .decrypt(&encrypted_object, &ptb, &session_key)
+            .await
+            .unwrap(),
+        message
+    );
+
+    // Fails if all key servers are needed
+    let encrypted_object = encrypt(&key_servers, package_id, &[1, 2, 3, 4], 3, message);
+    let Err(SealClientError::NotEnoughKeys { threshold, errors }) =
+        client.decrypt(&encrypted_object, &ptb, &session_key).await
+    else {
+        panic!("Expected NotEnoughKeys error");
+    };
+    assert_eq!(threshold, 3);
+    assert_eq!(
+        errors,
+        vec![SealClientError::KeyServer {
+            object_id: key_servers[2].object_id,
+            status: 403,
+            error: "NoAccess".to_string(),
+            message: "mock error".to_string(),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_fetch_keys_multiple_ids() {
+    let key_servers = setup_key_servers(vec![(allow_all(), 0), (allow_all(), 0)]).await;
+    let client = SealClient::new(key_servers.clone());
+
+    let package_id = ObjectID::random();
+    let ids = vec![vec![1], vec![2], vec![3]];
+    let session_key = signed_session_key(package_id);
+    let keys = client
+        .fetch_keys(
+            &key_servers.iter().map(|ks| ks.object_id).collect_vec(),
+            2,
+            &create_ptb(package_id, &ids),
+            &session_key,
+        )
+        .await
+        .unwrap();
+    assert_eq!(keys.len(), 2);
+    for keys in keys.values() {
+        assert_eq!(keys.len(), 3);
+        for id in &ids {
+            assert!(keys.contains_key(&create_full_id(&package_id, id)));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_retries() {
+    let package_id = ObjectID::random();
+    let id = vec![1, 2, 3, 4];
+    let ptb = create_ptb(package_id, &[id.clone()]);
+    let session_key = signed_session_key(package_id);
+
+    // The first two attempts fail, but the third succeeds
+    let key_servers = setup_key_servers(vec![(allow_all(), 2)]).await;
+    let client = SealClient::new_with_retry_config(key_servers.clone(), fast_retry_config());
+    let encrypted_object = encrypt(&key_servers, package_id, &id, 1, b"Hello");
+    assert_eq!(
+        client
+            .decrypt(&encrypted_object, &ptb, &session_key)
+            .await
+            .unwrap(),
+        b"Hello"
+    );
+
+    // More failures than retries
+    let key_servers = setup_key_servers(vec![(allow_all(), 3)]).await;
+    let client = SealClient::new_with_retry_config(key_servers.clone(), fast_retry_config());
+    let encrypted_object = encrypt(&key_servers, package_id, &id, 1, b"Hello");
+    assert!(client
+        .decrypt(&encrypted_object, &ptb, &session_key)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_invalid_key_server() {
+    let mut key_servers = setup_key_servers(vec![(allow_all(), 0), (allow_all(), 0)]).await;
+
+    // Configure the second key server with a wrong public key
+    key_servers[1].public_key = generate_key_pair(&mut thread_rng()).1;
+    let client = SealClient::new(key_servers.clone());
+    assert_eq!(
+        client.verify_key_servers().await,
+        Err(SealClientError::InvalidProofOfPossession(
+            key_servers[1].object_id
+        ))
+    );
+
+    // Keys from the second key server are rejected
+    let package_id = ObjectID::random();
+    let session_key = signed_session_key(package_id);
+    let result = client
+        .fetch_keys(
+            &key_servers.iter().map(|ks| ks.object_id).collect_vec(),
+            2,
+            &create_ptb(package_id, &[vec![1]]),
+            &session_key,
+        )
+        .await;
+    assert_eq!(
+        result.err(),
+        Some(SealClientError::NotEnoughKeys {
+            threshold: 2,
+            errors: vec![SealClientError::InvalidKeys(key_servers[1].object_id)],
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_unsigned_session_key() {
+    let key_servers = setup_key_servers(vec![(allow_all(), 0)]).await;
+    let client = SealClient::new(key_servers.clone());
+    let package_id = ObjectID::random();
+    let (user, _): (_, Ed25519KeyPair) = get_key_pair();
+    let session_key = SessionKey::new(user, package_id, None, 10);
+    assert_eq!(
+        client
+            .fetch_keys(
+                &[key_servers[0].object_id],
+                1,
+                &create_ptb(package_id, &[vec![1]]),
+                &session_key,
+            )
+            .await
+            .err(),
+        Some(SealClientError::UnsignedSessionKey)
+    );
+}
@@ -108,6 +108,16 @@ public fun update(s: &mut KeyServer, url: String) {
     v1.url = url;
 }
 
+// Update the public key when the master key is rotated. Key servers serve the key matching the
+// public key registered here by default, so this switches clients to the new key.
+public fun update_pk(s: &mut KeyServer, pk: vector<u8>) {
+    assert!(df::exists_(&s.id, 1), EInvalidVersion);
+    let v1: &mut KeyServerV1 = df::borrow_mut(&mut s.id, 1);
+    assert!(v1.key_type == KeyTypeBonehFranklinBLS12381, EInvalidKeyType);
+    let _ = g2_from_bytes(&pk);
+    v1.pk = pk;
+}
+
 #[test_only]
 public fun destroy_for_testing(v: KeyServer) {
     let KeyServer { id, .. } = v;
@@ -117,7 +127,7 @@ public fun destroy_for_testing(v: KeyServer) {
 #[test]
 fun test_flow() {
     use sui::test_scenario::{Self, next_tx, ctx};
-    use sui::bls12381::{g2_generator};
+    use sui::bls12381::{g2_generator, scalar_from_u64, g2_mul};
 
     let addr1 = @0xA;
     let mut scenario = test_scenario::begin(addr1);
@@ -139,6 +149,9 @@ fun test_flow() {
     assert!(pk(&s) == pk.bytes(), 0);
     s.update(b"https::/mysten-labs2.com".to_string());
     assert!(url(&s) == b"https::/mysten-labs2.com".to_string(), 0);
+    let new_pk = g2_mul(&scalar_from_u64(2), &pk);
+    s.update_pk(*new_pk.bytes());
+    assert!(pk(&s) == new_pk.bytes(), 0);
 
     destroy_for_testing(s);
     test_scenario::end(scenario);